@@ -74,6 +74,7 @@ struct Light {
 layout(location = 0) in vec3 v_WorldPosition;
 layout(location = 1) in vec3 v_WorldNormal;
 layout(location = 2) in vec3 v_Uv;
+layout(location = 4) in float v_AO;
 
 #ifdef STANDARDMATERIAL_NORMAL_MAP
 layout(location = 3) in vec4 v_WorldTangent;
@@ -157,6 +158,10 @@ struct FogConfig_t {
     vec4 color;
     float near;
     float far;
+    float density;
+    uint mode;
+    float height_start;
+    float height_falloff;
 };
 
 layout(set = 2, binding = 3) uniform FogConfig {
@@ -324,35 +329,42 @@ vec3 reinhard_extended_luminance(vec3 color, float max_white_l) {
 
 #endif
 
-highp float rand(vec2 co)
-{
-    highp float a = 12.9898;
-    highp float b = 78.233;
-    highp float c = 43758.5453;
-    highp float dt= dot(co.xy ,vec2(a,b));
-    highp float sn= mod(dt,3.14);
-    return fract(sin(sn) * c);
-}
+const uint FOG_MODE_LINEAR = 0;
+const uint FOG_MODE_EXP = 1;
+const uint FOG_MODE_EXP2 = 2;
 
 float get_fog_factor(float d) {
+    if (fog.mode == FOG_MODE_EXP) {
+        return 1.0 - exp(-fog.density * d);
+    } else if (fog.mode == FOG_MODE_EXP2) {
+        float x = fog.density * d;
+        return 1.0 - exp(-x * x);
+    }
     return smoothstep(fog.near, fog.far, d);
 }
 
+float get_height_fog_factor(float d, float y) {
+    if (fog.height_falloff <= 0.0) {
+        return 0.0;
+    }
+    float thickness = exp(-max(y - fog.height_start, 0.0) * fog.height_falloff);
+    return thickness * (1.0 - exp(-fog.density * d));
+}
+
 void main() {
     vec4 output_color = base_color;
+    float fade_progress = (fade_duration - fade_remaining) / max(fade_duration, 1e-6);
 #ifdef FADEUNIFORM_FADE_IN
     // alpha is 0 at fade_remaining == fade_duration and 1 at fade_remaining == 0
-    if (rand(gl_FragCoord.xy) > (fade_duration - fade_remaining) / fade_duration) {
-        discard;
-        return;
-    }
+    float fade_alpha = fade_progress;
 #else
     // alpha is 1 at fade_remaining == fade_duration and 0 at fade_remaining == 0
-    if (rand(gl_FragCoord.xy) < (fade_duration - fade_remaining) / fade_duration) {
+    float fade_alpha = 1.0 - fade_progress;
+#endif
+    if (fade_alpha <= 0.0) {
         discard;
         return;
     }
-#endif
 #ifdef STANDARDMATERIAL_BASE_COLOR_TEXTURE
     output_color *= texture(sampler2DArray(StandardMaterial_base_color_texture,
                                            StandardMaterial_base_color_texture_sampler),
@@ -461,9 +473,17 @@ void main() {
     // output_color.rgb = pow(output_color.rgb, vec3(1.0 / 2.2));
 #endif
 
+    // Voxel ambient occlusion, 1.0 when unoccluded
+    output_color.rgb *= v_AO;
+
     // FIXME - use the depth texture?
     float depth = length(v_WorldPosition.xyz - CameraPos.xyz);
-    output_color = mix(output_color, fog.color, get_fog_factor(depth));
+    // Combine the light transmitted through the distance fog and the height fog
+    float transmittance = (1.0 - get_fog_factor(depth))
+        * (1.0 - get_height_fog_factor(depth, v_WorldPosition.y));
+    output_color = mix(output_color, fog.color, 1.0 - transmittance);
+    // Fading meshes are drawn in the transparent pass, see mesh_fade_transparency_system
+    output_color.a *= fade_alpha;
 
     o_Target = output_color;
 }
@@ -35,10 +35,12 @@ layout(location = 3) in vec4 Vertex_Tangent;
 #endif
 
 layout(location = 4) in uint Vertex_Layer; // New thing
+layout(location = 5) in float Vertex_AO;
 
 layout(location = 0) out vec3 v_WorldPosition;
 layout(location = 1) out vec3 v_WorldNormal;
 layout(location = 2) out vec3 v_Uv;
+layout(location = 4) out float v_AO;
 
 layout(set = 0, binding = 0) uniform CameraViewProj {
     mat4 ViewProj;
@@ -58,6 +60,7 @@ void main() {
     v_WorldNormal = mat3(Model) * Vertex_Normal;
     // Gets used here and passed to the fragment shader.
     v_Uv = vec3(Vertex_Uv, Vertex_Layer);
+    v_AO = Vertex_AO;
 #ifdef STANDARDMATERIAL_NORMAL_MAP
     v_WorldTangent = vec4(mat3(Model) * Vertex_Tangent.xyz, Vertex_Tangent.w);
 #endif
@@ -20,6 +20,8 @@ struct PhysicalSkyMaterialType {
     vec4 mieKCoefficient;
     vec4 primaries;
     vec4 sunPosition;
+    vec4 moonPosition;
+    mat4 starRotation;
     float depolarizationFactor;
     float luminance;
     float mieCoefficient;
@@ -35,6 +37,10 @@ struct PhysicalSkyMaterialType {
     float sunIntensityFalloffSteepness;
     float tonemapWeighting;
     float turbidity;
+    float moonIntensity;
+    float cloudCoverage;
+    float cloudSpeed;
+    float cloudTime;
 };
 
 layout(set = 2, binding = 0) uniform PhysicalSkyMaterial {
@@ -71,6 +77,67 @@ float sunIntensity(float zenithAngleCos)
     return ps.sunIntensityFactor * max(0.0, 1.0 - exp(-((cutoffAngle - acos(zenithAngleCos)) / ps.sunIntensityFalloffSteepness)));
 }
 
+float hash(vec3 p)
+{
+    p = fract(p * 0.3183099 + 0.1);
+    p *= 17.0;
+    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// Sparse points of light fixed to the sky, where direction is in the frame of the stars
+vec3 stars(vec3 direction)
+{
+    vec3 cell = floor(direction * 300.0);
+    float h = hash(cell);
+    float brightness = smoothstep(0.997, 1.0, h);
+    return vec3(brightness) * mix(vec3(0.8, 0.85, 1.0), vec3(1.0, 0.9, 0.8), hash(cell + 1.0));
+}
+
+#ifdef PHYSICALSKYMATERIAL_CLOUDS
+float valueNoise(vec2 p)
+{
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3.0 - 2.0 * f);
+    float a = hash(vec3(i, 0.0));
+    float b = hash(vec3(i + vec2(1.0, 0.0), 0.0));
+    float c = hash(vec3(i + vec2(0.0, 1.0), 0.0));
+    float d = hash(vec3(i + vec2(1.0, 1.0), 0.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+float cloudNoise(vec2 p)
+{
+    float value = 0.0;
+    float amplitude = 0.5;
+    for (int i = 0; i < 5; i++) {
+        value += amplitude * valueNoise(p);
+        p = p * 2.03 + vec2(17.0, 31.0);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// The colour and opacity of a flat layer of clouds high above the camera, lit by the sun
+vec4 clouds(vec3 direction, vec3 sunDirection, float night)
+{
+    if (direction.y <= 0.0) {
+        return vec4(0.0);
+    }
+    vec2 uv = direction.xz / (direction.y + 0.1) * 2.0 + vec2(1.0, 0.3) * ps.cloudSpeed * ps.cloudTime;
+    float threshold = 1.0 - ps.cloudCoverage;
+    float density = smoothstep(threshold, threshold + 0.3, cloudNoise(uv));
+    // Thin out towards the horizon, where the layer is seen edge-on
+    density *= smoothstep(0.0, 0.15, direction.y);
+    // Warm when the sun is low and brightest around the sun
+    vec3 sunlight = mix(vec3(1.0, 0.6, 0.4), vec3(1.0), clamp(sunDirection.y * 3.0, 0.0, 1.0));
+    float silverLining = pow(max(dot(direction, sunDirection), 0.0), 8.0) * 0.5;
+    vec3 dayColor = vec3(0.35, 0.38, 0.45) + sunlight * (0.55 + silverLining);
+    vec3 color = mix(dayColor, vec3(0.02, 0.025, 0.04), night);
+    return vec4(color, density);
+}
+#endif
+
 // Whitescale tonemapping calculation, see http://filmicgames.com/archives/75
 // Also see http://blenderartists.org/forum/showthread.php?321110-Shaders-and-Skybox-madness
 const float A = 0.15; // Shoulder strength
@@ -127,5 +194,23 @@ void main()
     vec3 color = curr * whiteScale;
     vec3 retColor = pow(color, vec3(1.0 / (1.2 + (1.2 * sunfade))));
 
+    // Night sky, fading in as the sun sets
+    vec3 viewDirection = normalize(v_WorldPosition - CameraPos.xyz);
+    float night = clamp(-sunDirection.y * 10.0, 0.0, 1.0);
+    float aboveHorizon = smoothstep(-0.02, 0.02, viewDirection.y);
+    vec3 moonDirection = normalize(ps.moonPosition.xyz);
+    float moonCosTheta = dot(viewDirection, moonDirection);
+    float moonDisk = smoothstep(0.9995, 0.9997, moonCosTheta);
+    float moonGlow = pow(max(moonCosTheta, 0.0), 200.0) * 0.15;
+    vec3 moonColor = vec3(0.9, 0.92, 1.0) * (moonDisk + moonGlow) * ps.moonIntensity;
+    vec3 starDirection = (ps.starRotation * vec4(viewDirection, 0.0)).xyz;
+    vec3 nightColor = moonColor + stars(starDirection) * (1.0 - moonDisk);
+    retColor += nightColor * night * aboveHorizon;
+
+#ifdef PHYSICALSKYMATERIAL_CLOUDS
+    vec4 cloud = clouds(viewDirection, sunDirection, night);
+    retColor = mix(retColor, cloud.rgb, cloud.a);
+#endif
+
     o_Target = vec4(retColor, 1.0);
 }
\ No newline at end of file
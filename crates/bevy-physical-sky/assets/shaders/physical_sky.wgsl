@@ -0,0 +1,220 @@
+// A WGSL port of physical_sky.vert and physical_sky.frag, see physical_sky.frag for the origins of
+// the model. The bindings and the uniform layout are the same as for the GLSL shaders. WGSL has no
+// preprocessor, so the clouds are skipped at runtime while cloud_coverage is zero rather than
+// compiled out by PHYSICALSKYMATERIAL_CLOUDS.
+
+struct CameraViewProj {
+    view_proj: mat4x4<f32>,
+};
+
+struct CameraPosition {
+    position: vec4<f32>,
+};
+
+struct Transform {
+    model: mat4x4<f32>,
+};
+
+struct PhysicalSkyMaterial {
+    mie_k_coefficient: vec4<f32>,
+    primaries: vec4<f32>,
+    sun_position: vec4<f32>,
+    moon_position: vec4<f32>,
+    star_rotation: mat4x4<f32>,
+    depolarization_factor: f32,
+    luminance: f32,
+    mie_coefficient: f32,
+    mie_directional_g: f32,
+    mie_v: f32,
+    mie_zenith_length: f32,
+    num_molecules: f32,
+    rayleigh: f32,
+    rayleigh_zenith_length: f32,
+    refractive_index: f32,
+    sun_angular_diameter_degrees: f32,
+    sun_intensity_factor: f32,
+    sun_intensity_falloff_steepness: f32,
+    tonemap_weighting: f32,
+    turbidity: f32,
+    moon_intensity: f32,
+    cloud_coverage: f32,
+    cloud_speed: f32,
+    cloud_time: f32,
+};
+
+@group(0) @binding(0) var<uniform> camera_view_proj: CameraViewProj;
+@group(0) @binding(1) var<uniform> camera_position: CameraPosition;
+@group(1) @binding(0) var<uniform> transform: Transform;
+@group(2) @binding(0) var<uniform> ps: PhysicalSkyMaterial;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vertex(@location(0) vertex_position: vec3<f32>) -> VertexOutput {
+    let world_position = transform.model * vec4<f32>(vertex_position, 1.0);
+    var out: VertexOutput;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera_view_proj.view_proj * world_position;
+    return out;
+}
+
+const PI: f32 = 3.141592653589793;
+const UP: vec3<f32> = vec3<f32>(0.0, 1.0, 0.0);
+
+fn total_rayleigh(lambda: vec3<f32>) -> vec3<f32> {
+    return (8.0 * pow(PI, 3.0) * pow(pow(ps.refractive_index, 2.0) - 1.0, 2.0) * (6.0 + 3.0 * ps.depolarization_factor))
+        / (3.0 * ps.num_molecules * pow(lambda, vec3<f32>(4.0)) * (6.0 - 7.0 * ps.depolarization_factor));
+}
+
+fn total_mie(lambda: vec3<f32>, k: vec3<f32>, t: f32) -> vec3<f32> {
+    let c = 0.2 * t * 10e-18;
+    return 0.434 * c * PI * pow((2.0 * PI) / lambda, vec3<f32>(ps.mie_v - 2.0)) * k;
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    return (3.0 / (16.0 * PI)) * (1.0 + pow(cos_theta, 2.0));
+}
+
+fn henyey_greenstein_phase(cos_theta: f32, g: f32) -> f32 {
+    return (1.0 / (4.0 * PI)) * ((1.0 - pow(g, 2.0)) / pow(1.0 - 2.0 * g * cos_theta + pow(g, 2.0), 1.5));
+}
+
+fn sun_intensity(zenith_angle_cos: f32) -> f32 {
+    let cutoff_angle = PI / 1.95; // Earth shadow hack
+    return ps.sun_intensity_factor * max(0.0, 1.0 - exp(-((cutoff_angle - acos(zenith_angle_cos)) / ps.sun_intensity_falloff_steepness)));
+}
+
+fn hash(p_in: vec3<f32>) -> f32 {
+    var p = fract(p_in * 0.3183099 + 0.1);
+    p = p * 17.0;
+    return fract(p.x * p.y * p.z * (p.x + p.y + p.z));
+}
+
+// Sparse points of light fixed to the sky, where direction is in the frame of the stars
+fn stars(direction: vec3<f32>) -> vec3<f32> {
+    let cell = floor(direction * 300.0);
+    let h = hash(cell);
+    let brightness = smoothstep(0.997, 1.0, h);
+    return vec3<f32>(brightness) * mix(vec3<f32>(0.8, 0.85, 1.0), vec3<f32>(1.0, 0.9, 0.8), hash(cell + 1.0));
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash(vec3<f32>(i, 0.0));
+    let b = hash(vec3<f32>(i + vec2<f32>(1.0, 0.0), 0.0));
+    let c = hash(vec3<f32>(i + vec2<f32>(0.0, 1.0), 0.0));
+    let d = hash(vec3<f32>(i + vec2<f32>(1.0, 1.0), 0.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn cloud_noise(p_in: vec2<f32>) -> f32 {
+    var p = p_in;
+    var value = 0.0;
+    var amplitude = 0.5;
+    for (var i = 0; i < 5; i++) {
+        value += amplitude * value_noise(p);
+        p = p * 2.03 + vec2<f32>(17.0, 31.0);
+        amplitude *= 0.5;
+    }
+    return value;
+}
+
+// The colour and opacity of a flat layer of clouds high above the camera, lit by the sun
+fn clouds(direction: vec3<f32>, sun_direction: vec3<f32>, night: f32) -> vec4<f32> {
+    if (direction.y <= 0.0) {
+        return vec4<f32>(0.0);
+    }
+    let uv = direction.xz / (direction.y + 0.1) * 2.0 + vec2<f32>(1.0, 0.3) * ps.cloud_speed * ps.cloud_time;
+    let threshold = 1.0 - ps.cloud_coverage;
+    // Thin out towards the horizon, where the layer is seen edge-on
+    let density = smoothstep(threshold, threshold + 0.3, cloud_noise(uv)) * smoothstep(0.0, 0.15, direction.y);
+    // Warm when the sun is low and brightest around the sun
+    let sunlight = mix(vec3<f32>(1.0, 0.6, 0.4), vec3<f32>(1.0), clamp(sun_direction.y * 3.0, 0.0, 1.0));
+    let silver_lining = pow(max(dot(direction, sun_direction), 0.0), 8.0) * 0.5;
+    let day_color = vec3<f32>(0.35, 0.38, 0.45) + sunlight * (0.55 + silver_lining);
+    let color = mix(day_color, vec3<f32>(0.02, 0.025, 0.04), night);
+    return vec4<f32>(color, density);
+}
+
+// Whitescale tonemapping calculation, see http://filmicgames.com/archives/75
+// Also see http://blenderartists.org/forum/showthread.php?321110-Shaders-and-Skybox-madness
+const A: f32 = 0.15; // Shoulder strength
+const B: f32 = 0.50; // Linear strength
+const C: f32 = 0.10; // Linear angle
+const D: f32 = 0.20; // Toe strength
+const E: f32 = 0.02; // Toe numerator
+const F: f32 = 0.30; // Toe denominator
+fn uncharted2_tonemap(w: vec3<f32>) -> vec3<f32> {
+    return ((w * (A * w + C * B) + D * E) / (w * (A * w + B) + D * F)) - E / F;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let view_direction = normalize(in.world_position - camera_position.position.xyz);
+
+    // Rayleigh coefficient
+    let sunfade = 1.0 - clamp(1.0 - exp(ps.sun_position.y / 450000.0), 0.0, 1.0);
+    let rayleigh_coefficient = ps.rayleigh - (1.0 * (1.0 - sunfade));
+    let beta_r = total_rayleigh(ps.primaries.rgb) * rayleigh_coefficient;
+
+    // Mie coefficient
+    let beta_m = total_mie(ps.primaries.rgb, ps.mie_k_coefficient.rgb, ps.turbidity) * ps.mie_coefficient;
+
+    // Optical length, cutoff angle at 90 to avoid singularity
+    let zenith_angle = acos(max(0.0, dot(UP, view_direction)));
+    let denom = cos(zenith_angle) + 0.15 * pow(93.885 - ((zenith_angle * 180.0) / PI), -1.253);
+    let s_r = ps.rayleigh_zenith_length / denom;
+    let s_m = ps.mie_zenith_length / denom;
+
+    // Combined extinction factor
+    let fex = exp(-(beta_r * s_r + beta_m * s_m));
+
+    // In-scattering
+    let sun_direction = normalize(ps.sun_position.xyz);
+    let cos_theta = dot(view_direction, sun_direction);
+    let beta_r_theta = beta_r * rayleigh_phase(cos_theta * 0.5 + 0.5);
+    let beta_m_theta = beta_m * henyey_greenstein_phase(cos_theta, ps.mie_directional_g);
+    let sun_e = sun_intensity(dot(sun_direction, UP));
+    let scattering = sun_e * ((beta_r_theta + beta_m_theta) / (beta_r + beta_m));
+    var lin = pow(scattering * (1.0 - fex), vec3<f32>(1.5));
+    lin *= mix(vec3<f32>(1.0), pow(scattering * fex, vec3<f32>(0.5)), clamp(pow(1.0 - dot(UP, sun_direction), 5.0), 0.0, 1.0));
+
+    // Composition + solar disc
+    let sun_angular_diameter_cos = cos(ps.sun_angular_diameter_degrees);
+    let sundisk = smoothstep(sun_angular_diameter_cos, sun_angular_diameter_cos + 0.00002, cos_theta);
+    var l0 = vec3<f32>(0.1) * fex;
+    l0 += sun_e * 19000.0 * fex * sundisk;
+    var tex_color = lin + l0;
+    tex_color *= 0.04;
+    tex_color += vec3<f32>(0.0, 0.001, 0.0025) * 0.3;
+
+    // Tonemapping
+    let white_scale = 1.0 / uncharted2_tonemap(vec3<f32>(ps.tonemap_weighting));
+    let curr = uncharted2_tonemap((log2(2.0 / pow(ps.luminance, 4.0))) * tex_color);
+    let color = curr * white_scale;
+    var ret_color = pow(color, vec3<f32>(1.0 / (1.2 + (1.2 * sunfade))));
+
+    // Night sky, fading in as the sun sets
+    let night = clamp(-sun_direction.y * 10.0, 0.0, 1.0);
+    let above_horizon = smoothstep(-0.02, 0.02, view_direction.y);
+    let moon_direction = normalize(ps.moon_position.xyz);
+    let moon_cos_theta = dot(view_direction, moon_direction);
+    let moon_disk = smoothstep(0.9995, 0.9997, moon_cos_theta);
+    let moon_glow = pow(max(moon_cos_theta, 0.0), 200.0) * 0.15;
+    let moon_color = vec3<f32>(0.9, 0.92, 1.0) * (moon_disk + moon_glow) * ps.moon_intensity;
+    let star_direction = (ps.star_rotation * vec4<f32>(view_direction, 0.0)).xyz;
+    let night_color = moon_color + stars(star_direction) * (1.0 - moon_disk);
+    ret_color += night_color * night * above_horizon;
+
+    if (ps.cloud_coverage > 0.0) {
+        let cloud = clouds(view_direction, sun_direction, night);
+        ret_color = mix(ret_color, cloud.rgb, cloud.a);
+    }
+
+    return vec4<f32>(ret_color, 1.0);
+}
@@ -0,0 +1,101 @@
+use bevy::{
+    input::system::exit_on_esc_system,
+    prelude::*,
+    render::{
+        pipeline::{FrontFace, PipelineDescriptor, RenderPipeline},
+        shader::{ShaderStage, ShaderStages},
+    },
+};
+use bevy_physical_sky::{
+    PhysicalSkyCameraTag, PhysicalSkyMaterial, PhysicalSkyPlugin, SolarPosition, TimeZone,
+    Timelike, Utc, PHYSICAL_SKY_FRAGMENT_SHADER, PHYSICAL_SKY_PASS_TIME_SYSTEM,
+    PHYSICAL_SKY_SETUP_SYSTEM, PHYSICAL_SKY_VERTEX_SHADER,
+};
+
+fn main() {
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_system(exit_on_esc_system.system())
+        .insert_resource(SolarPosition {
+            now: Utc.ymd(2021, 03, 01).and_hms(7, 0, 0),
+            // one day per 30 seconds of real time
+            ..SolarPosition::at_city("Stockholm")
+                .unwrap()
+                .with_day_length(30.0)
+        })
+        .add_plugin(PhysicalSkyPlugin)
+        .add_startup_system(setup.system().after(PHYSICAL_SKY_SETUP_SYSTEM))
+        .add_system(cycle_presets.system().before(PHYSICAL_SKY_PASS_TIME_SYSTEM))
+        .run();
+}
+
+// Blends from stellar dawn at midnight to a red sunset at midday and back again
+fn cycle_presets(
+    solar_position: Res<SolarPosition>,
+    query: Query<&Handle<PhysicalSkyMaterial>>,
+    mut materials: ResMut<Assets<PhysicalSkyMaterial>>,
+) {
+    let seconds = solar_position.now.num_seconds_from_midnight() as f32;
+    let t = 1.0 - (2.0 * seconds / (24.0 * 60.0 * 60.0) - 1.0).abs();
+    let blended = PhysicalSkyMaterial::lerp(
+        &PhysicalSkyMaterial::stellar_dawn(true),
+        &PhysicalSkyMaterial::red_sunset(true),
+        t,
+    );
+    for handle in query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            // pass_time runs afterwards and moves the sun, moon and stars to where they should be
+            *material = PhysicalSkyMaterial {
+                sun_position: material.sun_position,
+                moon_position: material.moon_position,
+                star_rotation: material.star_rotation,
+                ..blended
+            };
+        }
+    }
+}
+
+fn setup(
+    mut shaders: ResMut<Assets<Shader>>,
+    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
+    mut sky_materials: ResMut<Assets<PhysicalSkyMaterial>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    // Create a new shader pipeline
+    let mut pipeline_descriptor = PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(
+            ShaderStage::Vertex,
+            PHYSICAL_SKY_VERTEX_SHADER,
+        )),
+        fragment: Some(shaders.add(Shader::from_glsl(
+            ShaderStage::Fragment,
+            PHYSICAL_SKY_FRAGMENT_SHADER,
+        ))),
+    });
+    // Reverse the winding so we can see the faces from the inside
+    pipeline_descriptor.primitive.front_face = FrontFace::Cw;
+    let pipeline = pipelines.add(pipeline_descriptor);
+
+    // Create a new material
+    let material = sky_materials.add(PhysicalSkyMaterial::stellar_dawn(true));
+
+    // plane
+    commands
+        .spawn_bundle(MeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 15.0 })),
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(pipeline)]),
+            transform: Transform::default(),
+            ..Default::default()
+        })
+        .insert(material);
+
+    // camera
+    commands
+        .spawn_bundle(PerspectiveCameraBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 0.0)
+                .looking_at(Vec3::new(1.0, 0.0, 1.0), Vec3::Y),
+            ..Default::default()
+        })
+        .insert(PhysicalSkyCameraTag);
+}
@@ -16,13 +16,11 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_system(exit_on_esc_system.system())
         .insert_resource(SolarPosition {
-            // Stockholm
-            latitude: 59.33258,
-            longitude: 18.0649,
-            // one day per 30 seconds of real time
-            simulation_seconds_per_second: 24.0 * 60.0 * 60.0 / 30.0,
             now: Utc.ymd(2021, 03, 01).and_hms(7, 0, 0),
-            ..Default::default()
+            // one day per 30 seconds of real time
+            ..SolarPosition::at_city("Stockholm")
+                .unwrap()
+                .with_day_length(30.0)
         })
         .add_plugin(PhysicalSkyPlugin)
         .add_startup_system(setup.system().after(PHYSICAL_SKY_SETUP_SYSTEM))
@@ -51,11 +49,13 @@ fn setup(
     pipeline_descriptor.primitive.front_face = FrontFace::Cw;
     let pipeline = pipelines.add(pipeline_descriptor);
 
-    // Create a new material
-    let material = sky_materials.add(PhysicalSkyMaterial {
+    // Create a new material, partly cloudy
+    let mut sky = PhysicalSkyMaterial {
         update_sun_position: true,
         ..Default::default()
-    });
+    };
+    sky.set_cloud_coverage(0.4);
+    let material = sky_materials.add(sky);
 
     // plane
     commands
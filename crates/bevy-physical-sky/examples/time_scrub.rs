@@ -0,0 +1,134 @@
+use bevy::{
+    input::system::exit_on_esc_system,
+    prelude::*,
+    render::{
+        pipeline::{FrontFace, PipelineDescriptor, RenderPipeline},
+        shader::{ShaderStage, ShaderStages},
+    },
+};
+use bevy_physical_sky::{
+    Duration, PhysicalSkyCameraTag, PhysicalSkyMaterial, PhysicalSkyPlugin, SolarPosition,
+    TimeZone, Utc, PHYSICAL_SKY_FRAGMENT_SHADER, PHYSICAL_SKY_PASS_TIME_SYSTEM,
+    PHYSICAL_SKY_SETUP_SYSTEM, PHYSICAL_SKY_VERTEX_SHADER,
+};
+
+// Space pauses and resumes, left and right scrub by an hour, up and down double and halve the
+// time scale, and D jumps to dawn
+fn main() {
+    App::build()
+        .add_plugins(DefaultPlugins)
+        .add_system(exit_on_esc_system.system())
+        .insert_resource(SolarPosition {
+            now: Utc.ymd(2021, 06, 21).and_hms(12, 0, 0),
+            // one day per 2 minutes of real time
+            ..SolarPosition::at_city("Stockholm")
+                .unwrap()
+                .with_day_length(120.0)
+        })
+        .add_plugin(PhysicalSkyPlugin)
+        .add_startup_system(setup.system().after(PHYSICAL_SKY_SETUP_SYSTEM))
+        .add_system(scrub_time.system().before(PHYSICAL_SKY_PASS_TIME_SYSTEM))
+        .run();
+}
+
+fn scrub_time(keyboard_input: Res<Input<KeyCode>>, mut solar_position: ResMut<SolarPosition>) {
+    let mut changed = false;
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        if solar_position.paused {
+            solar_position.resume();
+        } else {
+            solar_position.pause();
+        }
+        changed = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        let now = solar_position.now - Duration::hours(1);
+        solar_position.set_datetime(now);
+        changed = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) {
+        let now = solar_position.now + Duration::hours(1);
+        solar_position.set_datetime(now);
+        changed = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        let scale = solar_position.simulation_seconds_per_second * 2.0;
+        solar_position.set_time_scale(scale);
+        changed = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        let scale = solar_position.simulation_seconds_per_second * 0.5;
+        solar_position.set_time_scale(scale);
+        changed = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::D) {
+        // Sunrise in Stockholm around midsummer is at about 01:30 UTC
+        let dawn = solar_position.now.date().and_hms(1, 30, 0);
+        solar_position.set_datetime(dawn);
+        changed = true;
+    }
+    if changed {
+        let (azimuth, inclination) = solar_position.get_azimuth_inclination();
+        let (lowest, highest) = solar_position.inclination_range();
+        println!(
+            "{} paused: {} scale: {}x azimuth: {:.2} inclination: {:.2} today: [{:.2}, {:.2}] polar day: {} polar night: {}",
+            solar_position.now,
+            solar_position.paused,
+            solar_position.simulation_seconds_per_second,
+            azimuth,
+            inclination,
+            lowest,
+            highest,
+            solar_position.is_polar_day(),
+            solar_position.is_polar_night()
+        );
+    }
+}
+
+fn setup(
+    mut shaders: ResMut<Assets<Shader>>,
+    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
+    mut sky_materials: ResMut<Assets<PhysicalSkyMaterial>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    // Create a new shader pipeline
+    let mut pipeline_descriptor = PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(
+            ShaderStage::Vertex,
+            PHYSICAL_SKY_VERTEX_SHADER,
+        )),
+        fragment: Some(shaders.add(Shader::from_glsl(
+            ShaderStage::Fragment,
+            PHYSICAL_SKY_FRAGMENT_SHADER,
+        ))),
+    });
+    // Reverse the winding so we can see the faces from the inside
+    pipeline_descriptor.primitive.front_face = FrontFace::Cw;
+    let pipeline = pipelines.add(pipeline_descriptor);
+
+    // Create a new material
+    let material = sky_materials.add(PhysicalSkyMaterial {
+        update_sun_position: true,
+        ..Default::default()
+    });
+
+    // plane
+    commands
+        .spawn_bundle(MeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 15.0 })),
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(pipeline)]),
+            transform: Transform::default(),
+            ..Default::default()
+        })
+        .insert(material);
+
+    // camera
+    commands
+        .spawn_bundle(PerspectiveCameraBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 0.0)
+                .looking_at(Vec3::new(1.0, 0.0, 1.0), Vec3::Y),
+            ..Default::default()
+        })
+        .insert(PhysicalSkyCameraTag);
+}
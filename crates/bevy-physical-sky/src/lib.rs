@@ -3,24 +3,29 @@ use bevy::{
     prelude::*,
     reflect::TypeUuid,
     render::{
+        camera::PerspectiveProjection,
         render_graph::{base, AssetRenderResourcesNode, RenderGraph},
         renderer::{RenderResource, RenderResources},
-        shader::ShaderDefs,
+        shader::{asset_shader_defs_system, ShaderDefs},
     },
     transform::TransformSystem,
 };
 
 pub mod solar_position;
 
-pub use chrono::prelude::*;
+pub use chrono::{prelude::*, Duration};
 pub use solar_position::*;
 
 pub const PHYSICAL_SKY_SETUP_SYSTEM: &str = "physical_sky_setup";
 pub const PHYSICAL_SKY_PASS_TIME_SYSTEM: &str = "physical_sky_pass_time";
 pub const PHYSICAL_SKY_TRACK_CAMERA_SYSTEM: &str = "physical_sky_track_camera";
+pub const PHYSICAL_SKY_CONTROLS_SYSTEM: &str = "physical_sky_controls";
 pub const PHYSICAL_SKY_RENDER_NODE: &str = "physical_sky";
 pub const PHYSICAL_SKY_VERTEX_SHADER: &str = include_str!("../assets/shaders/physical_sky.vert");
 pub const PHYSICAL_SKY_FRAGMENT_SHADER: &str = include_str!("../assets/shaders/physical_sky.frag");
+/// Both stages in WGSL, with `vertex` and `fragment` entry points, for renderers that take WGSL.
+/// This version of Bevy only loads GLSL and SPIR-V, so the pipelines here use the GLSL shaders.
+pub const PHYSICAL_SKY_WGSL_SHADER: &str = include_str!("../assets/shaders/physical_sky.wgsl");
 
 const SUN_DISTANCE: f32 = 400000.0;
 
@@ -32,6 +37,16 @@ impl Plugin for PhysicalSkyPlugin {
             .add_startup_system(setup.system().label(PHYSICAL_SKY_SETUP_SYSTEM))
             .add_startup_system(pass_time.system())
             .add_system(pass_time.system().label(PHYSICAL_SKY_PASS_TIME_SYSTEM))
+            .add_system(
+                apply_controls
+                    .system()
+                    .label(PHYSICAL_SKY_CONTROLS_SYSTEM)
+                    .after(PHYSICAL_SKY_PASS_TIME_SYSTEM),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                asset_shader_defs_system::<PhysicalSkyMaterial>.system(),
+            )
             .add_system_to_stage(
                 CoreStage::PostUpdate,
                 track_camera
@@ -42,13 +57,16 @@ impl Plugin for PhysicalSkyPlugin {
     }
 }
 
-#[derive(Debug, RenderResource, RenderResources, ShaderDefs, TypeUuid)]
+#[derive(Clone, Debug, RenderResource, RenderResources, ShaderDefs, TypeUuid)]
 #[uuid = "3035b6eb-0716-4980-8ed9-6d4308900e30"]
 #[render_resources(from_self)]
 pub struct PhysicalSkyMaterial {
     pub mie_k_coefficient: Vec4,
     pub primaries: Vec4,
     pub sun_position: Vec4,
+    pub moon_position: Vec4,
+    /// Rotates view directions into the frame of the stars, see `set_star_rotation`
+    pub star_rotation: Mat4,
     pub depolarization_factor: f32,
     pub luminance: f32,
     pub mie_coefficient: f32,
@@ -64,8 +82,20 @@ pub struct PhysicalSkyMaterial {
     pub sun_intensity_falloff_steepness: f32,
     pub tonemap_weighting: f32,
     pub turbidity: f32,
+    pub moon_intensity: f32,
+    /// The fraction of the sky covered by clouds, from 0.0 to 1.0
+    pub cloud_coverage: f32,
+    /// How fast the clouds drift across the sky
+    pub cloud_speed: f32,
+    /// Real seconds that the clouds have been drifting for, advanced by `pass_time`
+    pub cloud_time: f32,
     #[render_resources(ignore)]
     pub update_sun_position: bool,
+    /// Whether the shader draws clouds at all, so that a clear sky costs nothing. Kept in step
+    /// with `cloud_coverage` by `set_cloud_coverage`.
+    #[render_resources(ignore)]
+    #[shader_def]
+    pub clouds: bool,
 }
 
 unsafe impl Byteable for PhysicalSkyMaterial {}
@@ -77,6 +107,8 @@ impl Default for PhysicalSkyMaterial {
             mie_k_coefficient: Vec4::new(0.686, 0.678, 0.666, 0.0),
             primaries: Vec4::new(6.8e-7, 5.5e-7, 4.5e-7, 0.0),
             sun_position: Vec4::ZERO,
+            moon_position: Vec4::ZERO,
+            star_rotation: Mat4::IDENTITY,
             depolarization_factor: 0.02,
             luminance: 1.00,
             mie_coefficient: 0.005,
@@ -92,23 +124,135 @@ impl Default for PhysicalSkyMaterial {
             sun_intensity_falloff_steepness: 1.5,
             tonemap_weighting: 9.50,
             turbidity: 4.7,
+            // The moon and stars are only drawn while the sun is below the horizon
+            moon_intensity: 1.0,
+            cloud_coverage: 0.0,
+            cloud_speed: 0.02,
+            cloud_time: 0.0,
             update_sun_position: false,
+            clouds: false,
         };
-        sky.set_sun_position(
+        let (inclination, azimuth) = (
             std::f32::consts::PI * (0.4983 - 0.5),
             2.0 * std::f32::consts::PI * (0.1979 - 0.5),
-            SUN_DISTANCE,
         );
+        sky.set_sun_position(inclination, azimuth, SUN_DISTANCE);
+        sky.set_moon_position(inclination, azimuth + std::f32::consts::PI, SUN_DISTANCE);
+        sky.set_star_rotation(0.0, 0.0);
         sky
     }
 }
 
 impl PhysicalSkyMaterial {
-    /// inclination in [-pi/2, pi/2], azimuth in [-pi, pi]
+    /// inclination in [-pi/2, pi/2], azimuth in [-pi, pi]. The inclination is clamped and the
+    /// azimuth wrapped into range.
     pub fn set_sun_position(&mut self, inclination: f32, azimuth: f32, distance: f32) {
-        self.sun_position.x = distance * azimuth.cos();
-        self.sun_position.y = distance * azimuth.sin() * inclination.sin();
-        self.sun_position.z = distance * azimuth.sin() * inclination.cos();
+        set_orbit_position(&mut self.sun_position, inclination, azimuth, distance);
+    }
+
+    /// The inclination and azimuth of the sun as set by `set_sun_position`, after clamping and
+    /// wrapping. Where the inputs are ambiguous, i.e. azimuth 0 or pi, or inclination -pi/2 or
+    /// pi/2, an equivalent pair is returned.
+    pub fn get_sun_inclination_azimuth(&self) -> (f32, f32) {
+        orbit_inclination_azimuth(self.sun_position)
+    }
+
+    /// inclination in [-pi/2, pi/2], azimuth in [-pi, pi]. The inclination is clamped and the
+    /// azimuth wrapped into range.
+    pub fn set_moon_position(&mut self, inclination: f32, azimuth: f32, distance: f32) {
+        set_orbit_position(&mut self.moon_position, inclination, azimuth, distance);
+    }
+
+    /// The inclination and azimuth of the moon as set by `set_moon_position`, see
+    /// `get_sun_inclination_azimuth`
+    pub fn get_moon_inclination_azimuth(&self) -> (f32, f32) {
+        orbit_inclination_azimuth(self.moon_position)
+    }
+
+    pub fn set_cloud_coverage(&mut self, cloud_coverage: f32) {
+        self.cloud_coverage = cloud_coverage.max(0.0).min(1.0);
+        self.clouds = self.cloud_coverage > 0.0;
+    }
+
+    /// Turns the stars for an observer at `latitude` when `local_sidereal_time` is on the meridian,
+    /// both in radians. The frame of the stars has its z axis through the celestial north pole and
+    /// its x axis at right ascension 0. The world's horizon is laid out as `pass_time` places the
+    /// sun, with north along -x, east along -z and up along +y.
+    pub fn set_star_rotation(&mut self, latitude: f32, local_sidereal_time: f32) {
+        let (sin_latitude, cos_latitude) = latitude.sin_cos();
+        let (sin_time, cos_time) = local_sidereal_time.sin_cos();
+        self.star_rotation = Mat4::from_cols(
+            Vec4::new(
+                cos_time * sin_latitude,
+                sin_time * sin_latitude,
+                -cos_latitude,
+                0.0,
+            ),
+            Vec4::new(
+                cos_time * cos_latitude,
+                sin_time * cos_latitude,
+                sin_latitude,
+                0.0,
+            ),
+            Vec4::new(sin_time, -cos_time, 0.0, 0.0),
+            Vec4::W,
+        );
+    }
+
+    /// Blends every parameter from `a` at `t == 0.0` to `b` at `t == 1.0`. The sun and moon
+    /// positions are interpolated as directions so that they stay at a constant distance while
+    /// moving across the sky. `star_rotation`, `cloud_time` and `update_sun_position` are taken
+    /// from `a`.
+    pub fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        let lerp = |x: f32, y: f32| x + (y - x) * t;
+        Self {
+            mie_k_coefficient: a.mie_k_coefficient.lerp(b.mie_k_coefficient, t),
+            primaries: a.primaries.lerp(b.primaries, t),
+            sun_position: lerp_position(a.sun_position, b.sun_position, t),
+            moon_position: lerp_position(a.moon_position, b.moon_position, t),
+            star_rotation: a.star_rotation,
+            depolarization_factor: lerp(a.depolarization_factor, b.depolarization_factor),
+            luminance: lerp(a.luminance, b.luminance),
+            mie_coefficient: lerp(a.mie_coefficient, b.mie_coefficient),
+            mie_directional_g: lerp(a.mie_directional_g, b.mie_directional_g),
+            mie_v: lerp(a.mie_v, b.mie_v),
+            mie_zenith_length: lerp(a.mie_zenith_length, b.mie_zenith_length),
+            num_molecules: lerp(a.num_molecules, b.num_molecules),
+            rayleigh: lerp(a.rayleigh, b.rayleigh),
+            rayleigh_zenith_length: lerp(a.rayleigh_zenith_length, b.rayleigh_zenith_length),
+            refractive_index: lerp(a.refractive_index, b.refractive_index),
+            sun_angular_diameter_degrees: lerp(
+                a.sun_angular_diameter_degrees,
+                b.sun_angular_diameter_degrees,
+            ),
+            sun_intensity_factor: lerp(a.sun_intensity_factor, b.sun_intensity_factor),
+            sun_intensity_falloff_steepness: lerp(
+                a.sun_intensity_falloff_steepness,
+                b.sun_intensity_falloff_steepness,
+            ),
+            tonemap_weighting: lerp(a.tonemap_weighting, b.tonemap_weighting),
+            turbidity: lerp(a.turbidity, b.turbidity),
+            moon_intensity: lerp(a.moon_intensity, b.moon_intensity),
+            cloud_coverage: lerp(a.cloud_coverage, b.cloud_coverage),
+            cloud_speed: lerp(a.cloud_speed, b.cloud_speed),
+            cloud_time: a.cloud_time,
+            update_sun_position: a.update_sun_position,
+            clouds: lerp(a.cloud_coverage, b.cloud_coverage) > 0.0,
+        }
+    }
+
+    /// The intensity of sunlight with the sun `elevation` radians above the horizon, as
+    /// `sunIntensity` in physical_sky.frag scales the scattered light and the solar disc. It
+    /// falls to zero a little below the horizon, where the earth is in the way.
+    pub fn sun_intensity(&self, elevation: f32) -> f32 {
+        let cutoff_angle = std::f32::consts::PI / 1.95;
+        let zenith_angle = std::f32::consts::FRAC_PI_2
+            - elevation
+                .max(-std::f32::consts::FRAC_PI_2)
+                .min(std::f32::consts::FRAC_PI_2);
+        self.sun_intensity_factor
+            * (1.0 - (-(cutoff_angle - zenith_angle) / self.sun_intensity_falloff_steepness).exp())
+                .max(0.0)
     }
 
     pub fn stellar_dawn(update_sun_position: bool) -> Self {
@@ -231,6 +375,54 @@ impl PhysicalSkyMaterial {
         }
     }
 
+    pub fn clear_day(update_sun_position: bool) -> Self {
+        Self {
+            mie_k_coefficient: Vec4::new(0.686, 0.678, 0.666, 0.0),
+            primaries: Vec4::new(6.8e-7, 5.5e-7, 4.5e-7, 0.0),
+            turbidity: 2.0,
+            rayleigh: 1.0,
+            mie_coefficient: 0.005,
+            mie_directional_g: 0.8,
+            luminance: 1.00,
+            refractive_index: 1.0003,
+            num_molecules: 2.542e25,
+            depolarization_factor: 0.035,
+            rayleigh_zenith_length: 8400.0,
+            mie_v: 4.0,
+            mie_zenith_length: 1250.0,
+            sun_intensity_factor: 1000.0,
+            sun_intensity_falloff_steepness: 1.5,
+            sun_angular_diameter_degrees: 0.0093,
+            tonemap_weighting: 9.50,
+            update_sun_position,
+            ..Default::default()
+        }
+    }
+
+    pub fn night(update_sun_position: bool) -> Self {
+        Self {
+            mie_k_coefficient: Vec4::new(0.686, 0.678, 0.666, 0.0),
+            primaries: Vec4::new(6.8e-7, 5.5e-7, 4.5e-7, 0.0),
+            turbidity: 2.0,
+            rayleigh: 1.5,
+            mie_coefficient: 0.003,
+            mie_directional_g: 0.8,
+            luminance: 1.1,
+            refractive_index: 1.00029,
+            num_molecules: 2.542e25,
+            depolarization_factor: 0.035,
+            rayleigh_zenith_length: 8400.0,
+            mie_v: 3.936,
+            mie_zenith_length: 34000.0,
+            sun_intensity_factor: 1000.0,
+            sun_intensity_falloff_steepness: 1.5,
+            sun_angular_diameter_degrees: 0.00933,
+            tonemap_weighting: 9.50,
+            update_sun_position,
+            ..Default::default()
+        }
+    }
+
     pub fn blood_sky(update_sun_position: bool) -> Self {
         Self {
             mie_k_coefficient: Vec4::new(0.686, 0.678, 0.666, 0.0),
@@ -256,6 +448,130 @@ impl PhysicalSkyMaterial {
     }
 }
 
+/// The most artistically relevant sky parameters, for tweaking at runtime. When this resource is
+/// present, any change to it is written into every `PhysicalSkyMaterial`.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalSkyControls {
+    pub turbidity: f32,
+    pub rayleigh: f32,
+    pub mie_coefficient: f32,
+    pub mie_directional_g: f32,
+    pub luminance: f32,
+    pub tonemap_weighting: f32,
+}
+
+impl PhysicalSkyControls {
+    pub fn apply(&self, material: &mut PhysicalSkyMaterial) {
+        material.turbidity = self.turbidity;
+        material.rayleigh = self.rayleigh;
+        material.mie_coefficient = self.mie_coefficient;
+        material.mie_directional_g = self.mie_directional_g;
+        material.luminance = self.luminance;
+        material.tonemap_weighting = self.tonemap_weighting;
+    }
+}
+
+impl Default for PhysicalSkyControls {
+    fn default() -> Self {
+        Self::from(&PhysicalSkyMaterial::default())
+    }
+}
+
+impl From<&PhysicalSkyMaterial> for PhysicalSkyControls {
+    fn from(material: &PhysicalSkyMaterial) -> Self {
+        Self {
+            turbidity: material.turbidity,
+            rayleigh: material.rayleigh,
+            mie_coefficient: material.mie_coefficient,
+            mie_directional_g: material.mie_directional_g,
+            luminance: material.luminance,
+            tonemap_weighting: material.tonemap_weighting,
+        }
+    }
+}
+
+/// Atmospheric parameters keyed by sun elevation in degrees. When this resource is present,
+/// `pass_time` blends between the two presets either side of the current elevation for every
+/// material with `update_sun_position` set.
+pub struct AutoSkyPreset {
+    // Sorted by elevation
+    presets: Vec<(f32, PhysicalSkyMaterial)>,
+}
+
+impl AutoSkyPreset {
+    pub fn new(mut presets: Vec<(f32, PhysicalSkyMaterial)>) -> Self {
+        assert!(!presets.is_empty(), "At least one preset is required");
+        presets.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        Self { presets }
+    }
+
+    /// The blended parameters at `elevation` degrees. Elevations outside the range of the presets
+    /// get the nearest preset.
+    pub fn blend(&self, elevation: f32) -> PhysicalSkyMaterial {
+        let upper = self
+            .presets
+            .iter()
+            .position(|(preset_elevation, _)| elevation < *preset_elevation);
+        match upper {
+            Some(0) => self.presets[0].1.clone(),
+            Some(i) => {
+                let (e0, ref a) = self.presets[i - 1];
+                let (e1, ref b) = self.presets[i];
+                PhysicalSkyMaterial::lerp(a, b, (elevation - e0) / (e1 - e0))
+            }
+            None => self.presets[self.presets.len() - 1].1.clone(),
+        }
+    }
+}
+
+impl Default for AutoSkyPreset {
+    fn default() -> Self {
+        Self::new(vec![
+            (-6.0, PhysicalSkyMaterial::night(true)),
+            (0.0, PhysicalSkyMaterial::red_sunset(true)),
+            (10.0, PhysicalSkyMaterial::red_sunset(true)),
+            (30.0, PhysicalSkyMaterial::clear_day(true)),
+        ])
+    }
+}
+
+// The azimuth goes around a circle that is tilted about the x axis by the inclination
+fn set_orbit_position(position: &mut Vec4, inclination: f32, azimuth: f32, distance: f32) {
+    let inclination = inclination
+        .max(-std::f32::consts::FRAC_PI_2)
+        .min(std::f32::consts::FRAC_PI_2);
+    let azimuth = (azimuth + std::f32::consts::PI).rem_euclid(2.0 * std::f32::consts::PI)
+        - std::f32::consts::PI;
+    position.x = distance * azimuth.cos();
+    position.y = distance * azimuth.sin() * inclination.sin();
+    position.z = distance * azimuth.sin() * inclination.cos();
+}
+
+// The inverse of set_orbit_position. As cos(inclination) is never negative, z has the sign of
+// sin(azimuth), except when z is 0 where sin(azimuth) is taken to be positive.
+fn orbit_inclination_azimuth(position: Vec4) -> (f32, f32) {
+    let sign = if position.z < 0.0 { -1.0 } else { 1.0 };
+    let radius = (position.y * position.y + position.z * position.z).sqrt();
+    let azimuth = (sign * radius).atan2(position.x);
+    let inclination = if radius > 0.0 {
+        (sign * position.y).atan2(sign * position.z)
+    } else {
+        0.0
+    };
+    (inclination, azimuth)
+}
+
+// Normalized lerp of the directions, with the distance interpolated separately
+fn lerp_position(a: Vec4, b: Vec4, t: f32) -> Vec4 {
+    let (a, b) = (a.truncate(), b.truncate());
+    let distance = a.length() + (b.length() - a.length()) * t;
+    let direction = a
+        .normalize_or_zero()
+        .lerp(b.normalize_or_zero(), t)
+        .normalize_or_zero();
+    (direction * distance).extend(0.0)
+}
+
 pub struct PhysicalSkyCameraTag;
 
 pub fn setup(mut render_graph: ResMut<RenderGraph>) {
@@ -274,23 +590,58 @@ pub fn setup(mut render_graph: ResMut<RenderGraph>) {
         .unwrap();
 }
 
+/// Scales a sky mesh with a radius of 1.0 to just inside the far plane of the
+/// `PhysicalSkyCameraTag` camera, so that the sky is never clipped whatever the camera's
+/// projection. Sky meshes without it keep their own size.
+pub struct SkyDome {
+    /// The fraction of the camera's far plane distance to place the dome at
+    pub far_fraction: f32,
+}
+
+impl Default for SkyDome {
+    fn default() -> Self {
+        Self { far_fraction: 0.98 }
+    }
+}
+
+/// Centres every sky mesh on the `PhysicalSkyCameraTag` camera. Only the translation is followed,
+/// so the sky keeps its own rotation and scale. Does nothing without a tagged camera. With more
+/// than one, the sky can only be centred on one of them, so a warning is logged and whichever
+/// comes first is followed.
 pub fn track_camera(
     transforms: QuerySet<(
-        Query<&GlobalTransform, With<PhysicalSkyCameraTag>>,
-        Query<&mut GlobalTransform, With<Handle<PhysicalSkyMaterial>>>,
+        Query<(&GlobalTransform, Option<&PerspectiveProjection>), With<PhysicalSkyCameraTag>>,
+        Query<(&mut GlobalTransform, Option<&SkyDome>), With<Handle<PhysicalSkyMaterial>>>,
     )>,
+    mut warned: Local<bool>,
 ) {
-    let mut cam_temp = transforms.q0().iter();
-    if let Some(camera_transform) = cam_temp.next() {
-        transforms
-            .q1()
-            .for_each_mut(|mut mesh_transform| *mesh_transform = *camera_transform);
+    let mut cameras = transforms.q0().iter();
+    let (camera_translation, far) = match cameras.next() {
+        Some((transform, projection)) => (
+            transform.translation,
+            projection.map(|projection| projection.far),
+        ),
+        None => return,
+    };
+    if cameras.next().is_some() && !*warned {
+        warn!("More than one PhysicalSkyCameraTag camera, the sky only follows one of them");
+        *warned = true;
     }
+    transforms
+        .q1()
+        .for_each_mut(|(mut mesh_transform, sky_dome)| {
+            mesh_transform.translation = camera_translation;
+            if let (Some(sky_dome), Some(far)) = (sky_dome, far) {
+                mesh_transform.scale = Vec3::splat(sky_dome.far_fraction * far);
+            }
+        });
 }
 
 pub fn pass_time(
     time: Res<Time>,
     mut solar_position: ResMut<SolarPosition>,
+    auto_sky_preset: Option<Res<AutoSkyPreset>>,
+    controls: Option<Res<PhysicalSkyControls>>,
     query: Query<&Handle<PhysicalSkyMaterial>>,
     mut materials: ResMut<Assets<PhysicalSkyMaterial>>,
 ) {
@@ -301,11 +652,167 @@ pub fn pass_time(
         (azimuth.to_radians() - std::f64::consts::PI) as f32,
         inclination.to_radians() as f32,
     );
+    let auto_sky =
+        auto_sky_preset.map(|presets| presets.blend(solar_position.lighting_inclination() as f32));
 
     for handle in query.iter() {
         let material = materials.get_mut(handle).unwrap();
+        // Clouds stand still while the simulation is paused, the same as the sun
+        if material.clouds && !solar_position.paused {
+            material.cloud_time += time.delta_seconds();
+        }
         if material.update_sun_position {
+            if let Some(auto_sky) = auto_sky.as_ref() {
+                // The clouds are weather rather than part of the preset
+                *material = PhysicalSkyMaterial {
+                    update_sun_position: true,
+                    cloud_coverage: material.cloud_coverage,
+                    cloud_speed: material.cloud_speed,
+                    cloud_time: material.cloud_time,
+                    clouds: material.clouds,
+                    ..auto_sky.clone()
+                };
+                // Manual tweaks take precedence over the automatic parameters
+                if let Some(controls) = controls.as_ref() {
+                    controls.apply(material);
+                }
+            }
             material.set_sun_position(inclination_radians, azimuth_radians, SUN_DISTANCE);
+            // Approximate the moon as being opposite the sun, i.e. always full
+            material.set_moon_position(
+                inclination_radians,
+                azimuth_radians + std::f32::consts::PI,
+                SUN_DISTANCE,
+            );
+            material.set_star_rotation(
+                solar_position.latitude.to_radians() as f32,
+                solar_position.local_sidereal_time().to_radians() as f32,
+            );
+        }
+    }
+}
+
+// Only writes on change, as mutably borrowing a material asset re-uploads it to the GPU
+pub fn apply_controls(
+    controls: Option<Res<PhysicalSkyControls>>,
+    query: Query<&Handle<PhysicalSkyMaterial>>,
+    mut materials: ResMut<Assets<PhysicalSkyMaterial>>,
+) {
+    let controls = match controls {
+        Some(controls) if controls.is_changed() => controls,
+        _ => return,
+    };
+    for handle in query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            controls.apply(material);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELEVATIONS: [f32; 5] = [0.0, 0.25, 0.5, 1.0, std::f32::consts::FRAC_PI_2];
+
+    fn presets() -> Vec<(&'static str, PhysicalSkyMaterial)> {
+        vec![
+            ("stellar_dawn", PhysicalSkyMaterial::stellar_dawn(false)),
+            ("red_sunset", PhysicalSkyMaterial::red_sunset(false)),
+            ("alien_day", PhysicalSkyMaterial::alien_day(false)),
+            ("blue_dusk", PhysicalSkyMaterial::blue_dusk(false)),
+            ("purple_dusk", PhysicalSkyMaterial::purple_dusk(false)),
+            ("blood_sky", PhysicalSkyMaterial::blood_sky(false)),
+        ]
+    }
+
+    #[test]
+    fn preset_sun_intensities_are_finite_and_positive() {
+        for (name, preset) in presets().iter() {
+            for elevation in ELEVATIONS.iter() {
+                let intensity = preset.sun_intensity(*elevation);
+                assert!(
+                    intensity.is_finite() && intensity > 0.0,
+                    "{} at elevation {}: {}",
+                    name,
+                    elevation,
+                    intensity
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn preset_sun_intensities_are_distinct() {
+        let presets = presets();
+        for (i, (name_a, a)) in presets.iter().enumerate() {
+            for (name_b, b) in presets[i + 1..].iter() {
+                // At least one elevation must differ by more than 1%
+                let max_difference = ELEVATIONS
+                    .iter()
+                    .map(|elevation| {
+                        let (x, y) = (a.sun_intensity(*elevation), b.sun_intensity(*elevation));
+                        (x - y).abs() / x.max(y)
+                    })
+                    .fold(0.0, f32::max);
+                assert!(
+                    max_difference > 0.01,
+                    "{} and {} have the same sun intensity",
+                    name_a,
+                    name_b
+                );
+            }
+        }
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn sun_and_moon_positions_round_trip() {
+        let mut sky = PhysicalSkyMaterial::default();
+        for inclination in [-1.4, -0.7, 0.0, 0.3, 1.0, 1.4].iter() {
+            for azimuth in [-3.0, -1.5, -0.2, 0.4, 2.0, 3.0].iter() {
+                sky.set_sun_position(*inclination, *azimuth, SUN_DISTANCE);
+                assert_close(sky.get_sun_inclination_azimuth(), (*inclination, *azimuth));
+                sky.set_moon_position(*inclination, *azimuth, SUN_DISTANCE);
+                assert_close(sky.get_moon_inclination_azimuth(), (*inclination, *azimuth));
+            }
         }
     }
+
+    #[test]
+    fn sun_position_is_wrapped_and_clamped() {
+        use std::f32::consts::{FRAC_PI_2, PI};
+        let mut sky = PhysicalSkyMaterial::default();
+        sky.set_sun_position(0.3, 0.5 + 2.0 * PI, SUN_DISTANCE);
+        assert_close(sky.get_sun_inclination_azimuth(), (0.3, 0.5));
+        sky.set_sun_position(-0.3, -0.5 - 4.0 * PI, SUN_DISTANCE);
+        assert_close(sky.get_sun_inclination_azimuth(), (-0.3, -0.5));
+
+        // The inclinations at the poles are ambiguous, so compare the positions instead
+        let mut clamped = PhysicalSkyMaterial::default();
+        sky.set_sun_position(2.0, 0.5, SUN_DISTANCE);
+        clamped.set_sun_position(FRAC_PI_2, 0.5, SUN_DISTANCE);
+        assert_eq!(sky.sun_position, clamped.sun_position);
+        sky.set_sun_position(-2.0, 0.5, SUN_DISTANCE);
+        clamped.set_sun_position(-FRAC_PI_2, 0.5, SUN_DISTANCE);
+        assert_eq!(sky.sun_position, clamped.sun_position);
+        let (inclination, azimuth) = sky.get_sun_inclination_azimuth();
+        sky.set_sun_position(inclination, azimuth, SUN_DISTANCE);
+        assert!((sky.sun_position - clamped.sun_position).length() < 1e-3 * SUN_DISTANCE);
+    }
+
+    #[test]
+    fn sun_intensity_is_zero_below_the_horizon() {
+        let sky = PhysicalSkyMaterial::clear_day(false);
+        assert_eq!(sky.sun_intensity(-0.1), 0.0);
+        assert_eq!(sky.sun_intensity(-std::f32::consts::FRAC_PI_2), 0.0);
+    }
 }
@@ -1,5 +1,43 @@
+use bevy::{log::warn, render::color::Color};
 use chrono::{prelude::*, Duration};
 use spa::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Civil twilight ends with the sun 6 degrees below the horizon
+const TWILIGHT_ELEVATION: f64 = -6.0;
+const DAY_ELEVATION: f64 = 6.0;
+// The elevation at and above which the sun is its full daytime colour
+const WHITE_SUN_ELEVATION: f64 = 20.0;
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+// The sun crosses a degree of longitude in four minutes
+const SECONDS_PER_DEGREE_LONGITUDE: f64 = SECONDS_PER_DAY / 360.0;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+const J2000_JULIAN_DAY: f64 = 2451545.0;
+
+// The position is calculated several times a frame, so an invalid one is only warned about once
+static WARNED_INVALID_POSITION: AtomicBool = AtomicBool::new(false);
+
+// (name, latitude, longitude) for SolarPosition::at_city
+const CITIES: &[(&str, f64, f64)] = &[
+    ("Stockholm", 59.33258, 18.0649),
+    ("London", 51.50735, -0.12776),
+    ("New York", 40.71278, -74.00597),
+    ("Tokyo", 35.67620, 139.65031),
+    ("Sydney", -33.86882, 151.20930),
+    ("Cape Town", -33.92487, 18.42406),
+    ("Rio de Janeiro", -22.90685, -43.17290),
+    ("Singapore", 1.35208, 103.81984),
+    ("Reykjavik", 64.14660, -21.94264),
+    ("Longyearbyen", 78.22317, 15.62672),
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DayPhase {
+    Night,
+    Dawn,
+    Day,
+    Dusk,
+}
 
 pub struct SolarPosition {
     // in degrees [-90.0, 90.0] where positive is north, negative is south
@@ -8,24 +46,154 @@ pub struct SolarPosition {
     pub longitude: f64,
     pub simulation_seconds_per_second: f64,
     pub now: DateTime<Utc>,
+    // while paused, tick does not advance time
+    pub paused: bool,
 }
 
 impl SolarPosition {
+    /// The position of one of a few built-in cities, ignoring case, such as "Stockholm", "London",
+    /// "New York", "Tokyo" or "Sydney"
+    pub fn at_city(name: &str) -> Option<Self> {
+        CITIES
+            .iter()
+            .find(|(city, _, _)| city.eq_ignore_ascii_case(name))
+            .map(|(_, latitude, longitude)| Self {
+                latitude: *latitude,
+                longitude: *longitude,
+                ..Default::default()
+            })
+    }
+
+    /// Sets the time scale so that a whole day passes in `real_seconds`
+    pub fn with_day_length(mut self, real_seconds: f64) -> Self {
+        self.set_time_scale(SECONDS_PER_DAY / real_seconds);
+        self
+    }
+
+    pub fn set_datetime(&mut self, now: DateTime<Utc>) {
+        self.now = now;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Simulated seconds per real second
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.simulation_seconds_per_second = scale;
+    }
+
     pub fn tick(&mut self, t: f64) {
+        if self.paused {
+            return;
+        }
         self.now = self.now
             + Duration::nanoseconds(
                 (t * 1_000_000_000f64 * self.simulation_seconds_per_second) as i64,
             );
     }
 
+    /// The azimuth and inclination of the sun in degrees. If they cannot be calculated, such as
+    /// for a latitude or longitude out of range, the sun is straight down so that it is night.
     pub fn get_azimuth_inclination(&self) -> (f64, f64) {
-        let SolarPos {
-            azimuth,
-            zenith_angle,
-        } = calc_solar_position(self.now, self.latitude, self.longitude).unwrap();
-        let inclination = 90.0 - zenith_angle;
+        self.azimuth_inclination_at(self.now)
+    }
+
+    fn azimuth_inclination_at(&self, time: DateTime<Utc>) -> (f64, f64) {
+        match calc_solar_position(time, self.latitude, self.longitude) {
+            Ok(SolarPos {
+                azimuth,
+                zenith_angle,
+            }) => (azimuth, 90.0 - zenith_angle),
+            Err(error) => {
+                if !WARNED_INVALID_POSITION.swap(true, Ordering::Relaxed) {
+                    warn!(
+                        "Cannot calculate the solar position at {} for latitude {} and longitude {}: {:?}",
+                        time, self.latitude, self.longitude, error
+                    );
+                }
+                (0.0, -90.0)
+            }
+        }
+    }
+
+    /// The lowest and highest inclination of the sun in degrees over the current day, at local
+    /// solar midnight and noon
+    pub fn inclination_range(&self) -> (f64, f64) {
+        let noon = self.now.date().and_hms(12, 0, 0)
+            - Duration::seconds((self.longitude * SECONDS_PER_DEGREE_LONGITUDE) as i64);
+        let (_, highest) = self.azimuth_inclination_at(noon);
+        let (_, lowest) = self.azimuth_inclination_at(noon + Duration::hours(12));
+        (lowest, highest)
+    }
+
+    /// Whether the sun stays above the horizon all day
+    pub fn is_polar_day(&self) -> bool {
+        self.inclination_range().0 >= 0.0
+    }
+
+    /// Whether the sun stays below the horizon all day
+    pub fn is_polar_night(&self) -> bool {
+        self.inclination_range().1 < 0.0
+    }
+
+    /// The inclination in degrees that the sky and lighting should follow. It is that of the sun
+    /// except in polar day, when it is kept at or above full daylight while the sun skims the
+    /// horizon, and in polar night, when it is kept at or below the end of twilight so that the
+    /// moon and stars stay out rather than the sky hanging in a sunset all day.
+    pub fn lighting_inclination(&self) -> f64 {
+        let (_, inclination) = self.get_azimuth_inclination();
+        let (lowest, highest) = self.inclination_range();
+        if lowest >= 0.0 {
+            inclination.max(DAY_ELEVATION)
+        } else if highest < 0.0 {
+            inclination.min(TWILIGHT_ELEVATION)
+        } else {
+            inclination
+        }
+    }
+
+    /// The local sidereal time in degrees [0.0, 360.0), i.e. the right ascension that is on the
+    /// meridian, which is how far the stars have turned about the celestial pole
+    pub fn local_sidereal_time(&self) -> f64 {
+        let julian_day = (self.now.timestamp() as f64
+            + self.now.timestamp_subsec_nanos() as f64 * 1e-9)
+            / SECONDS_PER_DAY
+            + UNIX_EPOCH_JULIAN_DAY;
+        // Greenwich mean sidereal time, good to within a second or so over centuries
+        let greenwich = 280.46061837 + 360.98564736629 * (julian_day - J2000_JULIAN_DAY);
+        (greenwich + self.longitude).rem_euclid(360.0)
+    }
 
-        (azimuth, inclination)
+    pub fn phase(&self) -> DayPhase {
+        let (azimuth, _) = self.get_azimuth_inclination();
+        let inclination = self.lighting_inclination();
+        if inclination < TWILIGHT_ELEVATION {
+            DayPhase::Night
+        } else if inclination >= DAY_ELEVATION {
+            DayPhase::Day
+        } else if azimuth < 180.0 {
+            // The azimuth is measured eastward from north, so the sun is in the east in the morning
+            DayPhase::Dawn
+        } else {
+            DayPhase::Dusk
+        }
+    }
+
+    /// The colour of sunlight, warming towards orange as the sun nears the horizon
+    pub fn sun_color(&self) -> Color {
+        let (_, inclination) = self.get_azimuth_inclination();
+        let t = (inclination / WHITE_SUN_ELEVATION).max(0.0).min(1.0) as f32;
+        let (horizon, white) = ([1.0, 0.5, 0.2], [1.0, 0.96, 0.9]);
+        Color::rgb(
+            horizon[0] + (white[0] - horizon[0]) * t,
+            horizon[1] + (white[1] - horizon[1]) * t,
+            horizon[2] + (white[2] - horizon[2]) * t,
+        )
     }
 }
 
@@ -36,6 +204,134 @@ impl Default for SolarPosition {
             longitude: 0.0,
             simulation_seconds_per_second: 1.0,
             now: Utc::now(),
+            paused: false,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // On an equinox at the equator the sun rises at about 06:00 and sets at about 18:00 UTC
+    fn equator_at(hour: u32, minute: u32) -> SolarPosition {
+        SolarPosition {
+            now: Utc.ymd(2021, 3, 20).and_hms(hour, minute, 0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn phases_follow_the_sun() {
+        assert_eq!(equator_at(3, 0).phase(), DayPhase::Night);
+        assert_eq!(equator_at(6, 0).phase(), DayPhase::Dawn);
+        assert_eq!(equator_at(9, 0).phase(), DayPhase::Day);
+        assert_eq!(equator_at(15, 0).phase(), DayPhase::Day);
+        assert_eq!(equator_at(18, 0).phase(), DayPhase::Dusk);
+        assert_eq!(equator_at(21, 0).phase(), DayPhase::Night);
+    }
+
+    #[test]
+    fn phase_boundaries_are_at_the_elevation_thresholds() {
+        // Step through the morning a minute at a time and check each phase against the elevation
+        for minute in 0..(4 * 60) {
+            let solar_position = equator_at(4 + minute / 60, minute % 60);
+            let (_, inclination) = solar_position.get_azimuth_inclination();
+            let expected = if inclination < TWILIGHT_ELEVATION {
+                DayPhase::Night
+            } else if inclination < DAY_ELEVATION {
+                DayPhase::Dawn
+            } else {
+                DayPhase::Day
+            };
+            assert_eq!(
+                solar_position.phase(),
+                expected,
+                "at inclination {}",
+                inclination
+            );
+        }
+    }
+
+    #[test]
+    fn sun_is_warmer_near_the_horizon() {
+        let noon = equator_at(12, 0).sun_color();
+        let dusk = equator_at(18, 0).sun_color();
+        assert!(dusk.b() < noon.b());
+        assert!(dusk.g() < noon.g());
+    }
+
+    #[test]
+    fn cities_are_found_ignoring_case() {
+        let stockholm = SolarPosition::at_city("stockholm").unwrap();
+        assert_eq!(
+            (stockholm.latitude, stockholm.longitude),
+            (59.33258, 18.0649)
+        );
+        assert!(SolarPosition::at_city("New York").is_some());
+        assert!(SolarPosition::at_city("Atlantis").is_none());
+    }
+
+    #[test]
+    fn a_sixty_second_day_advances_a_day_a_minute() {
+        let start = Utc.ymd(2021, 3, 20).and_hms(0, 0, 0);
+        let mut solar_position = SolarPosition::default().with_day_length(60.0);
+        solar_position.set_datetime(start);
+        solar_position.tick(60.0);
+        assert_eq!(solar_position.now, start + Duration::days(1));
+        solar_position.tick(15.0);
+        assert_eq!(solar_position.now, start + Duration::hours(30));
+    }
+
+    #[test]
+    fn paused_time_does_not_advance() {
+        let start = Utc.ymd(2021, 3, 20).and_hms(0, 0, 0);
+        let mut solar_position = SolarPosition::default().with_day_length(60.0);
+        solar_position.set_datetime(start);
+        solar_position.pause();
+        solar_position.tick(60.0);
+        assert_eq!(solar_position.now, start);
+    }
+
+    fn longyearbyen_at(month: u32, day: u32, hour: u32) -> SolarPosition {
+        SolarPosition {
+            now: Utc.ymd(2021, month, day).and_hms(hour, 0, 0),
+            ..SolarPosition::at_city("Longyearbyen").unwrap()
+        }
+    }
+
+    #[test]
+    fn polar_winter_is_night_all_day() {
+        for hour in 0..24 {
+            let solar_position = longyearbyen_at(12, 21, hour);
+            let (azimuth, inclination) = solar_position.get_azimuth_inclination();
+            assert!(azimuth.is_finite() && inclination.is_finite());
+            assert!(inclination < 0.0);
+            assert!(solar_position.is_polar_night());
+            assert!(!solar_position.is_polar_day());
+            assert!(solar_position.lighting_inclination() <= TWILIGHT_ELEVATION);
+            assert_eq!(solar_position.phase(), DayPhase::Night);
+        }
+    }
+
+    #[test]
+    fn polar_summer_is_day_all_day() {
+        for hour in 0..24 {
+            let solar_position = longyearbyen_at(6, 21, hour);
+            assert!(solar_position.is_polar_day());
+            assert!(solar_position.lighting_inclination() >= DAY_ELEVATION);
+            assert_eq!(solar_position.phase(), DayPhase::Day);
+        }
+    }
+
+    #[test]
+    fn invalid_positions_fall_back_to_night() {
+        let solar_position = SolarPosition {
+            latitude: 100.0,
+            now: Utc.ymd(2021, 6, 21).and_hms(12, 0, 0),
+            ..Default::default()
+        };
+        assert_eq!(solar_position.get_azimuth_inclination(), (0.0, -90.0));
+        assert_eq!(solar_position.phase(), DayPhase::Night);
+    }
+}
@@ -1,6 +1,9 @@
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum AppState {
+    MainMenu,
     Loading,
     Preparing,
     Running,
+    /// Pushed on top of `Running`, so the world is kept but its systems do not run
+    Paused,
 }
@@ -0,0 +1,393 @@
+//! Flies a scripted camera through the world without a window and prints chunk generation and
+//! meshing throughput, to compare the streaming pipeline across commits.
+//!
+//! `cargo run --release --bin bench -- [frames]`
+//!
+//! The colliders mode instead drops cubes onto the collider of one lod0 chunk and prints physics
+//! frame times, to compare the collider modes.
+//!
+//! `cargo run --release --bin bench -- colliders <trimesh|boxes> [frames]`
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    asset::AssetPlugin,
+    core::CorePlugin,
+    diagnostic::{DiagnosticId, Diagnostics, DiagnosticsPlugin},
+    input::InputPlugin,
+    prelude::*,
+    render::{
+        camera::{Camera, CameraProjection, PerspectiveProjection},
+        pipeline::RenderPipelines,
+    },
+    tasks::ComputeTaskPool,
+    transform::TransformPlugin,
+};
+use bevy_prototype_character_controller::controller::CameraTag;
+use bevy_rapier3d::{
+    physics::TimestepMode,
+    prelude::{
+        ColliderBundle, ColliderShape, NoUserData, RapierConfiguration, RapierPhysicsPlugin,
+        RigidBodyBundle, RigidBodyType, RigidBodyVelocity,
+    },
+};
+use building_blocks::core::prelude::*;
+use minkraft::{
+    app_state::AppState,
+    chunk_generator::{ChunkGenerator, TerrainGenerator},
+    frame_budget::FrameBudget,
+    level_of_detail::{level_of_detail_system, LodState},
+    material_registry::MaterialRegistry,
+    mesh_generator::{
+        lod0_chunk_collider, mesh_generator_system, ArrayTextureMaterial, ArrayTexturePipelines,
+        ChunkMeshes, ColliderMode, MeshCommandQueue, MeshConfig,
+    },
+    terrain_diagnostics::TerrainDiagnosticsPlugin,
+    voxel_map::{VoxelMap, VoxelMapConfig, VoxelMapPlugin},
+    world_seed::WorldSeed,
+};
+
+const DEFAULT_FRAMES: u32 = 600;
+const START: [f32; 3] = [0.0, 640.0, 0.0];
+// Voxels per frame, so that the path does not depend on how long frames take
+const VELOCITY: [f32; 3] = [4.0, 0.0, 1.0];
+// The budget adapts to frame times by default, which would make runs incomparable
+const CREATIONS_PER_THREAD: usize = 40;
+// The cubes of the colliders mode, dropped in a grid over the chunk
+const NUM_CUBES: i32 = 100;
+const CUBES_PER_ROW: i32 = 10;
+// Cubes slower than this in metres per second are counted as resting at the end
+const REST_SPEED: f32 = 0.1;
+
+struct BenchConfig {
+    frames: u32,
+}
+
+#[derive(Default)]
+struct BenchStats {
+    frames: u32,
+    frame_times: Vec<Duration>,
+    chunks_generated: f64,
+    meshes_created: f64,
+    // Milliseconds
+    mesh_spawn_times: Vec<f64>,
+}
+
+struct FallingCube;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parse_frames = |arg: Option<&String>| {
+        arg.map(|arg| {
+            arg.parse()
+                .expect("The number of frames must be an integer")
+        })
+        .unwrap_or(DEFAULT_FRAMES)
+    };
+    if args.first().map(String::as_str) == Some("colliders") {
+        let collider_mode = match args.get(1).map(String::as_str) {
+            Some("trimesh") => ColliderMode::Trimesh,
+            Some("boxes") => ColliderMode::MergedBoxes,
+            _ => panic!("The colliders mode needs a collider mode of trimesh or boxes"),
+        };
+        run_collider_bench(collider_mode, parse_frames(args.get(2)));
+    } else {
+        run_streaming_bench(parse_frames(args.first()));
+    }
+}
+
+fn run_streaming_bench(frames: u32) {
+    App::build()
+        .insert_resource(BenchConfig { frames })
+        .init_resource::<BenchStats>()
+        .add_plugin(CorePlugin::default())
+        .add_plugin(TransformPlugin::default())
+        .add_plugin(InputPlugin::default())
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(DiagnosticsPlugin::default())
+        .add_plugin(ScheduleRunnerPlugin::default())
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .add_state(AppState::Preparing)
+        .add_plugin(TerrainDiagnosticsPlugin::default())
+        .add_plugin(VoxelMapPlugin)
+        // NOTE: These must come after VoxelMapPlugin to override its defaults
+        .insert_resource(WorldSeed::default())
+        .insert_resource(VoxelMapConfig::default())
+        .insert_resource(FrameBudget {
+            min_creations_per_thread: CREATIONS_PER_THREAD,
+            max_creations_per_thread: CREATIONS_PER_THREAD,
+            ..Default::default()
+        })
+        .add_startup_system(setup.system())
+        .add_system_set(
+            SystemSet::on_enter(AppState::Preparing).with_system(
+                level_of_detail_system
+                    .system()
+                    .label("level_of_detail_system"),
+            ),
+        )
+        .add_system_set(
+            SystemSet::on_enter(AppState::Preparing).with_system(
+                mesh_generator_system
+                    .system()
+                    .label("mesh_generator_system")
+                    .after("level_of_detail_system"),
+            ),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::Running).with_system(camera_path_system.system()),
+        )
+        .add_system_to_stage(CoreStage::Last, bench_stats_system.system())
+        .run();
+}
+
+fn setup(
+    mut commands: Commands,
+    pool: Res<ComputeTaskPool>,
+    terrain_generator: Res<TerrainGenerator>,
+    world_seed: Res<WorldSeed>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    mesh_commands: ResMut<MeshCommandQueue>,
+) {
+    println!(
+        "Benchmarking with seed {} on {} compute threads",
+        world_seed.0,
+        pool.thread_num()
+    );
+    let start = Vec3::from(START);
+    let init_lod0_center = PointN(START).in_voxel() >> voxel_map_config.chunk_log2;
+    let map = VoxelMap::new(
+        &pool,
+        &voxel_map_config,
+        &*terrain_generator,
+        *world_seed,
+        mesh_commands,
+        init_lod0_center,
+    );
+    commands.insert_resource(LodState::new(init_lod0_center));
+    commands.insert_resource(map);
+    commands.insert_resource(ChunkMeshes::default());
+    commands.insert_resource(ArrayTexturePipelines(RenderPipelines::default()));
+    commands.insert_resource(ArrayTextureMaterial(Handle::default()));
+
+    let projection = PerspectiveProjection {
+        far: 5000.0,
+        aspect_ratio: 16.0 / 9.0,
+        ..Default::default()
+    };
+    commands.spawn_bundle((
+        Camera {
+            projection_matrix: projection.get_projection_matrix(),
+            ..Default::default()
+        },
+        Transform::from_translation(start).looking_at(start + Vec3::from(VELOCITY), Vec3::Y),
+        GlobalTransform::identity(),
+        CameraTag,
+    ));
+}
+
+fn camera_path_system(mut cameras: Query<&mut Transform, With<CameraTag>>) {
+    for mut transform in cameras.iter_mut() {
+        transform.translation += Vec3::from(VELOCITY);
+    }
+}
+
+fn bench_stats_system(
+    config: Res<BenchConfig>,
+    diagnostics: Res<Diagnostics>,
+    mut stats: ResMut<BenchStats>,
+    mut last_frame: Local<Option<Instant>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let now = Instant::now();
+    if let Some(last_frame) = last_frame.replace(now) {
+        stats.frame_times.push(now - last_frame);
+    }
+    let latest = |id: DiagnosticId| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.value())
+            .unwrap_or(0.0)
+    };
+    stats.chunks_generated += latest(TerrainDiagnosticsPlugin::CHUNKS_GENERATED);
+    stats.meshes_created += latest(TerrainDiagnosticsPlugin::MESHES_CREATED);
+    stats
+        .mesh_spawn_times
+        .push(latest(TerrainDiagnosticsPlugin::MESH_SPAWN_TIME));
+    stats.frames += 1;
+    if stats.frames < config.frames {
+        return;
+    }
+
+    let total = print_frame_times(&stats);
+    println!(
+        "Chunks generated: {} ({:.1}/s)",
+        stats.chunks_generated,
+        stats.chunks_generated / total.as_secs_f64()
+    );
+    println!(
+        "Meshes created: {} ({:.1}/s)",
+        stats.meshes_created,
+        stats.meshes_created / total.as_secs_f64()
+    );
+    // The main thread part of meshing, which stalls frames when many meshes arrive at once
+    let spawn_total: f64 = stats.mesh_spawn_times.iter().sum();
+    let spawn_max = stats.mesh_spawn_times.iter().cloned().fold(0.0, f64::max);
+    println!(
+        "Mesh entity spawning: total {:.3}ms, mean {:.3}ms, max {:.3}ms",
+        spawn_total,
+        spawn_total / stats.mesh_spawn_times.len().max(1) as f64,
+        spawn_max
+    );
+    println!(
+        "Pending at exit: {} chunk commands, {} mesh commands",
+        latest(TerrainDiagnosticsPlugin::PENDING_CHUNK_COMMANDS),
+        latest(TerrainDiagnosticsPlugin::PENDING_MESH_COMMANDS)
+    );
+    app_exit_events.send(AppExit);
+}
+
+// Prints the frame count and frame time statistics, returning the total time
+fn print_frame_times(stats: &BenchStats) -> Duration {
+    let total: Duration = stats.frame_times.iter().sum();
+    let mut sorted = stats.frame_times.clone();
+    sorted.sort();
+    let percentile = |p: f64| {
+        sorted
+            .get(((sorted.len() as f64 - 1.0) * p) as usize)
+            .copied()
+            .unwrap_or_default()
+    };
+    println!("Frames: {}", stats.frames);
+    println!("Total time: {:.3}s", total.as_secs_f64());
+    println!(
+        "Frame time: mean {:.3}ms, median {:.3}ms, p99 {:.3}ms, max {:.3}ms",
+        1000.0 * total.as_secs_f64() / sorted.len().max(1) as f64,
+        1000.0 * percentile(0.5).as_secs_f64(),
+        1000.0 * percentile(0.99).as_secs_f64(),
+        1000.0 * percentile(1.0).as_secs_f64(),
+    );
+    total
+}
+
+fn run_collider_bench(collider_mode: ColliderMode, frames: u32) {
+    App::build()
+        .insert_resource(BenchConfig { frames })
+        .insert_resource(collider_mode)
+        .init_resource::<BenchStats>()
+        .add_plugin(CorePlugin::default())
+        .add_plugin(TransformPlugin::default())
+        .add_plugin(ScheduleRunnerPlugin::default())
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        // NOTE: This must come after the plugin to override its defaults. One fixed step per frame
+        // so that the frame times are those of the same simulation for both modes.
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::FixedTimestep,
+            ..Default::default()
+        })
+        .add_startup_system(collider_bench_setup.system())
+        .add_system_to_stage(CoreStage::Last, collider_bench_stats_system.system())
+        .run();
+}
+
+// Spawns the collider of the lod0 chunk at the surface in the middle of the column at the origin,
+// and a grid of cubes above it
+fn collider_bench_setup(mut commands: Commands, collider_mode: Res<ColliderMode>) {
+    let voxel_map_config = VoxelMapConfig::default();
+    let world_seed = WorldSeed::default();
+    let mut voxel_map = VoxelMap::empty(&voxel_map_config);
+    for (voxel_key, chunk) in TerrainGenerator::default()
+        .generate(Point3i::ZERO, world_seed, &voxel_map_config)
+        .into_iter()
+    {
+        voxel_map.pyramid.level_mut(0).write_chunk(voxel_key, chunk);
+    }
+    let size = voxel_map_config.chunk_shape.x();
+    let surface_y = voxel_map
+        .surface_height(size / 2, size / 2)
+        .expect("The column at the origin has no surface");
+    let chunk_key = voxel_map
+        .pyramid
+        .level(0)
+        .indexer
+        .min_of_chunk_containing_point(PointN([size / 2, surface_y, size / 2]));
+
+    let mesh_config = MeshConfig {
+        collider_mode: *collider_mode,
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let shape = lod0_chunk_collider(
+        &voxel_map,
+        chunk_key,
+        &mesh_config,
+        &MaterialRegistry::default(),
+    )
+    .expect("The surface chunk has no collider");
+    println!(
+        "Benchmarking {:?} colliders with seed {}, built in {:.3}ms",
+        *collider_mode,
+        world_seed.0,
+        1000.0 * start.elapsed().as_secs_f64()
+    );
+    commands
+        .spawn()
+        .insert_bundle(RigidBodyBundle {
+            body_type: RigidBodyType::Static,
+            ..Default::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape,
+            ..Default::default()
+        });
+
+    // One cube over each cell of a grid over the chunk, at staggered heights
+    let spacing = size as f32 / CUBES_PER_ROW as f32;
+    for i in 0..NUM_CUBES {
+        let (row, column) = (i / CUBES_PER_ROW, i % CUBES_PER_ROW);
+        let position = Vec3::new(
+            chunk_key.x() as f32 + (column as f32 + 0.5) * spacing,
+            (chunk_key.y() + size) as f32 + 2.0 + (i % 3) as f32,
+            chunk_key.z() as f32 + (row as f32 + 0.5) * spacing,
+        );
+        commands
+            .spawn()
+            .insert_bundle(RigidBodyBundle {
+                position: position.into(),
+                ..Default::default()
+            })
+            .insert_bundle(ColliderBundle {
+                shape: ColliderShape::cuboid(0.4, 0.4, 0.4),
+                ..Default::default()
+            })
+            .insert(FallingCube);
+    }
+}
+
+fn collider_bench_stats_system(
+    config: Res<BenchConfig>,
+    mut stats: ResMut<BenchStats>,
+    cubes: Query<&RigidBodyVelocity, With<FallingCube>>,
+    mut last_frame: Local<Option<Instant>>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let now = Instant::now();
+    if let Some(last_frame) = last_frame.replace(now) {
+        stats.frame_times.push(now - last_frame);
+    }
+    stats.frames += 1;
+    if stats.frames < config.frames {
+        return;
+    }
+
+    print_frame_times(&stats);
+    // Cubes that missed the chunk's voxels are still falling
+    let resting = cubes
+        .iter()
+        .filter(|velocity| velocity.linvel.norm() < REST_SPEED)
+        .count();
+    println!("Cubes resting at exit: {} of {}", resting, NUM_CUBES);
+    app_exit_events.send(AppExit);
+}
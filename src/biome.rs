@@ -0,0 +1,174 @@
+use crate::voxel_map::Voxel;
+
+/// A named set of height to material breakpoints that applies where the biome noise is nearest
+/// to `biome_value`. The built-in palettes are spread evenly around zero, from cold to hot:
+/// tundra, forest, temperate and desert.
+#[derive(Clone, Debug)]
+pub struct BiomePalette {
+    pub name: &'static str,
+    pub biome_value: f32,
+    // Sorted (noise height, material) pairs where a voxel below a noise height gets its material.
+    // Voxels above the last noise height get the last material.
+    material_breakpoints: Vec<(f32, Voxel)>,
+}
+
+impl BiomePalette {
+    /// The noise heights of the material breakpoints must be strictly increasing.
+    pub fn new(
+        name: &'static str,
+        biome_value: f32,
+        material_breakpoints: Vec<(f32, Voxel)>,
+    ) -> Self {
+        assert!(
+            !material_breakpoints.is_empty(),
+            "At least one material breakpoint is required"
+        );
+        assert!(
+            material_breakpoints
+                .windows(2)
+                .all(|pair| pair[0].0 < pair[1].0),
+            "Material breakpoint heights must be strictly increasing: {:?}",
+            material_breakpoints
+        );
+        assert!(
+            material_breakpoints
+                .iter()
+                .all(|(_, material)| *material == Voxel::EMPTY
+                    || Voxel::MATERIALS.contains(material)),
+            "Material breakpoints must use materials from Voxel::MATERIALS: {:?}",
+            material_breakpoints
+        );
+        Self {
+            name,
+            biome_value,
+            material_breakpoints,
+        }
+    }
+
+    pub fn material_breakpoints(&self) -> &[(f32, Voxel)] {
+        &self.material_breakpoints
+    }
+
+    pub fn material(&self, noise_height: f32) -> Voxel {
+        self.material_breakpoints
+            .iter()
+            .find(|(height, _)| noise_height < *height)
+            .or_else(|| self.material_breakpoints.last())
+            .map(|(_, material)| *material)
+            .unwrap_or(Voxel::EMPTY)
+    }
+
+    pub fn temperate() -> Self {
+        Self::new(
+            "temperate",
+            0.02,
+            vec![
+                (4.52, Voxel::WATER),
+                (4.54, Voxel::SAND),
+                (4.55, Voxel::DIRT),
+                (4.7, Voxel::GRASS),
+                (f32::INFINITY, Voxel::STONE),
+            ],
+        )
+    }
+
+    pub fn desert() -> Self {
+        Self::new(
+            "desert",
+            0.06,
+            vec![
+                (4.52, Voxel::WATER),
+                (4.75, Voxel::SAND),
+                (f32::INFINITY, Voxel::STONE),
+            ],
+        )
+    }
+
+    pub fn forest() -> Self {
+        Self::new(
+            "forest",
+            -0.02,
+            vec![
+                (4.52, Voxel::WATER),
+                (4.53, Voxel::SAND),
+                (4.54, Voxel::DIRT),
+                (4.78, Voxel::GRASS),
+                (f32::INFINITY, Voxel::STONE),
+            ],
+        )
+    }
+
+    pub fn tundra() -> Self {
+        Self::new(
+            "tundra",
+            -0.06,
+            vec![
+                (4.52, Voxel::WATER),
+                (4.54, Voxel::GRAVEL),
+                (4.56, Voxel::DIRT),
+                (4.62, Voxel::STONE),
+                (4.7, Voxel::ICE),
+                (f32::INFINITY, Voxel::SNOW),
+            ],
+        )
+    }
+}
+
+/// Picks the material from the biome whose `biome_value` is nearest to the sampled `biome_value`.
+pub fn biome_material(biomes: &[BiomePalette], biome_value: f32, noise_height: f32) -> Voxel {
+    biomes
+        .iter()
+        .min_by(|a, b| {
+            (a.biome_value - biome_value)
+                .abs()
+                .partial_cmp(&(b.biome_value - biome_value).abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|biome| biome.material(noise_height))
+        .unwrap_or(Voxel::EMPTY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palettes() -> Vec<BiomePalette> {
+        vec![
+            BiomePalette::tundra(),
+            BiomePalette::forest(),
+            BiomePalette::temperate(),
+            BiomePalette::desert(),
+        ]
+    }
+
+    #[test]
+    fn built_in_palettes_have_distinct_biome_values() {
+        let palettes = palettes();
+        for (i, a) in palettes.iter().enumerate() {
+            for b in palettes[i + 1..].iter() {
+                assert!(
+                    a.biome_value < b.biome_value,
+                    "{} and {} are not in order from cold to hot",
+                    a.name,
+                    b.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn each_palette_is_picked_at_its_biome_value() {
+        let palettes = palettes();
+        // Where every palette has a different material
+        let noise_height = 4.75;
+        for palette in palettes.iter() {
+            assert_eq!(
+                biome_material(&palettes, palette.biome_value, noise_height),
+                palette.material(noise_height),
+                "{}",
+                palette.name
+            );
+        }
+        assert_eq!(biome_material(&[], 0.0, noise_height), Voxel::EMPTY);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::voxel_map::{Voxel, VoxelMap};
+
+use building_blocks::{prelude::*, storage::LodChunkKey3};
+
+/// A chunk run-length encoded along x. Runs never cross from one x row to the next, so a run
+/// length is at most the chunk width.
+#[derive(Clone, Debug)]
+pub struct CompressedChunk {
+    extent: Extent3i,
+    // (length, voxel) pairs in Array3x1 iteration order: x fastest, then y, then z
+    runs: Vec<(u16, Voxel)>,
+}
+
+impl CompressedChunk {
+    /// Returns `None` if the run lengths do not cover `extent` exactly.
+    pub fn from_runs(extent: Extent3i, runs: Vec<(u16, Voxel)>) -> Option<Self> {
+        let num_voxels: u64 = runs.iter().map(|(length, _)| *length as u64).sum();
+        if num_voxels == extent.num_points() as u64 {
+            Some(Self { extent, runs })
+        } else {
+            None
+        }
+    }
+
+    pub fn extent(&self) -> Extent3i {
+        self.extent
+    }
+
+    pub fn runs(&self) -> &[(u16, Voxel)] {
+        &self.runs
+    }
+
+    /// The memory used by the runs, to compare with the voxels of the uncompressed chunk
+    pub fn size_in_bytes(&self) -> usize {
+        self.runs.len() * std::mem::size_of::<(u16, Voxel)>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.iter().all(|(_, voxel)| *voxel == Voxel::EMPTY)
+    }
+
+    pub fn decompress(&self) -> Array3x1<Voxel> {
+        let mut chunk = Array3x1::fill(self.extent, Voxel::EMPTY);
+        let mut voxels = self
+            .runs
+            .iter()
+            .flat_map(|(length, voxel)| std::iter::repeat(*voxel).take(*length as usize));
+        chunk.for_each_mut(&self.extent, |_p: Point3i, voxel: &mut Voxel| {
+            *voxel = voxels.next().unwrap_or(Voxel::EMPTY);
+        });
+        chunk
+    }
+}
+
+pub fn compress_chunk(chunk: &Array3x1<Voxel>) -> CompressedChunk {
+    let extent = *chunk.extent();
+    assert!(
+        extent.shape.x() <= u16::MAX as i32,
+        "Chunk rows are too wide to run-length encode: {:?}",
+        extent.shape
+    );
+    let row_start = extent.minimum.x();
+    let mut runs: Vec<(u16, Voxel)> = Vec::new();
+    chunk.for_each(&extent, |p: Point3i, voxel: Voxel| match runs.last_mut() {
+        Some((length, run_voxel)) if p.x() != row_start && *run_voxel == voxel => *length += 1,
+        _ => runs.push((1, voxel)),
+    });
+    CompressedChunk { extent, runs }
+}
+
+impl VoxelMap {
+    /// Moves every chunk at `min_lod` and above that lies in `voxel_extent` (in lod0 voxel
+    /// coordinates) out of the pyramid and into compressed storage.
+    pub fn compress_extent(&mut self, voxel_extent: &Extent3i, min_lod: u8) {
+        for lod in min_lod.max(1)..self.pyramid.num_levels() {
+            let level = self.pyramid.level_mut(lod);
+            let keys: Vec<_> = level
+                .indexer
+                .chunk_keys_for_extent(&level_extent(voxel_extent, lod))
+                .collect();
+            for chunk_key in keys.into_iter() {
+                if let Some(chunk) = level.pop_chunk(chunk_key) {
+                    self.compressed
+                        .insert(LodChunkKey3 { lod, chunk_key }, compress_chunk(&chunk));
+                }
+            }
+        }
+    }
+
+    /// Moves every compressed chunk that lies in `voxel_extent` (in lod0 voxel coordinates) back
+    /// into the pyramid so that it can be downsampled into.
+    pub fn decompress_extent(&mut self, voxel_extent: &Extent3i) {
+        let keys: Vec<_> = self
+            .compressed
+            .keys()
+            .filter(|key| {
+                let chunk_extent = self
+                    .pyramid
+                    .level(key.lod)
+                    .indexer
+                    .extent_for_chunk_at_key(key.chunk_key);
+                !chunk_extent
+                    .intersection(&level_extent(voxel_extent, key.lod))
+                    .is_empty()
+            })
+            .cloned()
+            .collect();
+        for key in keys.into_iter() {
+            if let Some(compressed) = self.compressed.remove(&key) {
+                self.pyramid
+                    .level_mut(key.lod)
+                    .write_chunk(key.chunk_key, compressed.decompress());
+            }
+        }
+    }
+
+    /// Copies the voxels of the chunk at `key` into `dst`, whether the chunk is compressed or not.
+    pub fn copy_chunk(&self, key: LodChunkKey3, dst: &mut Array3x1<Voxel>) {
+        let chunks = self.pyramid.level(key.lod);
+        let chunk_extent = chunks.indexer.extent_for_chunk_at_key(key.chunk_key);
+        if let Some(compressed) = self.compressed.get(&key) {
+            copy_extent(&chunk_extent, &compressed.decompress(), dst);
+        } else {
+            copy_extent(&chunk_extent, chunks, dst);
+        }
+    }
+}
+
+fn level_extent(voxel_extent: &Extent3i, lod: u8) -> Extent3i {
+    Extent3i::from_min_and_max(
+        voxel_extent.minimum >> lod as i32,
+        voxel_extent.max() >> lod as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        voxel_map::{generate_chunk_stack, NoiseConfig, VoxelMapConfig},
+        world_seed::WorldSeed,
+    };
+
+    fn noise_chunks() -> Vec<(Point3i, Array3x1<Voxel>)> {
+        generate_chunk_stack(
+            PointN([1, 0, 2]),
+            WorldSeed::default(),
+            &NoiseConfig::default(),
+            &VoxelMapConfig::default(),
+        )
+    }
+
+    #[test]
+    fn noise_chunks_round_trip() {
+        let chunks = noise_chunks();
+        assert!(!chunks.is_empty());
+        for (_, chunk) in chunks.iter() {
+            let compressed = compress_chunk(chunk);
+            assert_eq!(compressed.extent(), *chunk.extent());
+            assert_eq!(&compressed.decompress(), chunk);
+        }
+    }
+
+    #[test]
+    fn runs_do_not_cross_rows() {
+        let extent = Extent3i::from_min_and_shape(PointN([-2, 3, 5]), PointN([4, 3, 2]));
+        let compressed = compress_chunk(&Array3x1::fill(extent, Voxel::STONE));
+        assert_eq!(compressed.runs(), &[(4, Voxel::STONE); 6][..]);
+        assert!(!compressed.is_empty());
+
+        // Every voxel different from the next is the worst case, one run per voxel
+        let chunk = Array3x1::fill_with(extent, |p: Point3i| Voxel((p.x() + p.y() + p.z()) as u8));
+        let compressed = compress_chunk(&chunk);
+        assert_eq!(compressed.runs().len(), extent.num_points() as usize);
+        assert_eq!(compressed.decompress(), chunk);
+    }
+
+    #[test]
+    fn compressed_noise_chunks_are_smaller() {
+        let chunks = noise_chunks();
+        let uncompressed: usize = chunks
+            .iter()
+            .map(|(_, chunk)| chunk.extent().num_points() as usize * std::mem::size_of::<Voxel>())
+            .sum();
+        let compressed: usize = chunks
+            .iter()
+            .map(|(_, chunk)| compress_chunk(chunk).size_in_bytes())
+            .sum();
+        assert!(
+            compressed * 2 < uncompressed,
+            "{} bytes compressed to {}",
+            uncompressed,
+            compressed
+        );
+    }
+
+    #[test]
+    fn from_runs_checks_the_length() {
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([2; 3]));
+        assert!(CompressedChunk::from_runs(extent, vec![(8, Voxel::DIRT)]).is_some());
+        assert!(CompressedChunk::from_runs(extent, vec![(7, Voxel::DIRT)]).is_none());
+    }
+}
@@ -24,28 +24,171 @@
  *
  */
 
-use crate::voxel_map::{generate_chunk_stack, NoiseConfig, Voxel, VoxelMap, VoxelMapConfig};
+use crate::{
+    decoration::{column_exists, decorate_column},
+    mesh_generator::{MeshCommand, MeshCommandQueue},
+    voxel_map::{generate_chunk_stack, NoiseConfig, Voxel, VoxelMap, VoxelMapConfig},
+    world_seed::WorldSeed,
+};
 
 use bevy_prototype_character_controller::controller::CameraTag;
-use building_blocks::{core::extent::bounding_extent, prelude::*};
+use building_blocks::{
+    core::extent::bounding_extent,
+    prelude::*,
+    storage::{LodChunkKey3, SmallKeyHashSet},
+};
 
-use bevy::{prelude::*, render::camera::Camera, tasks::ComputeTaskPool};
-use std::collections::VecDeque;
+use bevy::{
+    prelude::*,
+    render::{camera::Camera, texture::TextureFormat},
+    tasks::{AsyncComputeTaskPool, ComputeTaskPool},
+};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::{collections::VecDeque, sync::Arc};
 
-fn max_chunk_creations_per_frame(pool: &ComputeTaskPool) -> usize {
-    40 * pool.thread_num()
+/// Generates the voxel data for chunks of the world
+pub trait ChunkGenerator: Send + Sync {
+    /// Returns (minimum voxel key, chunk) pairs for the stack of chunks in the column at the
+    /// given chunk coordinates. The y coordinate of the key is ignored. The output must be a pure
+    /// function of the inputs so that worlds are reproducible from their seed.
+    fn generate(
+        &self,
+        key: Point3i,
+        seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)>;
+
+    /// A copy of this generator that uses `noise_config`, or `None` if it does not use a
+    /// NoiseConfig, in which case changes to the NoiseConfig resource leave it as it is
+    fn with_noise_config(&self, _noise_config: &NoiseConfig) -> Option<Arc<dyn ChunkGenerator>> {
+        None
+    }
+}
+
+/// The ChunkGenerator used to generate the world. Insert this before adding the VoxelMapPlugin to
+/// replace the default ridge noise terrain.
+#[derive(Clone)]
+pub struct TerrainGenerator(pub Arc<dyn ChunkGenerator>);
+
+impl Default for TerrainGenerator {
+    fn default() -> Self {
+        Self(Arc::new(RidgeNoiseGenerator::default()))
+    }
+}
+
+impl ChunkGenerator for TerrainGenerator {
+    fn generate(
+        &self,
+        key: Point3i,
+        seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        let span = info_span!("generate_chunk_stack", chunk_key = ?key);
+        let _guard = span.enter();
+        self.0.generate(key, seed, config)
+    }
+
+    fn with_noise_config(&self, noise_config: &NoiseConfig) -> Option<Arc<dyn ChunkGenerator>> {
+        self.0.with_noise_config(noise_config)
+    }
+}
+
+/// Terrain generated from simdnoise ridge noise, or 3D noise, depending on the NoiseConfig
+#[derive(Default)]
+pub struct RidgeNoiseGenerator {
+    pub noise_config: NoiseConfig,
+}
+
+impl ChunkGenerator for RidgeNoiseGenerator {
+    fn generate(
+        &self,
+        key: Point3i,
+        seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        generate_chunk_stack(key, seed, &self.noise_config, config)
+    }
+
+    fn with_noise_config(&self, noise_config: &NoiseConfig) -> Option<Arc<dyn ChunkGenerator>> {
+        Some(Arc::new(RidgeNoiseGenerator {
+            noise_config: noise_config.clone(),
+        }))
+    }
+}
+
+/// A flat world where everything below `height` is `material`
+pub struct FlatWorldGenerator {
+    pub height: i32,
+    pub material: Voxel,
+}
+
+impl Default for FlatWorldGenerator {
+    fn default() -> Self {
+        Self {
+            height: 0,
+            material: Voxel::GRASS,
+        }
+    }
+}
+
+impl ChunkGenerator for FlatWorldGenerator {
+    fn generate(
+        &self,
+        key: Point3i,
+        _seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        let chunk_min = key * config.chunk_shape;
+        let surface_y_chunk = (self.height - 1) >> config.chunk_log2;
+        ((surface_y_chunk - 1)..=surface_y_chunk)
+            .map(|y_chunk| {
+                let y_chunk_min =
+                    PointN([chunk_min.x(), y_chunk << config.chunk_log2, chunk_min.z()]);
+                let extent = Extent3i::from_min_and_shape(y_chunk_min, config.chunk_shape);
+                let chunk = Array3x1::fill_with(extent, |p: Point3i| {
+                    if p.y() < self.height {
+                        self.material
+                    } else {
+                        Voxel::EMPTY
+                    }
+                });
+                (y_chunk_min, chunk)
+            })
+            .collect()
+    }
 }
 
 #[derive(Default)]
 pub struct ChunkCommandQueue {
     commands: VecDeque<ChunkCommand>,
+    // The columns of the Generate commands in `commands`, so that each is only queued once
+    queued_generates: SmallKeyHashSet<Point3i>,
+    num_generated_last_frame: usize,
 }
 
 impl ChunkCommandQueue {
     pub fn enqueue(&mut self, command: ChunkCommand) {
+        if let ChunkCommand::Generate(chunk_key) = &command {
+            if !self.queued_generates.insert(*chunk_key) {
+                return;
+            }
+        }
         self.commands.push_front(command);
     }
 
+    /// Drops the Generate commands for columns outside of the x and z range of `chunks_extent`
+    pub fn retain_generates_within(&mut self, chunks_extent: &Extent3i) {
+        let (min, max) = (chunks_extent.minimum, chunks_extent.max());
+        let in_range = |key: &Point3i| {
+            key.x() >= min.x() && key.x() <= max.x() && key.z() >= min.z() && key.z() <= max.z()
+        };
+        self.commands.retain(|command| match command {
+            ChunkCommand::Generate(chunk_key) => in_range(chunk_key),
+            _ => true,
+        });
+        self.queued_generates.retain(in_range);
+    }
+
     pub fn is_empty(&self) -> bool {
         self.commands.is_empty()
     }
@@ -53,110 +196,362 @@ impl ChunkCommandQueue {
     pub fn len(&self) -> usize {
         self.commands.len()
     }
+
+    /// The number of chunks written by generate commands in the last run of the chunk generator
+    pub fn num_generated_last_frame(&self) -> usize {
+        self.num_generated_last_frame
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ChunkCommand {
+    /// Generate the stack of chunks in the column at the given chunk coordinates (y is ignored)
     Generate(Point3i),
-    Edit(Point3i, Array3x1<Voxel>),
+    /// Downsample and re-mesh the lod0 chunk with the given minimum voxel key, which has been
+    /// changed or generated in place, e.g. by `VoxelMap::set_voxel`
+    Edit(Point3i),
+    /// Remove the chunk at the given chunk coordinates
     Remove(Point3i),
+    /// Remove the chunk at the given chunk coordinates to free its memory, keeping it if it has
+    /// been edited and `VoxelMapConfig::keep_unloaded_edits` is set
+    Unload(Point3i),
+}
+
+fn grow_extent(extent: &mut Option<Extent3i>, chunk_extent: Extent3i) {
+    if let Some(extent_to_update) = extent.as_mut() {
+        *extent_to_update = bounding_extent(
+            [
+                extent_to_update.minimum,
+                chunk_extent.minimum,
+                extent_to_update.max(),
+                chunk_extent.max(),
+            ]
+            .iter()
+            .cloned(),
+        );
+    } else {
+        *extent = Some(chunk_extent);
+    }
+}
+
+/// Generates chunk columns on the AsyncComputeTaskPool, one task per column, so that the rate of
+/// generation does not depend on the frame rate and does not compete with meshing for the
+/// ComputeTaskPool. chunk_generator_system spawns the columns to generate and writes the finished
+/// ones back into the map as they arrive. The tasks never wait, so they do not hold on to the
+/// pool's threads between columns.
+pub struct ChunkGenerationPipeline {
+    results: Receiver<GenerateResult>,
+    result_sender: Sender<GenerateResult>,
+    // Columns that have been spawned and have not come back yet
+    pending: SmallKeyHashSet<Point3i>,
+    // Bumped when the map is regenerated, so that columns of the old map are dropped
+    epoch: u64,
+}
+
+impl Default for ChunkGenerationPipeline {
+    fn default() -> Self {
+        let (result_sender, results) = unbounded();
+        Self {
+            results,
+            result_sender,
+            pending: SmallKeyHashSet::default(),
+            epoch: 0,
+        }
+    }
+}
+
+// Columns pending per pool thread, enough to keep the threads busy between frames while leaving the
+// rest in the ChunkCommandQueue, where they are still reprioritised as the camera moves
+const MAX_PENDING_PER_THREAD: usize = 4;
+
+struct GenerateResult {
+    key: Point3i,
+    epoch: u64,
+    chunks: Vec<(Point3i, Array3x1<Voxel>)>,
+    // Decoration voxels that fall in other columns
+    spills: Vec<(Point3i, Voxel)>,
 }
 
-/// Generates / removes chunks
+impl ChunkGenerationPipeline {
+    /// Drops the columns that are being generated for the current map
+    pub fn invalidate(&mut self) {
+        self.epoch += 1;
+        self.pending.clear();
+    }
+
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    // Returns false if too many columns are already being generated
+    fn request(
+        &mut self,
+        pool: &AsyncComputeTaskPool,
+        key: Point3i,
+        generator: &TerrainGenerator,
+        seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> bool {
+        if self.pending.contains(&key) {
+            return true;
+        }
+        if self.pending.len() >= MAX_PENDING_PER_THREAD * pool.thread_num().max(1) {
+            return false;
+        }
+        self.pending.insert(key);
+        let epoch = self.epoch;
+        let generator = generator.clone();
+        let config = config.clone();
+        let results = self.result_sender.clone();
+        pool.spawn(async move {
+            let mut chunks = generator.generate(key, seed, &config);
+            let spills = decorate_column(key, &mut chunks, seed, &config);
+            // The result is dropped if the pipeline has been dropped
+            let _ = results.send(GenerateResult {
+                key,
+                epoch,
+                chunks,
+                spills,
+            });
+        })
+        .detach();
+        true
+    }
+
+    // The (column key, chunks, spills) of the columns that have been generated since the last call
+    fn finished(&mut self) -> Vec<GenerateResult> {
+        let mut columns = Vec::new();
+        for result in self.results.try_iter() {
+            if result.epoch == self.epoch {
+                self.pending.remove(&result.key);
+                columns.push(result);
+            }
+        }
+        columns
+    }
+}
+
+/// Writes finished columns and applies edits and removals. Generate commands are handed to the
+/// ChunkGenerationPipeline. A finished column is dropped if any of it has been written since it was
+/// requested, e.g. by an edit that generated the column itself, so that edits are not overwritten.
 pub fn chunk_generator_system(
+    async_pool: Res<AsyncComputeTaskPool>,
     pool: Res<ComputeTaskPool>,
     mut voxel_map: ResMut<VoxelMap>,
     mut chunk_commands: ResMut<ChunkCommandQueue>,
-    noise_config: Res<NoiseConfig>,
+    mut mesh_commands: ResMut<MeshCommandQueue>,
+    mut chunk_generation: ResMut<ChunkGenerationPipeline>,
+    terrain_generator: Res<TerrainGenerator>,
+    world_seed: Res<WorldSeed>,
     voxel_map_config: Res<VoxelMapConfig>,
 ) {
-    let num_chunks_to_generate = chunk_commands
-        .len()
-        .min(max_chunk_creations_per_frame(&pool));
-
-    let mut num_generates = 0;
-    let mut num_edits = 0;
-    let mut num_removes = 0;
-    let mut generated_chunks = pool.scope(|s| {
-        let noise_config = &noise_config;
-        let voxel_map_config = &voxel_map_config;
-        let mut num_chunks_generated = 0;
-        for command in chunk_commands.commands.iter().rev().cloned() {
-            match command {
-                ChunkCommand::Generate(chunk_key) => {
-                    num_chunks_generated += 1;
-                    s.spawn(async move {
-                        generate_chunk_stack(chunk_key, noise_config, voxel_map_config)
-                    });
-                }
-                _ => {}
+    let span = info_span!("chunk_generator_system");
+    let _guard = span.enter();
+
+    let mut num_chunks_written = 0;
+    let mut generated_chunk_extent: Option<Extent3i> = None;
+    let mut removed_chunk_extents = Vec::new();
+    let mut dirty_voxel_keys = Vec::new();
+    {
+        let voxel_map = &mut *voxel_map;
+        for result in chunk_generation.finished().into_iter() {
+            let GenerateResult {
+                key: column_key,
+                mut chunks,
+                spills,
+                ..
+            } = result;
+            if column_exists(
+                voxel_map.pyramid.level(0),
+                column_key,
+                voxel_map_config.chunk_log2,
+            ) {
+                continue;
             }
-            if num_chunks_generated >= num_chunks_to_generate {
-                break;
+            // Unloaded edits are written last so that they win over anything placed since
+            voxel_map.decorations.place_incoming(
+                column_key,
+                &mut chunks,
+                voxel_map_config.chunk_shape,
+            );
+            chunks.extend(voxel_map.take_unloaded_edits(column_key));
+            let lod0 = voxel_map.pyramid.level_mut(0);
+            for (voxel_key, chunk) in chunks.into_iter() {
+                lod0.write_chunk(voxel_key, chunk);
+                num_chunks_written += 1;
+                grow_extent(
+                    &mut generated_chunk_extent,
+                    Extent3i::from_min_and_shape(
+                        voxel_key >> voxel_map_config.chunk_log2,
+                        Point3i::ONES,
+                    ),
+                );
+            }
+            // Trees of this column that reach into its neighbors change chunks that may already
+            // have meshes
+            for voxel_key in voxel_map
+                .decorations
+                .add(lod0, column_key, spills, &*voxel_map_config)
+            {
+                grow_extent(
+                    &mut generated_chunk_extent,
+                    Extent3i::from_min_and_shape(
+                        voxel_key >> voxel_map_config.chunk_log2,
+                        Point3i::ONES,
+                    ),
+                );
+                dirty_voxel_keys.push(voxel_key);
             }
         }
-    });
-    generated_chunks.reverse();
 
-    let mut generated_chunk_extent: Option<Extent3i> = None;
-    {
-        let lod0 = voxel_map.pyramid.level_mut(0);
-        for command in chunk_commands.commands.iter().rev().cloned() {
+        // Oldest first. Columns that cannot be sent yet stay queued in the same order.
+        let mut deferred = VecDeque::new();
+        while let Some(command) = chunk_commands.commands.pop_back() {
             match command {
                 ChunkCommand::Generate(chunk_key) => {
-                    num_generates += 1;
-                    for (voxel_key, chunk) in generated_chunks.pop().unwrap().into_iter() {
-                        lod0.write_chunk(voxel_key, chunk);
-                        let chunk_extent = Extent3i::from_min_and_shape(chunk_key, Point3i::ONES);
-                        if let Some(extent_to_update) = generated_chunk_extent.as_mut() {
-                            *extent_to_update = bounding_extent(
-                                [
-                                    extent_to_update.minimum,
-                                    chunk_extent.minimum,
-                                    extent_to_update.max(),
-                                    chunk_extent.max(),
-                                ]
-                                .iter()
-                                .cloned(),
-                            );
-                        } else {
-                            generated_chunk_extent = Some(chunk_extent);
-                        }
+                    if chunk_generation.request(
+                        &async_pool,
+                        chunk_key,
+                        &*terrain_generator,
+                        *world_seed,
+                        &*voxel_map_config,
+                    ) {
+                        chunk_commands.queued_generates.remove(&chunk_key);
+                    } else {
+                        deferred.push_front(ChunkCommand::Generate(chunk_key));
                     }
                 }
-                ChunkCommand::Edit(chunk_key, chunk) => {
-                    num_edits += 1;
-                    lod0.write_chunk(chunk_key, chunk);
+                ChunkCommand::Edit(voxel_key) => {
+                    grow_extent(
+                        &mut generated_chunk_extent,
+                        Extent3i::from_min_and_shape(
+                            voxel_key >> voxel_map_config.chunk_log2,
+                            Point3i::ONES,
+                        ),
+                    );
+                    dirty_voxel_keys.push(voxel_key);
                 }
                 ChunkCommand::Remove(chunk_key) => {
-                    num_removes += 1;
+                    let voxel_key = chunk_key * voxel_map_config.chunk_shape;
+                    // Removing a chunk that was never generated is a no-op
+                    if voxel_map
+                        .pyramid
+                        .level_mut(0)
+                        .pop_chunk(voxel_key)
+                        .is_some()
+                    {
+                        voxel_map.edited.remove(&voxel_key);
+                        removed_chunk_extents
+                            .push(Extent3i::from_min_and_shape(chunk_key, Point3i::ONES));
+                        dirty_voxel_keys.push(voxel_key);
+                    }
+                }
+                ChunkCommand::Unload(chunk_key) => {
+                    let voxel_key = chunk_key * voxel_map_config.chunk_shape;
+                    if voxel_map.unload_chunk(
+                        voxel_key,
+                        voxel_map_config.chunk_log2,
+                        voxel_map_config.keep_unloaded_edits,
+                    ) {
+                        removed_chunk_extents
+                            .push(Extent3i::from_min_and_shape(chunk_key, Point3i::ONES));
+                        dirty_voxel_keys.push(voxel_key);
+                    }
                 }
-            }
-            if num_generates >= num_chunks_to_generate {
-                break;
             }
         }
+        chunk_commands.commands = deferred;
     }
 
-    if let Some(chunk_extent) = generated_chunk_extent {
+    let mut downsample_chunk_extent = generated_chunk_extent;
+    for chunk_extent in removed_chunk_extents.iter() {
+        grow_extent(&mut downsample_chunk_extent, *chunk_extent);
+    }
+    if let Some(chunk_extent) = downsample_chunk_extent {
         let voxel_extent = chunk_extent * voxel_map_config.chunk_shape;
+        let compressed_min_lod = voxel_map_config.compressed_min_lod;
         pool.scope(|s| {
             let voxel_map = &mut voxel_map;
+            let removed_chunk_extents = &removed_chunk_extents;
             s.spawn(async move {
+                let span = info_span!("downsample_chunks", extent = ?voxel_extent);
+                let _guard = span.enter();
                 let mut index = voxel_map.index.clone();
-                index.superchunk_octrees.add_extent(&chunk_extent);
+                if let Some(generated_chunk_extent) = generated_chunk_extent {
+                    index.superchunk_octrees.add_extent(&generated_chunk_extent);
+                }
+                // Removed chunks are still in the index at this point so that their now-empty
+                // space is downsampled into the higher LODs
+                voxel_map.decompress_extent(&voxel_extent);
                 voxel_map.pyramid.downsample_chunks_with_index(
                     &index,
                     &PointDownsampler,
                     &voxel_extent,
                 );
+                voxel_map.compress_extent(&voxel_extent, compressed_min_lod);
+                for removed_chunk_extent in removed_chunk_extents.iter() {
+                    index
+                        .superchunk_octrees
+                        .subtract_extent(removed_chunk_extent);
+                }
                 voxel_map.index = index;
             });
         });
     }
 
-    let new_length = chunk_commands.len() - (num_generates + num_edits + num_removes);
-    chunk_commands.commands.truncate(new_length);
+    // Edited and removed chunks need re-meshing at every LOD that covers them, as the downsampled
+    // data has changed too. Re-meshing a removed lod0 chunk despawns its entity and collider.
+    // Many edits in one frame, in one chunk or in neighbouring chunks that share a coarser chunk,
+    // result in only one re-mesh per chunk.
+    let mut remesh_keys = SmallKeyHashSet::default();
+    for voxel_key in dirty_voxel_keys.into_iter() {
+        for lod in 0..voxel_map_config.num_lods {
+            let chunk_key = voxel_map
+                .pyramid
+                .level(lod)
+                .indexer
+                .min_of_chunk_containing_point(voxel_key >> lod as i32);
+            remesh_keys.insert(LodChunkKey3 { lod, chunk_key });
+        }
+        // lod0 neighbours may use this chunk's voxels as their padding
+        for offset in [
+            PointN([1, 0, 0]),
+            PointN([-1, 0, 0]),
+            PointN([0, 1, 0]),
+            PointN([0, -1, 0]),
+            PointN([0, 0, 1]),
+            PointN([0, 0, -1]),
+        ]
+        .iter()
+        {
+            remesh_keys.insert(LodChunkKey3 {
+                lod: 0,
+                chunk_key: voxel_key + *offset * voxel_map_config.chunk_shape,
+            });
+        }
+    }
+    for lod_key in remesh_keys.into_iter() {
+        mesh_commands.enqueue(MeshCommand::Remesh(lod_key));
+    }
+
+    chunk_commands.num_generated_last_frame = num_chunks_written;
+}
+
+// Grows the chunk extent by up to `prefetch_chunks` in x and z towards the direction of travel, so
+// that chunks ahead of a fast moving camera are generated before they come into view
+fn prefetch_extent(chunks_extent: Extent3i, travel: Vec3, prefetch_chunks: i32) -> Extent3i {
+    let direction = Vec3::new(travel.x, 0.0, travel.z).normalize_or_zero();
+    let mut minimum = chunks_extent.minimum;
+    let mut max = chunks_extent.max();
+    for (axis, component) in [(0, direction.x), (2, direction.z)].iter() {
+        let grow = (prefetch_chunks as f32 * component).round() as i32;
+        if grow > 0 {
+            max.0[*axis] += grow;
+        } else {
+            minimum.0[*axis] += grow;
+        }
+    }
+    Extent3i::from_min_and_max(minimum, max)
 }
 
 pub fn chunk_detection_system(
@@ -164,24 +559,35 @@ pub fn chunk_detection_system(
     voxel_map: Res<VoxelMap>,
     voxel_map_config: Res<VoxelMapConfig>,
     mut chunk_commands: ResMut<ChunkCommandQueue>,
+    mut last_camera_position: Local<Option<Vec3>>,
 ) {
     let camera_position = if let Some((_camera, tfm)) = cameras.iter().next() {
         tfm.translation
     } else {
         return;
     };
+    let travel = last_camera_position
+        .replace(camera_position)
+        .map(|last| camera_position - last)
+        .unwrap_or(Vec3::ZERO);
 
-    let mut camera_center =
-        Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
-    *camera_center.y_mut() = 0;
-    let visible_extent = voxel_map_config.visible_chunks_extent + camera_center;
+    // Columns are generated whole, from the bottom to the top of the terrain, so only the x and z
+    // of the camera matter here
+    let camera_center = Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
+    let generation_extent = prefetch_extent(
+        voxel_map_config.generation_chunks_extent(camera_center),
+        travel,
+        voxel_map_config.prefetch_chunks,
+    );
+    // Columns that the camera has moved away from before they were generated are not needed
+    chunk_commands.retain_generates_within(&generation_extent);
 
     let lod0 = voxel_map.pyramid.level(0);
     let lod0_voxel_extent = lod0.bounding_extent();
     let min_y = lod0_voxel_extent.minimum.y() >> voxel_map_config.chunk_log2;
     let max_y = lod0_voxel_extent.max().y() >> voxel_map_config.chunk_log2;
-    for x in visible_extent.minimum.x()..visible_extent.least_upper_bound().x() {
-        for z in visible_extent.minimum.z()..visible_extent.least_upper_bound().z() {
+    for x in generation_extent.minimum.x()..generation_extent.least_upper_bound().x() {
+        for z in generation_extent.minimum.z()..generation_extent.least_upper_bound().z() {
             let chunk_key = PointN([x, 0, z]);
             let mut exists = false;
             for y in min_y..=max_y {
@@ -198,3 +604,173 @@ pub fn chunk_detection_system(
         }
     }
 }
+
+/// Unloads the lod0 chunks that are more than `keep_radius_chunks` from the camera in x or z,
+/// whenever the camera moves into another chunk
+pub fn chunk_unload_system(
+    cameras: Query<&GlobalTransform, With<CameraTag>>,
+    voxel_map: Res<VoxelMap>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    mut chunk_commands: ResMut<ChunkCommandQueue>,
+    mut last_camera_center: Local<Option<Point3i>>,
+) {
+    let camera_position = if let Some(tfm) = cameras.iter().next() {
+        tfm.translation
+    } else {
+        return;
+    };
+    let camera_center = Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
+    let camera_center = PointN([camera_center.x(), 0, camera_center.z()]);
+    if last_camera_center.replace(camera_center) == Some(camera_center) {
+        return;
+    }
+
+    let keep_radius = voxel_map_config.keep_radius_chunks;
+    for voxel_key in voxel_map.pyramid.level(0).storage().keys() {
+        let chunk_key = *voxel_key >> voxel_map_config.chunk_log2;
+        let offset = chunk_key - camera_center;
+        if offset.x().abs() > keep_radius || offset.z().abs() > keep_radius {
+            chunk_commands.enqueue(ChunkCommand::Unload(chunk_key));
+        }
+    }
+}
+
+/// How a HeightmapGenerator samples columns outside of its image
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum HeightmapEdge {
+    /// Repeat the image across the world
+    Tile,
+    /// Extend the edge pixels of the image outwards
+    Clamp,
+}
+
+/// Terrain whose column heights come from the intensity of the pixels of an image, from
+/// `min_height` for black to `max_height` for white. Pixel (x, y) is the column at voxel (x, z).
+pub struct HeightmapGenerator {
+    width: i32,
+    depth: i32,
+    // One per pixel, row by row
+    intensities: Vec<u8>,
+    pub min_height: i32,
+    pub max_height: i32,
+    pub edge: HeightmapEdge,
+    pub material: Voxel,
+}
+
+impl HeightmapGenerator {
+    pub fn new(
+        width: u32,
+        depth: u32,
+        intensities: Vec<u8>,
+        min_height: i32,
+        max_height: i32,
+        edge: HeightmapEdge,
+    ) -> Self {
+        assert!(width > 0 && depth > 0, "The heightmap must not be empty");
+        assert_eq!(
+            intensities.len(),
+            (width * depth) as usize,
+            "The heightmap must have one intensity per pixel"
+        );
+        Self {
+            width: width as i32,
+            depth: depth as i32,
+            intensities,
+            min_height,
+            max_height,
+            edge,
+            material: Voxel::GRASS,
+        }
+    }
+
+    /// Uses the first channel of each pixel of a loaded image. Returns None for formats that do
+    /// not have 8-bit channels.
+    pub fn from_texture(
+        texture: &Texture,
+        min_height: i32,
+        max_height: i32,
+        edge: HeightmapEdge,
+    ) -> Option<Self> {
+        match texture.format {
+            TextureFormat::R8Unorm
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb => {}
+            _ => return None,
+        }
+        let pixel_size = texture.format.pixel_size();
+        let intensities = texture.data.iter().step_by(pixel_size).copied().collect();
+        Some(Self::new(
+            texture.size.width,
+            texture.size.height,
+            intensities,
+            min_height,
+            max_height,
+            edge,
+        ))
+    }
+
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let (x, z) = match self.edge {
+            HeightmapEdge::Tile => (x.rem_euclid(self.width), z.rem_euclid(self.depth)),
+            HeightmapEdge::Clamp => (x.clamp(0, self.width - 1), z.clamp(0, self.depth - 1)),
+        };
+        let intensity = self.intensities[(z * self.width + x) as usize] as f32 / 255.0;
+        self.min_height + (intensity * (self.max_height - self.min_height) as f32).round() as i32
+    }
+}
+
+impl ChunkGenerator for HeightmapGenerator {
+    fn generate(
+        &self,
+        key: Point3i,
+        _seed: WorldSeed,
+        config: &VoxelMapConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        let chunk_min = key * config.chunk_shape;
+        let mut heights =
+            Vec::with_capacity((config.chunk_shape.x() * config.chunk_shape.z()) as usize);
+        for z in 0..config.chunk_shape.z() {
+            for x in 0..config.chunk_shape.x() {
+                heights.push(self.height(chunk_min.x() + x, chunk_min.z() + z));
+            }
+        }
+        let min_y = heights.iter().copied().min().unwrap_or(self.min_height);
+        let max_y = heights.iter().copied().max().unwrap_or(self.max_height);
+
+        let min_y_chunk = (min_y - 1) >> config.chunk_log2;
+        let max_y_chunk = (max_y - 1) >> config.chunk_log2;
+        ((min_y_chunk - 1)..=max_y_chunk)
+            .map(|y_chunk| {
+                let y_chunk_min =
+                    PointN([chunk_min.x(), y_chunk << config.chunk_log2, chunk_min.z()]);
+                let extent = Extent3i::from_min_and_shape(y_chunk_min, config.chunk_shape);
+                let chunk = Array3x1::fill_with(extent, |p: Point3i| {
+                    let local_p = p - chunk_min;
+                    let height =
+                        heights[(local_p.z() * config.chunk_shape.x() + local_p.x()) as usize];
+                    if p.y() < height {
+                        self.material
+                    } else {
+                        Voxel::EMPTY
+                    }
+                });
+                (y_chunk_min, chunk)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_noise_generators_take_a_noise_config() {
+        let noise_config = NoiseConfig::default().with_octaves(2);
+        let flat = TerrainGenerator(Arc::new(FlatWorldGenerator::default()));
+        assert!(flat.with_noise_config(&noise_config).is_none());
+        let noise = TerrainGenerator::default();
+        assert!(noise.with_noise_config(&noise_config).is_some());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    app_state::AppState,
+    chunk_generator::{ChunkCommandQueue, TerrainGenerator},
+    raycast::VoxelHit,
+    voxel_map::{Voxel, VoxelMap, VoxelMapConfig},
+    world_seed::WorldSeed,
+};
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, pipeline::PrimitiveTopology},
+};
+use bevy_prototype_character_controller::controller::{BodyTag, CameraTag};
+use building_blocks::prelude::*;
+
+/// The farthest voxel that can be targeted, in voxels
+const MAX_TARGET_DISTANCE: f32 = 8.0;
+// Grow the highlight box slightly so that it is not hidden by the faces of the voxel
+const HIGHLIGHT_INFLATION: f32 = 0.005;
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+// Voxels above and below the one at the centre of the player's body that it takes up
+const BODY_HALF_HEIGHT_VOXELS: i32 = 1;
+
+/// Draws a crosshair in the middle of the screen and outlines the voxel it is over. While running,
+/// the left mouse button digs out the targeted voxel and the right one places a `SelectedMaterial`
+/// voxel against the targeted face.
+pub struct CrosshairPlugin;
+
+impl Plugin for CrosshairPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TargetedVoxel>()
+            .init_resource::<SelectedMaterial>()
+            .add_startup_system(crosshair_setup.system())
+            .add_system(targeted_voxel_system.system().label("targeted_voxel"))
+            .add_system(
+                target_highlight_system
+                    .system()
+                    .label("target_highlight")
+                    .after("targeted_voxel"),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Running)
+                    .with_system(voxel_edit_system.system().after("targeted_voxel")),
+            );
+    }
+}
+
+/// The voxel under the crosshair, if any is within reach
+#[derive(Default)]
+pub struct TargetedVoxel(pub Option<VoxelHit>);
+
+/// The material placed with the right mouse button
+pub struct SelectedMaterial(pub Voxel);
+
+impl Default for SelectedMaterial {
+    fn default() -> Self {
+        Self(Voxel::STONE)
+    }
+}
+
+pub struct TargetHighlightTag;
+
+fn crosshair_setup(
+    mut commands: Commands,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let white = color_materials.add(ColorMaterial::color(Color::WHITE));
+    let transparent = color_materials.add(ColorMaterial::color(Color::NONE));
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: transparent,
+            ..Default::default()
+        })
+        .with_children(|p| {
+            for (width, height) in [
+                (CROSSHAIR_SIZE, CROSSHAIR_THICKNESS),
+                (CROSSHAIR_THICKNESS, CROSSHAIR_SIZE),
+            ]
+            .iter()
+            {
+                p.spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(*width), Val::Px(*height)),
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    material: white.clone(),
+                    ..Default::default()
+                });
+            }
+        });
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(unit_cube_outline(HIGHLIGHT_INFLATION)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::BLACK,
+                unlit: true,
+                ..Default::default()
+            }),
+            visible: Visible {
+                is_visible: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(TargetHighlightTag);
+}
+
+// The 12 edges of the cube from 0 to 1, grown by `inflation` on all sides
+fn unit_cube_outline(inflation: f32) -> Mesh {
+    let (lo, hi) = (-inflation, 1.0 + inflation);
+    let mut positions = Vec::with_capacity(8);
+    for i in 0..8 {
+        positions.push([
+            if i & 1 == 0 { lo } else { hi },
+            if i & 2 == 0 { lo } else { hi },
+            if i & 4 == 0 { lo } else { hi },
+        ]);
+    }
+    // Each edge joins two corners whose indices differ in exactly one bit
+    let mut indices = Vec::with_capacity(24);
+    for i in 0..8u32 {
+        for bit in [1, 2, 4].iter() {
+            if i & bit == 0 {
+                indices.push(i);
+                indices.push(i | bit);
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; 8]);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; 8]);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+pub fn targeted_voxel_system(
+    voxel_map: Option<Res<VoxelMap>>,
+    cameras: Query<&GlobalTransform, With<CameraTag>>,
+    mut targeted_voxel: ResMut<TargetedVoxel>,
+) {
+    let hit = match (voxel_map, cameras.iter().next()) {
+        (Some(voxel_map), Some(tfm)) => voxel_map.raycast(
+            tfm.translation,
+            tfm.rotation * -Vec3::Z,
+            MAX_TARGET_DISTANCE,
+        ),
+        _ => None,
+    };
+    if targeted_voxel.0 != hit {
+        targeted_voxel.0 = hit;
+    }
+}
+
+fn target_highlight_system(
+    targeted_voxel: Res<TargetedVoxel>,
+    mut highlights: Query<(&mut Transform, &mut Visible), With<TargetHighlightTag>>,
+) {
+    if !targeted_voxel.is_changed() {
+        return;
+    }
+    for (mut transform, mut visible) in highlights.iter_mut() {
+        visible.is_visible = targeted_voxel.0.is_some();
+        if let Some(hit) = targeted_voxel.0 {
+            transform.translation = Vec3::new(
+                hit.point.x() as f32,
+                hit.point.y() as f32,
+                hit.point.z() as f32,
+            );
+        }
+    }
+}
+
+fn voxel_edit_system(
+    mouse_buttons: Res<Input<MouseButton>>,
+    targeted_voxel: Res<TargetedVoxel>,
+    selected_material: Res<SelectedMaterial>,
+    voxel_map: Option<ResMut<VoxelMap>>,
+    terrain_generator: Res<TerrainGenerator>,
+    world_seed: Res<WorldSeed>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    bodies: Query<&GlobalTransform, With<BodyTag>>,
+    mut chunk_commands: ResMut<ChunkCommandQueue>,
+) {
+    let (mut voxel_map, hit) = match (voxel_map, targeted_voxel.0) {
+        (Some(voxel_map), Some(hit)) => (voxel_map, hit),
+        _ => return,
+    };
+    let (p, v) = if mouse_buttons.just_pressed(MouseButton::Left) {
+        (hit.point, Voxel::EMPTY)
+    } else if mouse_buttons.just_pressed(MouseButton::Right) {
+        // Placing into the player would trap them
+        let in_body = bodies.iter().any(|tfm| {
+            let body = Point3f::from(tfm.translation).in_voxel();
+            hit.adjacent.x() == body.x()
+                && hit.adjacent.z() == body.z()
+                && (hit.adjacent.y() - body.y()).abs() <= BODY_HALF_HEIGHT_VOXELS
+        });
+        if in_body || hit.adjacent == hit.point {
+            return;
+        }
+        (hit.adjacent, selected_material.0)
+    } else {
+        return;
+    };
+    for command in voxel_map.set_voxel(p, v, &*terrain_generator, *world_seed, &*voxel_map_config) {
+        chunk_commands.enqueue(command);
+    }
+}
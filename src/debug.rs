@@ -4,7 +4,12 @@ use bevy::{
 };
 use bevy_prototype_character_controller::look::MouseSettings;
 
-use crate::mesh_diagnostics::MeshDiagnosticsPlugin;
+use crate::{
+    mesh_diagnostics::MeshDiagnosticsPlugin,
+    mesh_generator::MeshCommandQueue,
+    terrain_diagnostics::TerrainDiagnosticsPlugin,
+    voxel_map::{VoxelMap, VoxelMapConfig},
+};
 
 pub struct Debug {
     pub enabled: bool,
@@ -31,6 +36,7 @@ impl Plugin for DebugPlugin {
         app.init_resource::<Debug>()
             .add_plugin(FrameTimeDiagnosticsPlugin::default())
             .add_plugin(MeshDiagnosticsPlugin::default())
+            .add_plugin(TerrainDiagnosticsPlugin::default())
             .add_plugin(LogDiagnosticsPlugin::default())
             .add_startup_system(debug_setup.system())
             .add_system(debug_toggle_system.system().label("debug_toggle"))
@@ -40,6 +46,28 @@ impl Plugin for DebugPlugin {
 
 pub struct DebugTransformTag;
 
+/// Marks each line of the debug overlay with the value it shows
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DebugLine {
+    FrameTime,
+    Position,
+    YawPitch,
+    ChunkCount,
+    MeshQueue,
+    Lod,
+}
+
+impl DebugLine {
+    pub const ALL: [DebugLine; 6] = [
+        DebugLine::FrameTime,
+        DebugLine::Position,
+        DebugLine::YawPitch,
+        DebugLine::ChunkCount,
+        DebugLine::MeshQueue,
+        DebugLine::Lod,
+    ];
+}
+
 fn debug_setup(
     mut debug: ResMut<Debug>,
     asset_server: Res<AssetServer>,
@@ -66,57 +94,11 @@ fn debug_toggle_system(mut commands: Commands, mut debug: ResMut<Debug>) {
                         ..Default::default()
                     })
                     .with_children(|p| {
-                        p.spawn_bundle(TextBundle {
-                            style: Style {
-                                align_self: AlignSelf::FlexStart,
-                                ..Default::default()
-                            },
-                            text: Text::with_section(
-                                "FT:".to_string(),
-                                TextStyle {
-                                    font: debug.font_handle.as_ref().unwrap().clone(),
-                                    font_size: 24.0,
-                                    color: Color::WHITE,
-                                    ..Default::default()
-                                },
-                                Default::default(),
-                            ),
-                            ..Default::default()
-                        });
-                        p.spawn_bundle(TextBundle {
-                            style: Style {
-                                align_self: AlignSelf::FlexStart,
-                                ..Default::default()
-                            },
-                            text: Text::with_section(
-                                "XYZ:".to_string(),
-                                TextStyle {
-                                    font: debug.font_handle.as_ref().unwrap().clone(),
-                                    font_size: 24.0,
-                                    color: Color::WHITE,
-                                    ..Default::default()
-                                },
-                                Default::default(),
-                            ),
-                            ..Default::default()
-                        });
-                        p.spawn_bundle(TextBundle {
-                            style: Style {
-                                align_self: AlignSelf::FlexStart,
-                                ..Default::default()
-                            },
-                            text: Text::with_section(
-                                "YP:".to_string(),
-                                TextStyle {
-                                    font: debug.font_handle.as_ref().unwrap().clone(),
-                                    font_size: 24.0,
-                                    color: Color::WHITE,
-                                    ..Default::default()
-                                },
-                                Default::default(),
-                            ),
-                            ..Default::default()
-                        });
+                        let font = debug.font_handle.as_ref().unwrap();
+                        for line in DebugLine::ALL.iter() {
+                            p.spawn_bundle(debug_text_bundle(font.clone()))
+                                .insert(*line);
+                        }
                     })
                     .id(),
             );
@@ -127,21 +109,44 @@ fn debug_toggle_system(mut commands: Commands, mut debug: ResMut<Debug>) {
     }
 }
 
+fn debug_text_bundle(font: Handle<Font>) -> TextBundle {
+    TextBundle {
+        style: Style {
+            align_self: AlignSelf::FlexStart,
+            ..Default::default()
+        },
+        text: Text::with_section(
+            String::new(),
+            TextStyle {
+                font,
+                font_size: 24.0,
+                color: Color::WHITE,
+                ..Default::default()
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }
+}
+
 fn debug_system(
     debug: Res<Debug>,
     diagnostics: Res<Diagnostics>,
     settings: Res<MouseSettings>,
+    voxel_map: Option<Res<VoxelMap>>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    mesh_commands: Res<MeshCommandQueue>,
     camera: Query<&Transform, With<DebugTransformTag>>,
-    mut query: Query<&mut Text>,
+    mut query: Query<(&DebugLine, &mut Text)>,
 ) {
     if !debug.enabled || debug.text_entity.is_none() {
         return;
     }
     let mut cam_iter = camera.iter();
     let cam_transform = cam_iter.next().unwrap();
-    for mut text in query.iter_mut() {
-        match text.sections[0].value.get(..3) {
-            Some("FT:") => {
+    for (line, mut text) in query.iter_mut() {
+        match line {
+            DebugLine::FrameTime => {
                 if let Some(frame_time) = diagnostics.get(FrameTimeDiagnosticsPlugin::FRAME_TIME) {
                     if let Some(fdt) = frame_time.average() {
                         text.sections[0].value =
@@ -149,20 +154,35 @@ fn debug_system(
                     }
                 }
             }
-            Some("XYZ") => {
+            DebugLine::Position => {
                 let cam_pos = cam_transform.translation;
                 text.sections[0].value = format!(
                     "XYZ: ({:>8.2}, {:>8.2}, {:>8.2})",
                     cam_pos.x, cam_pos.y, cam_pos.z
                 );
             }
-            Some("YP:") => {
+            DebugLine::YawPitch => {
                 text.sections[0].value = format!(
                     "YP: ({:>8.2}, {:>8.2})",
                     settings.yaw_pitch_roll.x, settings.yaw_pitch_roll.y
                 );
             }
-            _ => {}
+            DebugLine::ChunkCount => {
+                let num_chunks = voxel_map
+                    .as_ref()
+                    .map(|voxel_map| voxel_map.pyramid.level(0).storage().len())
+                    .unwrap_or(0);
+                text.sections[0].value = format!("CHK: {:>8} lod0 chunks", num_chunks);
+            }
+            DebugLine::MeshQueue => {
+                text.sections[0].value = format!("MQ: {:>8} mesh commands", mesh_commands.len());
+            }
+            DebugLine::Lod => {
+                text.sections[0].value = format!(
+                    "LOD: {} LODs, clip box radius {}",
+                    voxel_map_config.num_lods, voxel_map_config.clip_box_radius
+                );
+            }
         }
     }
 }
@@ -0,0 +1,300 @@
+use crate::{
+    voxel_map::{Voxel, VoxelMapConfig},
+    world_seed::WorldSeed,
+};
+
+use building_blocks::{
+    core::extent::bounding_extent,
+    prelude::*,
+    storage::{ChunkHashMap3x1, SmallKeyHashMap, SmallKeyHashSet},
+};
+
+/// Trees placed on grass after a column has been generated. The world is divided into square
+/// cells of `spacing` voxels and each cell has one candidate tree at a random position within it,
+/// so trees are spread out without being on a grid.
+#[derive(Clone, Debug)]
+pub struct TreeConfig {
+    pub enabled: bool,
+    /// Voxels
+    pub spacing: i32,
+    /// The chance that a cell has a tree
+    pub density: f32,
+    pub min_trunk_height: i32,
+    pub max_trunk_height: i32,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            spacing: 12,
+            density: 0.3,
+            min_trunk_height: 4,
+            max_trunk_height: 6,
+        }
+    }
+}
+
+// How far leaves reach out from the trunk
+const LEAF_RADIUS: i32 = 2;
+
+type ColumnChunks = Vec<(Point3i, Array3x1<Voxel>)>;
+
+/// Places the trees that are rooted in the column at `column_key` into its chunks, adding empty
+/// chunks above the terrain where needed. Returns the voxels of those trees that fall in other
+/// columns. Like the generators, the result only depends on the inputs.
+pub fn decorate_column(
+    column_key: Point3i,
+    chunks: &mut ColumnChunks,
+    seed: WorldSeed,
+    config: &VoxelMapConfig,
+) -> Vec<(Point3i, Voxel)> {
+    let trees = &config.trees;
+    let mut spills = Vec::new();
+    if !trees.enabled || trees.spacing <= 0 || chunks.is_empty() {
+        return spills;
+    }
+    let seed = seed.derive("trees");
+    let column_min = column_key * config.chunk_shape;
+    let column_max = column_min + config.chunk_shape - Point3i::ONES;
+
+    let cell_min = PointN([
+        column_min.x().div_euclid(trees.spacing),
+        0,
+        column_min.z().div_euclid(trees.spacing),
+    ]);
+    let cell_max = PointN([
+        column_max.x().div_euclid(trees.spacing),
+        0,
+        column_max.z().div_euclid(trees.spacing),
+    ]);
+    for cell_x in cell_min.x()..=cell_max.x() {
+        for cell_z in cell_min.z()..=cell_max.z() {
+            let hash = cell_hash(seed, cell_x, cell_z);
+            if unit(hash) >= trees.density {
+                continue;
+            }
+            let spacing = trees.spacing as u64;
+            let x = cell_x * trees.spacing + ((hash >> 16) % spacing) as i32;
+            let z = cell_z * trees.spacing + ((hash >> 32) % spacing) as i32;
+            // Each tree belongs to the column that its root is in
+            if x < column_min.x() || x > column_max.x() || z < column_min.z() || z > column_max.z()
+            {
+                continue;
+            }
+            let root_y = match surface_y(chunks, x, z) {
+                Some((y, Voxel::GRASS)) => y,
+                _ => continue,
+            };
+            let height_range = (trees.max_trunk_height - trees.min_trunk_height).max(0) + 1;
+            let height = trees.min_trunk_height + ((hash >> 48) % height_range as u64) as i32;
+            for (p, voxel) in tree_voxels(PointN([x, root_y, z]), height).into_iter() {
+                if p.x() < column_min.x()
+                    || p.x() > column_max.x()
+                    || p.z() < column_min.z()
+                    || p.z() > column_max.z()
+                {
+                    spills.push((p, voxel));
+                } else {
+                    place_voxel(chunks, p, voxel, config.chunk_shape);
+                }
+            }
+        }
+    }
+    spills
+}
+
+// SplitMix64 of the seed and cell, so that each cell has its own independent random bits
+fn cell_hash(seed: WorldSeed, x: i32, z: i32) -> u64 {
+    let mut h = seed.0 ^ ((x as u32 as u64) << 32 | z as u32 as u64);
+    h = h.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+fn unit(hash: u64) -> f32 {
+    (hash & 0xffff) as f32 / 65536.0
+}
+
+// The highest non-empty voxel of the column at (x, z), if it has nothing but empty voxels above
+fn surface_y(chunks: &ColumnChunks, x: i32, z: i32) -> Option<(i32, Voxel)> {
+    let mut surface: Option<(i32, Voxel)> = None;
+    for (min, chunk) in chunks.iter() {
+        if surface.map_or(false, |(y, _)| y >= min.y() + chunk.extent().shape.y()) {
+            continue;
+        }
+        let top = min.y() + chunk.extent().shape.y() - 1;
+        for y in (min.y()..=top).rev() {
+            let voxel = chunk.get(PointN([x, y, z]));
+            if voxel != Voxel::EMPTY {
+                if surface.map_or(true, |(surface_y, _)| y > surface_y) {
+                    surface = Some((y, voxel));
+                }
+                break;
+            }
+        }
+    }
+    surface
+}
+
+// A trunk of `height` on top of `root`, with a crown of leaves around its top
+fn tree_voxels(root: Point3i, height: i32) -> Vec<(Point3i, Voxel)> {
+    let mut voxels = Vec::new();
+    let top = root + PointN([0, height, 0]);
+    for y in 1..=height {
+        voxels.push((root + PointN([0, y, 0]), Voxel::WOOD));
+    }
+    for dy in -1..=1 {
+        let radius = if dy == 1 { 1 } else { LEAF_RADIUS };
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                let is_corner = dx.abs() == radius && dz.abs() == radius;
+                if (dx == 0 && dz == 0 && dy < 1) || (is_corner && radius == LEAF_RADIUS) {
+                    continue;
+                }
+                voxels.push((top + PointN([dx, dy, dz]), Voxel::LEAVES));
+            }
+        }
+    }
+    voxels
+}
+
+// Sets an empty voxel of the column, so that decorations never replace terrain or each other
+fn place_voxel(chunks: &mut ColumnChunks, p: Point3i, voxel: Voxel, chunk_shape: Point3i) {
+    let index = match chunks
+        .iter()
+        .position(|(_, chunk)| chunk.extent().contains(p))
+    {
+        Some(index) => index,
+        None => {
+            let min = PointN([
+                p.x().div_euclid(chunk_shape.x()) * chunk_shape.x(),
+                p.y().div_euclid(chunk_shape.y()) * chunk_shape.y(),
+                p.z().div_euclid(chunk_shape.z()) * chunk_shape.z(),
+            ]);
+            let extent = Extent3i::from_min_and_shape(min, chunk_shape);
+            chunks.push((min, Array3x1::fill(extent, Voxel::EMPTY)));
+            chunks.len() - 1
+        }
+    };
+    let current = chunks[index].1.get_mut(p);
+    if *current == Voxel::EMPTY {
+        *current = voxel;
+    }
+}
+
+/// The voxels of trees that reach across from their own column into others. They are recorded
+/// per source column, so that they can be placed again when the target column is regenerated
+/// after being unloaded, and forgotten when the source column is unloaded.
+#[derive(Default)]
+pub struct DecorationSpills {
+    // By target column, then by source column
+    incoming: SmallKeyHashMap<Point3i, SmallKeyHashMap<Point3i, Vec<(Point3i, Voxel)>>>,
+    // The target columns of each source column
+    outgoing: SmallKeyHashMap<Point3i, SmallKeyHashSet<Point3i>>,
+}
+
+impl DecorationSpills {
+    /// Places the voxels that other columns have spilled into the freshly generated `column_key`
+    pub fn place_incoming(
+        &self,
+        column_key: Point3i,
+        chunks: &mut ColumnChunks,
+        chunk_shape: Point3i,
+    ) {
+        if let Some(sources) = self.incoming.get(&column_key) {
+            for (p, voxel) in sources.values().flatten() {
+                place_voxel(chunks, *p, *voxel, chunk_shape);
+            }
+        }
+    }
+
+    /// Records the voxels that the column at `source_key` spills into other columns and places
+    /// those that fall in columns of `lod0` that have already been generated. Returns the minimum
+    /// keys of the chunks that were written, including any that had to be created.
+    pub fn add(
+        &mut self,
+        lod0: &mut ChunkHashMap3x1<Voxel>,
+        source_key: Point3i,
+        spills: Vec<(Point3i, Voxel)>,
+        config: &VoxelMapConfig,
+    ) -> Vec<Point3i> {
+        self.remove_source(source_key);
+        let mut by_target: SmallKeyHashMap<Point3i, Vec<(Point3i, Voxel)>> =
+            SmallKeyHashMap::default();
+        for (p, voxel) in spills.into_iter() {
+            let target_key = PointN([p.x() >> config.chunk_log2, 0, p.z() >> config.chunk_log2]);
+            by_target
+                .entry(target_key)
+                .or_insert_with(Vec::new)
+                .push((p, voxel));
+        }
+
+        let mut written = Vec::new();
+        for (target_key, voxels) in by_target.into_iter() {
+            if column_exists(lod0, target_key, config.chunk_log2) {
+                let mut chunks: ColumnChunks = lod0
+                    .indexer
+                    .chunk_keys_for_extent(&bounding_extent(voxels.iter().map(|(p, _)| *p)))
+                    .map(|chunk_key| {
+                        let chunk = lod0.get_chunk(chunk_key).cloned().unwrap_or_else(|| {
+                            Array3x1::fill(
+                                Extent3i::from_min_and_shape(chunk_key, config.chunk_shape),
+                                Voxel::EMPTY,
+                            )
+                        });
+                        (chunk_key, chunk)
+                    })
+                    .collect();
+                for (p, voxel) in voxels.iter() {
+                    place_voxel(&mut chunks, *p, *voxel, config.chunk_shape);
+                }
+                for (chunk_key, chunk) in chunks.into_iter() {
+                    lod0.write_chunk(chunk_key, chunk);
+                    written.push(chunk_key);
+                }
+            }
+            self.outgoing
+                .entry(source_key)
+                .or_insert_with(SmallKeyHashSet::default)
+                .insert(target_key);
+            self.incoming
+                .entry(target_key)
+                .or_insert_with(SmallKeyHashMap::default)
+                .insert(source_key, voxels);
+        }
+        written
+    }
+
+    /// Forgets the voxels spilled by the column at `source_key`, once it has been unloaded
+    pub fn remove_source(&mut self, source_key: Point3i) {
+        for target_key in self.outgoing.remove(&source_key).unwrap_or_default() {
+            if let Some(sources) = self.incoming.get_mut(&target_key) {
+                sources.remove(&source_key);
+                if sources.is_empty() {
+                    self.incoming.remove(&target_key);
+                }
+            }
+        }
+    }
+}
+
+// Whether any chunk of the column at `column_key` has been generated
+pub(crate) fn column_exists(
+    lod0: &ChunkHashMap3x1<Voxel>,
+    column_key: Point3i,
+    chunk_log2: i32,
+) -> bool {
+    let lod0_voxel_extent = lod0.bounding_extent();
+    let min_y = lod0_voxel_extent.minimum.y() >> chunk_log2;
+    let max_y = lod0_voxel_extent.max().y() >> chunk_log2;
+    (min_y..=max_y).any(|y| {
+        let voxel_key = PointN([
+            column_key.x() << chunk_log2,
+            y << chunk_log2,
+            column_key.z() << chunk_log2,
+        ]);
+        lod0.get_chunk(voxel_key).is_some()
+    })
+}
@@ -0,0 +1,77 @@
+use crate::{
+    material_registry::MaterialRegistry,
+    mesh_generator::{for_each_lod0_chunk_mesh, MeshBuf, MeshConfig},
+    voxel_map::VoxelMap,
+};
+
+use building_blocks::{prelude::*, storage::SmallKeyHashSet};
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+impl VoxelMap {
+    /// Writes the greedy quad mesh of the lod0 chunks that intersect `extent` to a Wavefront OBJ
+    /// file at `path`, so the region is rounded out to whole chunks. Faces use the material
+    /// `layer<N>` for array texture layer N. Chunks are meshed and written one at a time.
+    pub fn export_region(&self, extent: Extent3i, path: &Path) -> io::Result<()> {
+        let lod0 = self.pyramid.level(0);
+        let chunk_keys: SmallKeyHashSet<Point3i> = lod0
+            .indexer
+            .chunk_keys_for_extent(&extent)
+            .filter(|chunk_key| lod0.get_chunk(*chunk_key).is_some())
+            .collect();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# minkraft region {:?}", extent)?;
+        // OBJ indices are 1-based and count vertices across the whole file
+        let mut num_vertices = 1;
+        let mut result = Ok(());
+        for_each_lod0_chunk_mesh(
+            self,
+            &chunk_keys,
+            &MeshConfig::default(),
+            &MaterialRegistry::default(),
+            |mesh_buf| {
+                if result.is_ok() {
+                    result = write_obj_mesh(&mut writer, mesh_buf, num_vertices);
+                    num_vertices += mesh_buf.positions.len() as u32;
+                }
+            },
+        );
+        result?;
+
+        writer.flush()
+    }
+}
+
+fn write_obj_mesh(writer: &mut impl Write, mesh_buf: &MeshBuf, first_index: u32) -> io::Result<()> {
+    for [x, y, z] in mesh_buf.positions.iter() {
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    for [x, y, z] in mesh_buf.normals.iter() {
+        writeln!(writer, "vn {} {} {}", x, y, z)?;
+    }
+    for [u, v] in mesh_buf.tex_coords.iter() {
+        writeln!(writer, "vt {} {}", u, v)?;
+    }
+
+    let mut current_layer = None;
+    for triangle in mesh_buf.indices.chunks(3) {
+        // All the vertices of a quad are on the same layer
+        let layer = mesh_buf.layer[triangle[0] as usize];
+        if current_layer != Some(layer) {
+            writeln!(writer, "usemtl layer{}", layer)?;
+            current_layer = Some(layer);
+        }
+        write!(writer, "f")?;
+        for i in triangle.iter() {
+            let i = first_index + i;
+            write!(writer, " {}/{}/{}", i, i, i)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
@@ -6,18 +6,40 @@ use bevy::{
         renderer::{RenderResource, RenderResources},
     },
 };
+use bevy_physical_sky::SolarPosition;
+use std::collections::HashMap;
 
 const FOG_RENDER_NODE: &str = "fog";
 pub const FOG_SETUP_SYSTEM: &str = "fog_setup";
+pub const FOG_TINT_SYSTEM: &str = "fog_tint";
+
+const DAY_HORIZON_COLOR: [f32; 3] = [0.55, 0.62, 0.7];
+const NIGHT_HORIZON_COLOR: [f32; 3] = [0.02, 0.025, 0.05];
+// Fog colour changes smaller than this are not written, to avoid uploading the fog uniforms every
+// frame as the sun moves
+const FOG_COLOR_EPSILON: f32 = 1.0 / 255.0;
 
 pub struct FogPlugin;
 
 impl Plugin for FogPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(setup.system().label(FOG_SETUP_SYSTEM));
+        app.init_resource::<FogOverride>()
+            .add_startup_system(setup.system().label(FOG_SETUP_SYSTEM))
+            .add_system(fog_tint_system.system().label(FOG_TINT_SYSTEM));
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u32)]
+pub enum FogMode {
+    /// Ramps from clear at `near` to opaque at `far`
+    Linear = 0,
+    /// 1 - e^(-density * distance)
+    Exp = 1,
+    /// 1 - e^(-(density * distance)^2)
+    Exp2 = 2,
+}
+
 #[derive(Debug, Clone, Copy, RenderResource, RenderResources)]
 #[render_resources(from_self)]
 #[repr(C)]
@@ -25,6 +47,13 @@ pub struct FogConfig {
     pub color: [f32; 4],
     pub near: f32,
     pub far: f32,
+    pub density: f32,
+    // A FogMode, stored as a u32 for the shader
+    pub mode: u32,
+    // Mist fills everything below height_start and thins out exponentially above it
+    pub height_start: f32,
+    // How quickly the mist thins out per unit of height above height_start. 0.0 disables it.
+    pub height_falloff: f32,
 }
 
 unsafe impl Byteable for FogConfig {}
@@ -35,10 +64,37 @@ impl Default for FogConfig {
             color: [0.43, 0.35, 0.25, 1.0],
             near: 500.0,
             far: 5000.0,
+            // Almost opaque at the default far distance in the exponential modes
+            density: 0.0008,
+            mode: FogMode::Linear as u32,
+            height_start: 0.0,
+            height_falloff: 0.0,
+        }
+    }
+}
+
+impl FogConfig {
+    pub fn with_mode(self, mode: FogMode) -> Self {
+        Self {
+            mode: mode as u32,
+            ..self
+        }
+    }
+
+    pub fn with_height_fog(self, height_start: f32, height_falloff: f32) -> Self {
+        Self {
+            height_start,
+            height_falloff,
+            ..self
         }
     }
 }
 
+/// While set, all fog is replaced by this instead of following the sky, e.g. while the camera
+/// is under water. Once it is cleared each fog goes back to the distances and mode it had before.
+#[derive(Default)]
+pub struct FogOverride(pub Option<FogConfig>);
+
 pub fn setup(mut render_graph: ResMut<RenderGraph>) {
     // Add an AssetRenderResourcesNode to our Render Graph. This will bind
     // PhysicalSkyMaterial resources to our shader
@@ -54,3 +110,120 @@ pub fn setup(mut render_graph: ResMut<RenderGraph>) {
         .add_node_edge(FOG_RENDER_NODE, base::node::MAIN_PASS)
         .unwrap();
 }
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// The colour of the sky at the horizon for the current sun elevation: dark at night, warmed by
+/// the sun around sunrise and sunset and blue-grey during the day
+pub fn horizon_color(solar_position: &SolarPosition) -> [f32; 4] {
+    let (_, elevation) = solar_position.get_azimuth_inclination();
+    let elevation = elevation as f32;
+    let sun = solar_position.sun_color();
+    let sunset = [sun.r() * 0.8, sun.g() * 0.6, sun.b() * 0.5];
+    let lit = lerp3(sunset, DAY_HORIZON_COLOR, smoothstep(0.0, 20.0, elevation));
+    let color = lerp3(NIGHT_HORIZON_COLOR, lit, smoothstep(-6.0, 2.0, elevation));
+    [color[0], color[1], color[2], 1.0]
+}
+
+// Whether writing `to` over `from` would make a visible difference
+fn fog_differs(from: &FogConfig, to: &FogConfig) -> bool {
+    from.color
+        .iter()
+        .zip(to.color.iter())
+        .any(|(a, b)| (a - b).abs() > FOG_COLOR_EPSILON)
+        || from.near != to.near
+        || from.far != to.far
+        || from.density != to.density
+        || from.mode != to.mode
+        || from.height_start != to.height_start
+        || from.height_falloff != to.height_falloff
+}
+
+/// Tints all fog to match the sky at the horizon, unless there is a `FogOverride`. Without a
+/// SolarPosition resource the colour is left alone.
+pub fn fog_tint_system(
+    solar_position: Option<Res<SolarPosition>>,
+    fog_override: Res<FogOverride>,
+    // The fog of each entity from before the override, to restore once it is cleared
+    mut overridden: Local<HashMap<Entity, FogConfig>>,
+    mut query: Query<(Entity, &mut FogConfig)>,
+) {
+    let color = solar_position.map(|solar_position| horizon_color(&solar_position));
+    for (entity, mut fog) in query.iter_mut() {
+        let target = match fog_override.0 {
+            Some(target) => {
+                overridden.entry(entity).or_insert(*fog);
+                target
+            }
+            None => {
+                let original = overridden.remove(&entity).unwrap_or(*fog);
+                FogConfig {
+                    color: color.unwrap_or(original.color),
+                    ..original
+                }
+            }
+        };
+        // Only dereference mutably on a change so that unchanged fog is not re-uploaded
+        if fog_differs(&fog, &target) {
+            *fog = target;
+        }
+    }
+    // Fog of entities that were despawned while overridden
+    if fog_override.0.is_none() {
+        overridden.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_the_override_restores_each_fog() {
+        let mut world = World::default();
+        world.insert_resource(FogOverride::default());
+        let thin = FogConfig {
+            near: 100.0,
+            far: 1000.0,
+            ..Default::default()
+        }
+        .with_mode(FogMode::Exp2);
+        let misty = FogConfig::default().with_height_fog(10.0, 0.5);
+        let entities = [
+            world.spawn().insert(thin).id(),
+            world.spawn().insert(misty).id(),
+        ];
+        let mut stage = SystemStage::single(fog_tint_system.system());
+        stage.run(&mut world);
+
+        let under_water = FogConfig {
+            color: [0.0, 0.2, 0.3, 1.0],
+            near: 0.0,
+            far: 20.0,
+            ..Default::default()
+        };
+        world.get_resource_mut::<FogOverride>().unwrap().0 = Some(under_water);
+        stage.run(&mut world);
+        for entity in entities.iter() {
+            assert_eq!(world.get::<FogConfig>(*entity).unwrap().far, 20.0);
+        }
+
+        world.get_resource_mut::<FogOverride>().unwrap().0 = None;
+        stage.run(&mut world);
+        for (entity, expected) in entities.iter().zip([thin, misty].iter()) {
+            let fog = world.get::<FogConfig>(*entity).unwrap();
+            assert!(!fog_differs(fog, expected), "{:?} != {:?}", fog, expected);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+    tasks::ComputeTaskPool,
+};
+
+/// How many meshes may be created per compute thread each frame. The budget shrinks when frames
+/// take longer than `target_frame_time` and grows again when they are comfortably within it.
+pub struct FrameBudget {
+    /// Seconds
+    pub target_frame_time: f64,
+    pub min_creations_per_thread: usize,
+    pub max_creations_per_thread: usize,
+    creations_per_thread: usize,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        Self {
+            target_frame_time: 1.0 / 60.0,
+            min_creations_per_thread: 1,
+            max_creations_per_thread: 80,
+            creations_per_thread: 40,
+        }
+    }
+}
+
+impl FrameBudget {
+    pub fn creations_per_frame(&self, pool: &ComputeTaskPool) -> usize {
+        self.creations_per_thread * pool.thread_num()
+    }
+}
+
+// Frames faster than this fraction of the target leave room to grow the budget
+const HEADROOM: f64 = 0.75;
+
+/// Backs off quickly when frames are slow and recovers slowly, so that heavy generation settles at
+/// a budget that holds the target frame time
+pub fn frame_budget_system(diagnostics: Res<Diagnostics>, mut budget: ResMut<FrameBudget>) {
+    let frame_time = match diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.average())
+    {
+        Some(frame_time) => frame_time,
+        None => return,
+    };
+    let creations_per_thread = if frame_time > budget.target_frame_time {
+        budget.creations_per_thread * 3 / 4
+    } else if frame_time < HEADROOM * budget.target_frame_time {
+        budget.creations_per_thread + 1
+    } else {
+        return;
+    };
+    budget.creations_per_thread = creations_per_thread
+        .max(budget.min_creations_per_thread)
+        .min(budget.max_creations_per_thread);
+}
@@ -0,0 +1,155 @@
+use crate::key_bindings::KeyBindings;
+
+use bevy::prelude::*;
+use bevy_prototype_character_controller::{
+    controller::{BodyTag, CameraTag, HeadTag},
+    look::LookDirection,
+};
+use bevy_rapier3d::prelude::{RigidBodyPosition, RigidBodyType};
+
+/// A noclip spectator camera, toggled with `KeyBindings::toggle_free_fly`. While it is active the
+/// camera is detached from the player's head and the player's body is frozen in place. Toggling it
+/// off moves the body to the camera and attaches the camera to the head again.
+pub struct FreeFlyPlugin;
+
+impl Plugin for FreeFlyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeyBindings>()
+            .init_resource::<FreeFlyConfig>()
+            .init_resource::<FreeFlyState>()
+            .add_system(free_fly_toggle_system.system().label("free_fly_toggle"))
+            .add_system(
+                free_fly_movement_system
+                    .system()
+                    .label("free_fly_movement")
+                    .after("free_fly_toggle"),
+            );
+    }
+}
+
+pub struct FreeFlyConfig {
+    /// Voxels per second
+    pub speed: f32,
+    /// Voxels per second while left shift is held
+    pub fast_speed: f32,
+}
+
+impl Default for FreeFlyConfig {
+    fn default() -> Self {
+        Self {
+            speed: 40.0,
+            fast_speed: 400.0,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FreeFlyState {
+    attachment: Option<Attachment>,
+}
+
+impl FreeFlyState {
+    pub fn is_active(&self) -> bool {
+        self.attachment.is_some()
+    }
+}
+
+// What is needed to put the camera back where it came from
+struct Attachment {
+    head: Entity,
+    camera_transform: Transform,
+    body_type: RigidBodyType,
+    // From the body to the camera, in world space, when the camera was detached
+    body_to_camera: Vec3,
+}
+
+fn free_fly_toggle_system(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut state: ResMut<FreeFlyState>,
+    mut cameras: Query<(Entity, &mut Transform, &GlobalTransform), With<CameraTag>>,
+    mut bodies: Query<(&mut RigidBodyType, &mut RigidBodyPosition), With<BodyTag>>,
+    heads: Query<(Entity, &Children), With<HeadTag>>,
+) {
+    if !keyboard_input.just_pressed(key_bindings.toggle_free_fly) {
+        return;
+    }
+    let (camera, mut camera_transform, camera_global) = match cameras.iter_mut().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let (mut body_type, mut body_position) = match bodies.iter_mut().next() {
+        Some(body) => body,
+        None => return,
+    };
+
+    if let Some(attachment) = state.attachment.take() {
+        let translation = camera_global.translation - attachment.body_to_camera;
+        body_position.position = translation.into();
+        body_position.next_position = body_position.position;
+        *body_type = attachment.body_type;
+        *camera_transform = attachment.camera_transform;
+        commands.entity(attachment.head).push_children(&[camera]);
+    } else {
+        let head = match heads
+            .iter()
+            .find(|(_, children)| children.iter().any(|child| *child == camera))
+        {
+            Some((head, _)) => head,
+            None => return,
+        };
+        let body_translation = body_position.position.translation;
+        let body_translation =
+            Vec3::new(body_translation.x, body_translation.y, body_translation.z);
+        state.attachment = Some(Attachment {
+            head,
+            camera_transform: *camera_transform,
+            body_type: *body_type,
+            body_to_camera: camera_global.translation - body_translation,
+        });
+        // A static body is neither moved by the character controller nor by gravity
+        *body_type = RigidBodyType::Static;
+        *camera_transform = Transform::from(*camera_global);
+        commands.entity(camera).remove::<Parent>();
+    }
+}
+
+fn free_fly_movement_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    config: Res<FreeFlyConfig>,
+    state: Res<FreeFlyState>,
+    mut cameras: Query<(&mut Transform, &LookDirection), With<CameraTag>>,
+) {
+    if !state.is_active() {
+        return;
+    }
+    for (mut transform, look) in cameras.iter_mut() {
+        let forward = look.forward.normalize_or_zero();
+        transform.rotation = Transform::identity().looking_at(forward, Vec3::Y).rotation;
+
+        let right = forward.cross(Vec3::Y).normalize_or_zero();
+        let mut direction = Vec3::ZERO;
+        for (key, axis) in [
+            (KeyCode::W, forward),
+            (KeyCode::S, -forward),
+            (KeyCode::D, right),
+            (KeyCode::A, -right),
+            (KeyCode::Space, Vec3::Y),
+            (KeyCode::LControl, -Vec3::Y),
+        ]
+        .iter()
+        {
+            if keyboard_input.pressed(*key) {
+                direction += *axis;
+            }
+        }
+        let speed = if keyboard_input.pressed(KeyCode::LShift) {
+            config.fast_speed
+        } else {
+            config.speed
+        };
+        transform.translation += speed * time.delta_seconds() * direction.normalize_or_zero();
+    }
+}
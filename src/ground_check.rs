@@ -0,0 +1,108 @@
+use crate::{mesh_generator::ChunkMeshes, voxel_map::VoxelMap};
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{RigidBodyPosition, RigidBodyType, RigidBodyVelocity};
+use building_blocks::prelude::*;
+
+// A body this far below the surface is assumed to be meant to be there, e.g. in a cave under an
+// overhang, rather than sinking through it
+const MAX_SINK_DEPTH: f32 = 2.0;
+
+/// Holds a rigid body up on the voxel surface while the lod0 chunk under it has no collider, as
+/// happens while chunks are still being meshed during streaming. Once the collider is attached
+/// physics takes over again.
+pub struct GroundCheck {
+    /// From the body's origin down to the bottom of its collider
+    pub foot_offset: f32,
+}
+
+pub fn ground_check_system(
+    voxel_map: Option<Res<VoxelMap>>,
+    chunk_meshes: Option<Res<ChunkMeshes>>,
+    mut bodies: Query<(&GroundCheck, &mut RigidBodyPosition, &mut RigidBodyVelocity)>,
+) {
+    let (voxel_map, chunk_meshes) = match (voxel_map, chunk_meshes) {
+        (Some(voxel_map), Some(chunk_meshes)) => (voxel_map, chunk_meshes),
+        _ => return,
+    };
+    let indexer = &voxel_map.pyramid.level(0).indexer;
+    for (ground_check, mut position, mut velocity) in bodies.iter_mut() {
+        let translation = position.position.translation;
+        let (x, z) = (translation.x.floor() as i32, translation.z.floor() as i32);
+        let surface_y = match voxel_map.surface_height(x, z) {
+            Some(y) => y,
+            None => continue,
+        };
+        let chunk_key = indexer.min_of_chunk_containing_point(PointN([x, surface_y, z]));
+        if chunk_meshes.has_lod0_collider(chunk_key) {
+            continue;
+        }
+        // The top of the surface voxel
+        let ground = surface_y as f32 + 1.0;
+        let feet = translation.y - ground_check.foot_offset;
+        if feet < ground && feet > ground - MAX_SINK_DEPTH {
+            let lifted_y = ground + ground_check.foot_offset;
+            position.position.translation.vector.y = lifted_y;
+            position.next_position.translation.vector.y = lifted_y;
+            velocity.linvel.y = velocity.linvel.y.max(0.0);
+        }
+    }
+}
+
+/// Keeps a body frozen where it was spawned until the terrain under it can hold it. The body is
+/// first moved to `margin` above the voxel surface, which is known as soon as its column has been
+/// generated, and is released once the lod0 chunk there has a collider.
+pub struct SpawnHold {
+    pub margin: f32,
+    /// The body type to restore on release
+    pub body_type: RigidBodyType,
+    snapped: bool,
+}
+
+impl SpawnHold {
+    pub fn new(margin: f32, body_type: RigidBodyType) -> Self {
+        Self {
+            margin,
+            body_type,
+            snapped: false,
+        }
+    }
+}
+
+pub fn spawn_hold_system(
+    mut commands: Commands,
+    voxel_map: Option<Res<VoxelMap>>,
+    chunk_meshes: Option<Res<ChunkMeshes>>,
+    mut bodies: Query<(
+        Entity,
+        &mut SpawnHold,
+        &GroundCheck,
+        &mut RigidBodyType,
+        &mut RigidBodyPosition,
+    )>,
+) {
+    let (voxel_map, chunk_meshes) = match (voxel_map, chunk_meshes) {
+        (Some(voxel_map), Some(chunk_meshes)) => (voxel_map, chunk_meshes),
+        _ => return,
+    };
+    let indexer = &voxel_map.pyramid.level(0).indexer;
+    for (entity, mut hold, ground_check, mut body_type, mut position) in bodies.iter_mut() {
+        let translation = position.position.translation;
+        let (x, z) = (translation.x.floor() as i32, translation.z.floor() as i32);
+        let surface_y = match voxel_map.surface_height(x, z) {
+            Some(y) => y,
+            None => continue,
+        };
+        if !hold.snapped {
+            let y = surface_y as f32 + 1.0 + ground_check.foot_offset + hold.margin;
+            position.position.translation.vector.y = y;
+            position.next_position.translation.vector.y = y;
+            hold.snapped = true;
+        }
+        let chunk_key = indexer.min_of_chunk_containing_point(PointN([x, surface_y, z]));
+        if chunk_meshes.has_lod0_collider(chunk_key) {
+            *body_type = hold.body_type;
+            commands.entity(entity).remove::<SpawnHold>();
+        }
+    }
+}
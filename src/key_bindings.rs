@@ -0,0 +1,120 @@
+use bevy::prelude::*;
+
+/// The key for each keyboard action. Insert a modified copy before adding the plugins to remap
+/// them.
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    pub start_game: KeyCode,
+    pub toggle_debug: KeyCode,
+    pub toggle_wireframe: KeyCode,
+    pub toggle_third_person: KeyCode,
+    pub toggle_free_fly: KeyCode,
+    pub toggle_pause: KeyCode,
+    pub cycle_clip_box_radius: KeyCode,
+    pub cycle_chunk_log2: KeyCode,
+    pub cycle_num_lods: KeyCode,
+    pub toggle_meshing_mode: KeyCode,
+    pub decrease_noise_frequency: KeyCode,
+    pub increase_noise_frequency: KeyCode,
+    pub cycle_noise_octaves: KeyCode,
+    pub toggle_minimap: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            start_game: KeyCode::Return,
+            toggle_debug: KeyCode::H,
+            toggle_wireframe: KeyCode::M,
+            toggle_third_person: KeyCode::T,
+            toggle_free_fly: KeyCode::F,
+            toggle_pause: KeyCode::P,
+            cycle_clip_box_radius: KeyCode::R,
+            cycle_chunk_log2: KeyCode::C,
+            cycle_num_lods: KeyCode::L,
+            toggle_meshing_mode: KeyCode::N,
+            decrease_noise_frequency: KeyCode::LBracket,
+            increase_noise_frequency: KeyCode::RBracket,
+            cycle_noise_octaves: KeyCode::O,
+            toggle_minimap: KeyCode::K,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn actions(&self) -> [(&'static str, KeyCode); 14] {
+        [
+            ("start_game", self.start_game),
+            ("toggle_debug", self.toggle_debug),
+            ("toggle_wireframe", self.toggle_wireframe),
+            ("toggle_third_person", self.toggle_third_person),
+            ("toggle_free_fly", self.toggle_free_fly),
+            ("toggle_pause", self.toggle_pause),
+            ("cycle_clip_box_radius", self.cycle_clip_box_radius),
+            ("cycle_chunk_log2", self.cycle_chunk_log2),
+            ("cycle_num_lods", self.cycle_num_lods),
+            ("toggle_meshing_mode", self.toggle_meshing_mode),
+            ("decrease_noise_frequency", self.decrease_noise_frequency),
+            ("increase_noise_frequency", self.increase_noise_frequency),
+            ("cycle_noise_octaves", self.cycle_noise_octaves),
+            ("toggle_minimap", self.toggle_minimap),
+        ]
+    }
+
+    /// Pairs of actions that are bound to the same key
+    pub fn collisions(&self) -> Vec<(&'static str, &'static str, KeyCode)> {
+        let actions = self.actions();
+        let mut collisions = Vec::new();
+        for (i, (a, a_key)) in actions.iter().enumerate() {
+            for (b, b_key) in actions[i + 1..].iter() {
+                if a_key == b_key {
+                    collisions.push((*a, *b, *a_key));
+                }
+            }
+        }
+        collisions
+    }
+}
+
+pub fn key_bindings_check_system(key_bindings: Res<KeyBindings>) {
+    for (a, b, key) in key_bindings.collisions() {
+        warn!("{} and {} are both bound to {:?}", a, b, key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_do_not_collide() {
+        assert_eq!(KeyBindings::default().collisions(), vec![]);
+    }
+
+    #[test]
+    fn default_bindings_leave_the_movement_keys_free() {
+        let movement = [
+            KeyCode::W,
+            KeyCode::A,
+            KeyCode::S,
+            KeyCode::D,
+            KeyCode::Space,
+            KeyCode::LShift,
+        ];
+        for (action, key) in KeyBindings::default().actions().iter() {
+            assert!(!movement.contains(key), "{} is bound to {:?}", action, key);
+        }
+    }
+
+    #[test]
+    fn remapped_collisions_are_found() {
+        let key_bindings = KeyBindings {
+            toggle_minimap: KeyCode::M,
+            ..Default::default()
+        };
+        assert_eq!(
+            key_bindings.collisions(),
+            vec![("toggle_wireframe", "toggle_minimap", KeyCode::M)]
+        );
+    }
+}
@@ -36,13 +36,18 @@ use bevy::{prelude::*, render::camera::Camera};
 
 #[derive(Default)]
 pub struct LodState {
+    /// The lod0 chunk containing the camera, including its height, when the clipmap was last
+    /// updated
     pub old_lod0_center: Point3i,
+    /// The camera position that last caused a clipmap update
+    pub trigger_position: Option<Vec3>,
 }
 
 impl LodState {
     pub fn new(lod0_center: Point3i) -> Self {
         Self {
             old_lod0_center: lod0_center,
+            trigger_position: None,
         }
     }
 }
@@ -55,6 +60,8 @@ pub fn level_of_detail_system(
     mut lod_state: ResMut<LodState>,
     mut mesh_commands: ResMut<MeshCommandQueue>,
 ) {
+    let span = info_span!("level_of_detail_system");
+    let _guard = span.enter();
     let camera_position = if let Some((_camera, tfm)) = cameras.iter().next() {
         tfm.translation
     } else {
@@ -66,6 +73,13 @@ pub fn level_of_detail_system(
     if lod0_center == lod_state.old_lod0_center {
         return;
     }
+    // Pacing back and forth across a chunk boundary would otherwise split and merge the same
+    // chunks over and over
+    if let Some(trigger_position) = lod_state.trigger_position {
+        if camera_position.distance(trigger_position) < voxel_map_config.lod_hysteresis_voxels {
+            return;
+        }
+    }
 
     let bounding_voxel_extent = voxel_map.pyramid.level(0).bounding_extent();
     voxel_map.index.find_clipmap_chunk_updates(
@@ -77,4 +91,5 @@ pub fn level_of_detail_system(
     );
 
     lod_state.old_lod0_center = lod0_center;
+    lod_state.trigger_position = Some(camera_position);
 }
@@ -1,11 +1,32 @@
 pub mod app_state;
+pub mod biome;
+pub mod chunk_compression;
 pub mod chunk_generator;
+pub mod crosshair;
 pub mod debug;
+pub mod decoration;
+pub mod export;
 pub mod fog;
+pub mod frame_budget;
+pub mod free_fly;
+pub mod ground_check;
+pub mod key_bindings;
 pub mod level_of_detail;
+pub mod lod_wireframe;
+pub mod material_registry;
+pub mod menu;
 pub mod mesh_diagnostics;
 pub mod mesh_fade;
 pub mod mesh_generator;
+pub mod minimap;
+pub mod movement;
+pub mod persistence;
+pub mod raycast;
+pub mod season;
 pub mod shaders;
+pub mod spawn;
+pub mod terrain_diagnostics;
+pub mod underwater;
 pub mod utilities;
 pub mod voxel_map;
+pub mod world_seed;
@@ -0,0 +1,122 @@
+use crate::voxel_map::VoxelMapConfig;
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+    },
+};
+use building_blocks::storage::LodChunkKey3;
+
+/// Outlines the triangles of each chunk mesh in a colour for its LOD, from green at lod0 to red at
+/// the coarsest LOD, to show the rings of the clipmap
+pub struct LodWireframePlugin;
+
+impl Plugin for LodWireframePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LodWireframe>()
+            .add_system(lod_wireframe_system.system());
+    }
+}
+
+#[derive(Default)]
+pub struct LodWireframe {
+    pub enabled: bool,
+    // One per LOD, created when first enabled
+    materials: Vec<Handle<StandardMaterial>>,
+}
+
+// On chunk entities that have a wireframe child
+struct HasLodWireframe;
+
+struct LodWireframeTag;
+
+fn lod_wireframe_system(
+    mut commands: Commands,
+    mut lod_wireframe: ResMut<LodWireframe>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunks: Query<(Entity, &LodChunkKey3, &Handle<Mesh>), Without<HasLodWireframe>>,
+    outlined_chunks: Query<Entity, With<HasLodWireframe>>,
+    wireframes: Query<Entity, With<LodWireframeTag>>,
+) {
+    if !lod_wireframe.enabled {
+        for entity in wireframes.iter() {
+            commands.entity(entity).despawn();
+        }
+        for entity in outlined_chunks.iter() {
+            commands.entity(entity).remove::<HasLodWireframe>();
+        }
+        return;
+    }
+
+    let num_lods = voxel_map_config.num_lods as usize;
+    if lod_wireframe.materials.len() != num_lods {
+        lod_wireframe.materials = (0..num_lods)
+            .map(|lod| {
+                let t = lod as f32 / (num_lods - 1).max(1) as f32;
+                materials.add(StandardMaterial {
+                    base_color: Color::rgb(t, 1.0 - t, 0.0),
+                    unlit: true,
+                    ..Default::default()
+                })
+            })
+            .collect();
+    }
+
+    for (entity, lod_chunk_key, mesh_handle) in chunks.iter() {
+        let material = match lod_wireframe.materials.get(lod_chunk_key.lod as usize) {
+            Some(material) => material.clone(),
+            None => continue,
+        };
+        let wireframe_mesh = match meshes.get(mesh_handle).and_then(triangle_edges) {
+            Some(mesh) => meshes.add(mesh),
+            None => continue,
+        };
+        let wireframe = commands
+            .spawn_bundle(PbrBundle {
+                mesh: wireframe_mesh,
+                material,
+                ..Default::default()
+            })
+            .insert(LodWireframeTag)
+            .id();
+        commands
+            .entity(entity)
+            .insert(HasLodWireframe)
+            .push_children(&[wireframe]);
+    }
+}
+
+// A LineList mesh of the edges of the triangles of `mesh`
+fn triangle_edges(mesh: &Mesh) -> Option<Mesh> {
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float3(positions) => positions.clone(),
+        _ => return None,
+    };
+    let triangles: Vec<u32> = match mesh.indices()? {
+        Indices::U32(indices) => indices.clone(),
+        Indices::U16(indices) => indices.iter().map(|i| *i as u32).collect(),
+    };
+    let mut indices = Vec::with_capacity(2 * triangles.len());
+    for triangle in triangles.chunks(3) {
+        indices.extend_from_slice(&[
+            triangle[0],
+            triangle[1],
+            triangle[1],
+            triangle[2],
+            triangle[2],
+            triangle[0],
+        ]);
+    }
+
+    let num_vertices = positions.len();
+    let mut wireframe = Mesh::new(PrimitiveTopology::LineList);
+    wireframe.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    wireframe.set_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; num_vertices]);
+    wireframe.set_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; num_vertices]);
+    wireframe.set_indices(Some(Indices::U32(indices)));
+    Some(wireframe)
+}
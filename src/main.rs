@@ -1,6 +1,8 @@
 use bevy::{
-    asset::AssetServerSettings,
+    app::AppExit,
+    asset::{AssetServerSettings, LoadState},
     input::{keyboard::KeyCode, system::exit_on_esc_system},
+    pbr::AmbientLight,
     prelude::*,
     render::{
         camera::PerspectiveProjection,
@@ -12,6 +14,7 @@ use bevy::{
     },
     tasks::ComputeTaskPool,
     wgpu::{WgpuFeature, WgpuFeatures, WgpuOptions},
+    window::{WindowId, WindowResized},
 };
 use bevy_frustum_culling::*;
 use bevy_hud_pass::{
@@ -20,12 +23,13 @@ use bevy_hud_pass::{
 };
 use bevy_mod_bounding::*;
 use bevy_physical_sky::{
-    PhysicalSkyCameraTag, PhysicalSkyMaterial, PhysicalSkyPlugin, SolarPosition,
-    PHYSICAL_SKY_FRAGMENT_SHADER, PHYSICAL_SKY_PASS_TIME_SYSTEM, PHYSICAL_SKY_VERTEX_SHADER,
+    AutoSkyPreset, PhysicalSkyCameraTag, PhysicalSkyMaterial, PhysicalSkyPlugin, SkyDome,
+    SolarPosition, PHYSICAL_SKY_FRAGMENT_SHADER, PHYSICAL_SKY_PASS_TIME_SYSTEM,
+    PHYSICAL_SKY_VERTEX_SHADER,
 };
 use bevy_prototype_character_controller::{
-    controller::{BodyTag, CameraTag, CharacterController, HeadTag, YawTag},
-    look::{LookDirection, LookEntity},
+    controller::{BodyTag, CameraTag, HeadTag, YawTag},
+    look::{LookDirection, LookEntity, MouseSettings},
     rapier::RapierDynamicImpulseCharacterControllerPlugin,
 };
 use bevy_rapier3d::{
@@ -39,16 +43,29 @@ use bevy_rapier3d::{
 use building_blocks::core::prelude::*;
 use minkraft::{
     app_state::AppState,
+    chunk_generator::TerrainGenerator,
+    crosshair::CrosshairPlugin,
     debug::{Debug, DebugPlugin, DebugTransformTag},
     fog::{FogConfig, FogPlugin},
+    free_fly::FreeFlyPlugin,
+    ground_check::{ground_check_system, spawn_hold_system, GroundCheck, SpawnHold},
+    key_bindings::{key_bindings_check_system, KeyBindings},
     level_of_detail::{level_of_detail_system, LodState},
+    lod_wireframe::{LodWireframe, LodWireframePlugin},
+    menu::MenuPlugin,
     mesh_fade::FadeUniform,
     mesh_generator::{
         mesh_generator_system, ArrayTextureMaterial, ArrayTexturePipelines, ChunkMeshes,
         MeshCommandQueue,
     },
+    minimap::MinimapPlugin,
+    movement::{JumpTimers, MovementConfig, MovementPlugin},
+    season::SeasonPlugin,
     shaders::{ARRAY_TEXTURE_FRAGMENT_SHADER, ARRAY_TEXTURE_VERTEX_SHADER},
-    voxel_map::{NoiseConfig, VoxelMap, VoxelMapConfig, VoxelMapPlugin},
+    spawn::SpawnConfig,
+    underwater::UnderwaterPlugin,
+    voxel_map::{Voxel, VoxelMap, VoxelMapConfig, VoxelMapPlugin},
+    world_seed::WorldSeed,
 };
 
 struct ArrayTexture(Handle<Texture>);
@@ -59,15 +76,29 @@ struct ThirdPerson {
     pub head: Entity,
 }
 
-const SPAWN_POINT: [f32; 3] = [8.5, 641.0, -3.5];
-const NO_GRAVITY: [f32; 3] = [0.0, 0.0, 0.0];
-const GRAVITY: [f32; 3] = [0.0, -9.81, 0.0];
+// The player is placed this far above the terrain at the spawn point's x and z
+const SPAWN_MARGIN: f32 = 0.5;
 const RENDER_BODY: bool = false;
 
+/// The spawn after `--spawn` on the command line, or else in `MINKRAFT_SPAWN`
+fn spawn_config_from_args_or_env() -> Option<SpawnConfig> {
+    let arg = std::env::args()
+        .skip_while(|arg| arg != "--spawn")
+        .nth(1)
+        .map(|arg| arg.parse());
+    arg.or_else(SpawnConfig::from_env)
+        .map(|spawn_config| spawn_config.expect("The spawn must be x,y,z or x,y,z,yaw"))
+}
+
 fn main() {
     env_logger::builder().format_timestamp_micros().init();
 
-    App::build()
+    let mut app = App::build();
+    // Taken before the default below, e.g. `minkraft --spawn 8.5,641,-3.5,90`
+    if let Some(spawn_config) = spawn_config_from_args_or_env() {
+        app.insert_resource(spawn_config);
+    }
+    app
         // Generic
         .insert_resource(WindowDescriptor {
             width: 1600.0,
@@ -87,21 +118,31 @@ fn main() {
         })
         .add_plugins(DefaultPlugins)
         .add_plugin(WireframePlugin)
+        .add_plugin(LodWireframePlugin)
         .insert_resource(AssetServerSettings {
             asset_folder: env!("CARGO_MANIFEST_DIR").to_string(),
         })
         .add_system(exit_on_esc_system.system())
+        .init_resource::<KeyBindings>()
+        .add_startup_system(key_bindings_check_system.system())
+        .init_resource::<SpawnConfig>()
         // States
-        .insert_resource(State::new(AppState::Loading))
-        .add_state(AppState::Loading)
+        .insert_resource(State::new(AppState::MainMenu))
+        .add_state(AppState::MainMenu)
+        .add_plugin(MenuPlugin)
         // Debug
         .add_plugin(DebugPlugin)
+        .add_plugin(CrosshairPlugin)
+        .add_plugin(MinimapPlugin)
+        .add_plugin(FreeFlyPlugin)
         .add_plugin(HUDPassPlugin)
         .add_plugin(WorldAxesPlugin)
         .insert_resource(WorldAxes {
             enabled: false,
             ..Default::default()
         })
+        .init_resource::<WorldAxesPlacement>()
+        .add_system(world_axes_placement_system.system())
         .add_system_to_stage(
             bevy::app::CoreStage::PreUpdate,
             toggle_debug_system.system(),
@@ -118,12 +159,18 @@ fn main() {
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         // NOTE: This overridden configuration must come after the plugin to override the defaults
         .insert_resource(RapierConfiguration {
-            gravity: GRAVITY.into(),
+            gravity: MovementConfig::default().gravity.into(),
             timestep_mode: TimestepMode::InterpolatedTimestep,
             ..Default::default()
         })
         // Character Controller
         .add_plugin(RapierDynamicImpulseCharacterControllerPlugin)
+        .add_plugin(MovementPlugin)
+        .add_system_set(
+            SystemSet::on_update(AppState::Running)
+                .with_system(ground_check_system.system())
+                .with_system(spawn_hold_system.system()),
+        )
         // Terrain
         // For fade in/out
         .add_system_to_stage(
@@ -137,21 +184,26 @@ fn main() {
         // Minkraft
         .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_assets.system()))
         .add_system_set(SystemSet::on_update(AppState::Loading).with_system(check_loaded.system()))
-        .insert_resource(SolarPosition {
-            // Stockholm
-            latitude: 59.33258,
-            longitude: 18.0649,
-            // one day per 8 minutes of real time
-            simulation_seconds_per_second: 24.0 * 60.0 * 60.0 / (8.0 * 60.0),
-            ..Default::default()
-        })
+        // one day per 8 minutes of real time
+        .insert_resource(
+            SolarPosition::at_city("Stockholm")
+                .unwrap()
+                .with_day_length(8.0 * 60.0),
+        )
+        .insert_resource(AutoSkyPreset::default())
         .add_plugin(PhysicalSkyPlugin)
+        .add_plugin(SeasonPlugin)
         .add_system(
             update_sun_light_position
                 .system()
                 .label("update_sun_light_position")
                 .after(PHYSICAL_SKY_PASS_TIME_SYSTEM),
         )
+        .add_system(
+            update_ambient_light
+                .system()
+                .after(PHYSICAL_SKY_PASS_TIME_SYSTEM),
+        )
         .add_system_set(SystemSet::on_exit(AppState::Loading).with_system(setup_graphics.system()))
         .add_system_set(
             SystemSet::on_exit(AppState::Loading)
@@ -174,11 +226,14 @@ fn main() {
             ),
         )
         .add_plugin(FogPlugin)
+        .add_plugin(UnderwaterPlugin)
         .run();
 }
 
+const ARRAY_TEXTURE_PATH: &str = "textures/voxel-pack/array_texture.png";
+
 fn load_assets(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let handle = asset_server.load("textures/voxel-pack/array_texture.png");
+    let handle = asset_server.load(ARRAY_TEXTURE_PATH);
     commands.insert_resource(ArrayTexture(handle));
 }
 
@@ -187,10 +242,23 @@ fn check_loaded(
     mut state: ResMut<State<AppState>>,
     handle: Res<ArrayTexture>,
     asset_server: Res<AssetServer>,
+    mut app_exit_events: EventWriter<AppExit>,
 ) {
-    if let bevy::asset::LoadState::Loaded = asset_server.get_load_state(&handle.0) {
-        println!("-> AppState::Preparing");
-        state.set(AppState::Preparing).unwrap();
+    match asset_server.get_load_state(&handle.0) {
+        LoadState::Loaded => {
+            debug!("-> AppState::Preparing");
+            state.set(AppState::Preparing).unwrap();
+        }
+        LoadState::Failed => {
+            // Nothing can be drawn without the array texture, so exit rather than wait forever
+            error!(
+                "Failed to load the array texture from {}/{}",
+                env!("CARGO_MANIFEST_DIR"),
+                ARRAY_TEXTURE_PATH
+            );
+            app_exit_events.send(AppExit);
+        }
+        _ => {}
     }
 }
 
@@ -204,6 +272,7 @@ fn setup_graphics(
     mut pipelines: ResMut<Assets<PipelineDescriptor>>,
     mut shaders: ResMut<Assets<Shader>>,
     mut render_graph: ResMut<RenderGraph>,
+    spawn_config: Res<SpawnConfig>,
 ) {
     // Create a new shader pipeline
     let mut pipeline_descriptor = PipelineDescriptor::default_config(ShaderStages {
@@ -226,24 +295,40 @@ fn setup_graphics(
     // Sky box cube
     commands
         .spawn_bundle(MeshBundle {
+            // Scaled to the camera's far plane by SkyDome
             mesh: meshes.add(Mesh::from(shape::Icosphere {
-                radius: 4900.0,
+                radius: 1.0,
                 subdivisions: 5,
             })),
             render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(pipeline)]),
-            transform: Transform::from_xyz(SPAWN_POINT[0], SPAWN_POINT[1], SPAWN_POINT[2]),
+            transform: Transform::from_translation(spawn_config.position),
             ..Default::default()
         })
-        .insert(material);
-
-    let mut texture = textures.get_mut(&texture_handle.0).unwrap();
-    // Set the texture to tile over the entire quad
-    texture.sampler = SamplerDescriptor {
-        address_mode_u: AddressMode::Repeat,
-        address_mode_v: AddressMode::Repeat,
-        ..Default::default()
-    };
-    texture.reinterpret_stacked_2d_as_array(6);
+        .insert(material)
+        .insert(SkyDome::default());
+
+    if let Some(texture) = textures.get_mut(&texture_handle.0) {
+        // Set the texture to tile over the entire quad
+        texture.sampler = SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            ..Default::default()
+        };
+        // One layer per material, stacked vertically
+        let layers = Voxel::MATERIALS.len() as u32;
+        if texture.size.height % layers == 0 {
+            texture.reinterpret_stacked_2d_as_array(layers);
+        } else {
+            error!(
+                "The array texture is {} pixels high, which cannot be split into {} layers",
+                texture.size.height, layers
+            );
+        }
+    } else {
+        // check_loaded only moves on once the texture is loaded, but it may have been removed
+        // since
+        error!("The array texture is no longer loaded, voxels will be untextured");
+    }
     let mut material = StandardMaterial::from(texture_handle.0.clone());
     material.roughness = 0.6;
     let material_handle = materials.add(material);
@@ -279,8 +364,13 @@ fn setup_player(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    movement_config: Res<MovementConfig>,
+    spawn_config: Res<SpawnConfig>,
+    mut mouse_settings: ResMut<MouseSettings>,
 ) {
-    let spawn_pos = SPAWN_POINT.into();
+    let spawn_pos = spawn_config.position;
+    // The character controller turns the player to face the yaw of the mouse settings
+    mouse_settings.yaw_pitch_roll.x = spawn_config.yaw;
     let obj_scale = Vec3::new(0.465, 1.75, 0.25);
 
     let camera_transform = Mat4::face_toward(Vec3::ZERO, -Vec3::Z, Vec3::Y);
@@ -294,20 +384,23 @@ fn setup_player(
         .spawn_bundle((
             GlobalTransform::identity(),
             Transform::from_translation(spawn_pos),
-            CharacterController {
-                run_speed: 40.0f32,
-                ..Default::default()
-            },
+            movement_config.character_controller(),
             BodyTag,
             PlayerTag,
             DebugTransformTag,
+            GroundCheck {
+                foot_offset: 0.5 * obj_scale.y,
+            },
+            SpawnHold::new(SPAWN_MARGIN, RigidBodyType::Dynamic),
+            JumpTimers::default(),
         ))
         .insert_bundle(RigidBodyBundle {
             activation: RigidBodyActivation {
                 sleeping: true,
                 ..Default::default()
             },
-            body_type: RigidBodyType::Dynamic,
+            // Released by SpawnHold once the ground under the spawn point has a collider
+            body_type: RigidBodyType::Static,
             mass_properties: RigidBodyMassPropsFlags::ROTATION_LOCKED.into(),
             position: RigidBodyPosition {
                 position: spawn_pos.into(),
@@ -400,16 +493,20 @@ fn setup_player(
 fn setup_world(
     mut commands: Commands,
     pool: Res<ComputeTaskPool>,
-    noise_config: Res<NoiseConfig>,
+    terrain_generator: Res<TerrainGenerator>,
+    world_seed: Res<WorldSeed>,
     voxel_map_config: Res<VoxelMapConfig>,
     mesh_commands: ResMut<MeshCommandQueue>,
+    spawn_config: Res<SpawnConfig>,
 ) {
-    let init_lod0_center = PointN(SPAWN_POINT).in_voxel() >> voxel_map_config.chunk_log2;
+    let init_lod0_center =
+        Point3f::from(spawn_config.position).in_voxel() >> voxel_map_config.chunk_log2;
 
     let map = VoxelMap::new(
         &pool,
         &voxel_map_config,
-        &noise_config,
+        &*terrain_generator,
+        *world_seed,
         mesh_commands,
         init_lod0_center,
     );
@@ -422,50 +519,167 @@ fn setup_world(
         .spawn_bundle(HUDCameraBundle::default())
         .insert(WorldAxesPositionTag);
     commands.spawn_bundle(UiCameraBundle::default());
-    commands.spawn_bundle(LightBundle {
-        transform: Transform::from_translation(Vec3::new(
-            SPAWN_POINT[0] + 1000.0,
-            SPAWN_POINT[1] + 512.0,
-            SPAWN_POINT[2] + 3200.0,
-        )),
-        light: Light {
-            color: Color::ANTIQUE_WHITE,
-            intensity: 10000000.0,
-            depth: 0.1..1000000.0,
-            range: 1000000.0,
+    // Both are moved into place by update_sun_light_position
+    commands
+        .spawn_bundle(LightBundle {
+            light: Light {
+                color: Color::ANTIQUE_WHITE,
+                intensity: SUN_LIGHT_INTENSITY,
+                depth: 0.1..1000000.0,
+                range: 1000000.0,
+                ..Default::default()
+            },
             ..Default::default()
-        },
-        ..Default::default()
-    });
+        })
+        .insert(SunLightTag);
+    commands
+        .spawn_bundle(LightBundle {
+            light: Light {
+                color: MOON_LIGHT_COLOR,
+                intensity: 0.0,
+                depth: 0.1..1000000.0,
+                range: 1000000.0,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MoonLightTag);
+}
+
+struct SunLightTag;
+struct MoonLightTag;
+
+const SUN_LIGHT_INTENSITY: f32 = 10000000.0;
+// Real moonlight is hundreds of thousands of times dimmer than sunlight. This is only 20 times
+// dimmer, so that the terrain can still be seen at night.
+const MOON_LIGHT_INTENSITY: f32 = 500000.0;
+const MOON_LIGHT_COLOR: Color = Color::rgb(0.7, 0.75, 0.9);
+// Far enough out that the terrain around the player is lit from one direction
+const LIGHT_DISTANCE: f32 = 4500.0;
+
+/// Ramps a light smoothly from nothing with its source 3 degrees below the horizon to full
+/// intensity at 6 degrees above it
+fn brightness_for_elevation(deg: f64) -> f32 {
+    let t = ((deg + 3.0) / 9.0).max(0.0).min(1.0) as f32;
+    t * t * (3.0 - 2.0 * t)
 }
 
 fn update_sun_light_position(
     solar_position: Res<SolarPosition>,
-    mut query: Query<&mut Transform, With<Light>>,
+    mut lights: QuerySet<(
+        Query<(&mut Transform, &mut Light), With<SunLightTag>>,
+        Query<(&mut Transform, &mut Light), With<MoonLightTag>>,
+    )>,
 ) {
     let (azimuth, inclination) = solar_position.get_azimuth_inclination();
     let (azimuth_radians, inclination_radians) = (
         (azimuth.to_radians() - std::f64::consts::PI) as f32,
         inclination.to_radians() as f32,
     );
-    let translation = Vec3::new(
+    let sun_direction = Vec3::new(
         azimuth_radians.cos(),
         azimuth_radians.sin() * inclination_radians.sin(),
         azimuth_radians.sin() * inclination_radians.cos(),
     )
-    .normalize()
-        * 4500.0;
-    for mut transform in query.iter_mut() {
-        *transform = Transform::from_translation(translation);
+    .normalize();
+    // The moon is opposite the sun, so it rises as the sun sets. In polar day the sun stays at
+    // full intensity and in polar night the moon does.
+    let sun_elevation = solar_position.lighting_inclination();
+    let sun_color = solar_position.sun_color();
+    for (mut transform, mut light) in lights.q0_mut().iter_mut() {
+        *transform = Transform::from_translation(sun_direction * LIGHT_DISTANCE);
+        light.color = sun_color;
+        light.intensity = SUN_LIGHT_INTENSITY * brightness_for_elevation(sun_elevation);
+    }
+    for (mut transform, mut light) in lights.q1_mut().iter_mut() {
+        *transform = Transform::from_translation(-sun_direction * LIGHT_DISTANCE);
+        light.intensity = MOON_LIGHT_INTENSITY * brightness_for_elevation(-sun_elevation);
     }
 }
 
+// The ambient light when the sun is well up, and when it is well down
+const DAY_AMBIENT: ([f32; 3], f32) = ([1.0, 1.0, 1.0], 0.2);
+const NIGHT_AMBIENT: ([f32; 3], f32) = ([0.4, 0.5, 0.8], 0.04);
+
+/// Keeps shadowed terrain and nights legible, fading between a neutral and a dim blue ambient
+/// light over the same elevations as the sun light
+fn update_ambient_light(solar_position: Res<SolarPosition>, mut ambient: ResMut<AmbientLight>) {
+    let t = brightness_for_elevation(solar_position.lighting_inclination());
+    let lerp = |night: f32, day: f32| night + (day - night) * t;
+    let ((night_color, night_brightness), (day_color, day_brightness)) =
+        (NIGHT_AMBIENT, DAY_AMBIENT);
+    ambient.color = Color::rgb(
+        lerp(night_color[0], day_color[0]),
+        lerp(night_color[1], day_color[1]),
+        lerp(night_color[2], day_color[2]),
+    );
+    ambient.brightness = lerp(night_brightness, day_brightness);
+}
+
+/// A corner of the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ScreenCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Pins the world axes gizmo to a corner of the window, `margin` logical pixels in from each edge
+struct WorldAxesPlacement {
+    corner: ScreenCorner,
+    margin: f32,
+}
+
+impl Default for WorldAxesPlacement {
+    fn default() -> Self {
+        Self {
+            corner: ScreenCorner::BottomLeft,
+            margin: 16.0,
+        }
+    }
+}
+
+// The gizmo is drawn `scale` clip-space units from its centre vertically. Clip space is stretched
+// to the window so the horizontal extent is scaled by the aspect ratio to keep it square.
+fn world_axes_placement_system(
+    placement: Res<WorldAxesPlacement>,
+    windows: Res<Windows>,
+    mut resized: EventReader<WindowResized>,
+    mut world_axes: ResMut<WorldAxes>,
+) {
+    let resized = resized.iter().any(|event| event.id == WindowId::primary());
+    if !resized && !placement.is_changed() {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (width, height) = (window.width(), window.height());
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let scale = world_axes.scale;
+    let x = 1.0 - 2.0 * placement.margin / width - scale * height / width;
+    let y = 1.0 - 2.0 * placement.margin / height - scale;
+    let (x, y) = match placement.corner {
+        ScreenCorner::TopLeft => (-x, y),
+        ScreenCorner::TopRight => (x, y),
+        ScreenCorner::BottomLeft => (-x, -y),
+        ScreenCorner::BottomRight => (x, -y),
+    };
+    let z = world_axes.clip_space_position.z;
+    world_axes.clip_space_position = Vec3::new(x, y, z);
+}
+
 fn toggle_debug_system(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut debug: ResMut<Debug>,
     mut world_axes: ResMut<WorldAxes>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::H) {
+    if keyboard_input.just_pressed(key_bindings.toggle_debug) {
         // Use debug.enabled as the source of truth
         let new_state = !debug.enabled;
         debug.enabled = new_state;
@@ -475,19 +689,30 @@ fn toggle_debug_system(
 
 fn toggle_wireframe_system(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut wireframe_config: ResMut<WireframeConfig>,
+    mut lod_wireframe: ResMut<LodWireframe>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::M) {
-        wireframe_config.global = !wireframe_config.global;
+    // Cycles between no wireframe, a white wireframe over everything and LOD-coloured chunk
+    // wireframes
+    if keyboard_input.just_pressed(key_bindings.toggle_wireframe) {
+        let (global, lod) = match (wireframe_config.global, lod_wireframe.enabled) {
+            (false, false) => (true, false),
+            (true, _) => (false, true),
+            (false, true) => (false, false),
+        };
+        wireframe_config.global = global;
+        lod_wireframe.enabled = lod;
     }
 }
 
 fn toggle_third_person(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut camera_transforms: Query<(&mut Transform, &mut ThirdPerson)>,
     mut models: Query<&mut Visible>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::T) {
+    if keyboard_input.just_pressed(key_bindings.toggle_third_person) {
         for (mut camera_transform, mut third_person) in camera_transforms.iter_mut() {
             third_person.is_third_person = !third_person.is_third_person;
             *camera_transform = Transform::from_matrix(if third_person.is_third_person {
@@ -512,3 +737,29 @@ fn toggle_third_person(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_ramps_between_the_elevation_limits() {
+        assert_eq!(brightness_for_elevation(-90.0), 0.0);
+        assert_eq!(brightness_for_elevation(-3.0), 0.0);
+        assert!((brightness_for_elevation(1.5) - 0.5).abs() < 1e-6);
+        assert_eq!(brightness_for_elevation(6.0), 1.0);
+        assert_eq!(brightness_for_elevation(90.0), 1.0);
+    }
+
+    #[test]
+    fn brightness_increases_smoothly() {
+        let mut last = 0.0;
+        for step in 0..=90 {
+            let brightness = brightness_for_elevation(-3.0 + 0.1 * step as f64);
+            assert!(brightness >= last);
+            // The smoothstep is flat at both ends, so there is no pop at either limit
+            assert!(brightness - last < 0.02);
+            last = brightness;
+        }
+    }
+}
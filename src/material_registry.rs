@@ -0,0 +1,80 @@
+use crate::voxel_map::Voxel;
+
+use building_blocks::prelude::*;
+
+/// The array texture layers of the top, sides and bottom of a voxel
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MaterialLayers {
+    pub top: u32,
+    pub side: u32,
+    pub bottom: u32,
+}
+
+impl MaterialLayers {
+    pub fn uniform(layer: u32) -> Self {
+        Self {
+            top: layer,
+            side: layer,
+            bottom: layer,
+        }
+    }
+}
+
+/// Maps each voxel material to the array texture layers that its faces are drawn with, so that
+/// voxel values do not have to follow the order of the layers in the texture
+pub struct MaterialRegistry {
+    // Indexed by the voxel value
+    layers: Vec<Option<MaterialLayers>>,
+}
+
+impl Default for MaterialRegistry {
+    /// Each of `Voxel::MATERIALS` uses its own layer, in order, except that grass only has its
+    /// layer on top and the dirt layer on its sides and bottom
+    fn default() -> Self {
+        let mut registry = Self { layers: Vec::new() };
+        for (layer, material) in Voxel::MATERIALS.iter().enumerate() {
+            registry.set(*material, MaterialLayers::uniform(layer as u32));
+        }
+        if let (Some(grass), Some(dirt)) = (registry.get(Voxel::GRASS), registry.get(Voxel::DIRT)) {
+            registry.set(
+                Voxel::GRASS,
+                MaterialLayers {
+                    top: grass.top,
+                    side: dirt.side,
+                    bottom: dirt.bottom,
+                },
+            );
+        }
+        registry
+    }
+}
+
+impl MaterialRegistry {
+    pub fn set(&mut self, material: Voxel, layers: MaterialLayers) {
+        let index = material.0 as usize;
+        if self.layers.len() <= index {
+            self.layers.resize(index + 1, None);
+        }
+        self.layers[index] = Some(layers);
+    }
+
+    pub fn get(&self, material: Voxel) -> Option<MaterialLayers> {
+        self.layers.get(material.0 as usize).copied().flatten()
+    }
+
+    /// The layer of the face of `material` with the outward `normal`. Materials that have not
+    /// been registered use layer 0.
+    pub fn layer(&self, material: Voxel, normal: Point3i) -> u32 {
+        let layers = match self.get(material) {
+            Some(layers) => layers,
+            None => return 0,
+        };
+        if normal.y() > 0 {
+            layers.top
+        } else if normal.y() < 0 {
+            layers.bottom
+        } else {
+            layers.side
+        }
+    }
+}
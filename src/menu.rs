@@ -0,0 +1,162 @@
+use crate::{app_state::AppState, key_bindings::KeyBindings};
+
+use bevy::prelude::*;
+use bevy_physical_sky::SolarPosition;
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+/// Shows a title screen in `AppState::MainMenu` that moves on to `AppState::Loading`, and pauses
+/// the game by pushing `AppState::Paused` on top of `AppState::Running`.
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeyBindings>()
+            .init_resource::<SolarPausedBeforeMenu>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::MainMenu).with_system(main_menu_setup.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::MainMenu).with_system(main_menu_system.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::MainMenu).with_system(despawn_menu_system.system()),
+            )
+            // Not part of a state's system set, as those can run again in the same frame after a
+            // transition and would undo it while the key is still just pressed
+            .add_system(pause_toggle_system.system())
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(pause_setup.system()))
+            .add_system_set(
+                SystemSet::on_exit(AppState::Paused)
+                    .with_system(resume_cleanup.system())
+                    .with_system(despawn_menu_system.system()),
+            );
+    }
+}
+
+// Entities that only exist while a menu is shown
+struct MenuTag;
+
+// Whether the sun was already paused when the game was paused, e.g. as configured at startup, so
+// that resuming the game leaves it paused
+#[derive(Default)]
+struct SolarPausedBeforeMenu(bool);
+
+fn menu_text_bundle(asset_server: &AssetServer, text: &str) -> TextBundle {
+    TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: Rect {
+                left: Val::Percent(40.0),
+                top: Val::Percent(45.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        text: Text::with_section(
+            text,
+            TextStyle {
+                font: asset_server.load("fonts/FiraMono-Medium.ttf"),
+                font_size: 32.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }
+}
+
+fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    // The game's own UI camera is only spawned once the world is set up
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(MenuTag);
+    commands
+        .spawn_bundle(menu_text_bundle(&*asset_server, "Press Enter to start"))
+        .insert(MenuTag);
+}
+
+fn main_menu_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if keyboard_input.just_pressed(key_bindings.start_game) {
+        debug!("-> AppState::Loading");
+        state.set(AppState::Loading).unwrap();
+    }
+}
+
+fn despawn_menu_system(mut commands: Commands, menu_entities: Query<Entity, With<MenuTag>>) {
+    for entity in menu_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pause_toggle_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if !keyboard_input.just_pressed(key_bindings.toggle_pause) {
+        return;
+    }
+    match state.current() {
+        AppState::Running => state.push(AppState::Paused).unwrap(),
+        AppState::Paused => state.pop().unwrap(),
+        _ => {}
+    }
+}
+
+fn pause_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    solar_position: Option<ResMut<SolarPosition>>,
+    mut solar_paused_before: ResMut<SolarPausedBeforeMenu>,
+) {
+    // The interpolated timestep only accumulates time while the pipeline is active, so physics
+    // picks up where it left off on resume rather than catching up on the paused time
+    rapier_configuration.physics_pipeline_active = false;
+    if let Some(mut solar_position) = solar_position {
+        solar_paused_before.0 = solar_position.paused;
+        solar_position.pause();
+    }
+    commands
+        .spawn_bundle(menu_text_bundle(&*asset_server, "Paused"))
+        .insert(MenuTag);
+}
+
+fn resume_cleanup(
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+    solar_position: Option<ResMut<SolarPosition>>,
+    solar_paused_before: Res<SolarPausedBeforeMenu>,
+) {
+    rapier_configuration.physics_pipeline_active = true;
+    if let Some(mut solar_position) = solar_position {
+        if !solar_paused_before.0 {
+            solar_position.resume();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resuming_leaves_a_sun_that_was_paused_before_paused() {
+        for &paused_before in [false, true].iter() {
+            let mut world = World::default();
+            world.insert_resource(RapierConfiguration::default());
+            world.insert_resource(SolarPausedBeforeMenu(paused_before));
+            let mut solar_position = SolarPosition::default();
+            solar_position.pause();
+            world.insert_resource(solar_position);
+            SystemStage::single(resume_cleanup.system()).run(&mut world);
+            assert_eq!(
+                world.get_resource::<SolarPosition>().unwrap().paused,
+                paused_before
+            );
+        }
+    }
+}
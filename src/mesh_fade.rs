@@ -22,7 +22,10 @@ impl Default for FadeUniform {
     }
 }
 
-const FADE_DURATION: f32 = 1.0;
+pub const FADE_DURATION: f32 = 1.0;
+// Seconds of fade in delay per voxel of distance from the camera, and the longest delay allowed
+const FADE_DELAY_PER_VOXEL: f32 = 1.0 / 2000.0;
+const MAX_FADE_DELAY: f32 = 1.0;
 pub const FADE_IN: FadeUniform = FadeUniform {
     duration: FADE_DURATION,
     remaining: FADE_DURATION,
@@ -36,6 +39,38 @@ pub const FADE_OUT: FadeUniform = FadeUniform {
     fade_in: false,
 };
 
+impl FadeUniform {
+    pub fn with_duration(duration: f32, fade_in: bool) -> Self {
+        Self {
+            duration,
+            remaining: duration,
+            delay: 0.0,
+            fade_in,
+        }
+    }
+
+    /// Delays the start of the fade so that meshes nearer the camera reveal first
+    pub fn with_delay_for_distance(self, distance: f32) -> Self {
+        Self {
+            delay: (distance * FADE_DELAY_PER_VOXEL).clamp(0.0, MAX_FADE_DELAY),
+            ..self
+        }
+    }
+}
+
+/// Fading meshes are blended so they need to be sorted and drawn with the transparent meshes.
+/// They return to being drawn as opaque once they have finished fading in.
+pub fn mesh_fade_transparency_system(
+    mut fades: Query<(&FadeUniform, &mut Visible), Changed<FadeUniform>>,
+) {
+    for (fade, mut visible) in fades.iter_mut() {
+        let is_transparent = !fade.fade_in || fade.remaining > 0.0;
+        if visible.is_transparent != is_transparent {
+            visible.is_transparent = is_transparent;
+        }
+    }
+}
+
 pub fn mesh_fade_update_system(time: Res<Time>, mut fades: Query<&mut FadeUniform>) {
     for mut fade in fades.iter_mut() {
         let mut dt = time.delta_seconds();
@@ -51,3 +86,26 @@ pub fn mesh_fade_update_system(time: Res<Time>, mut fades: Query<&mut FadeUnifor
         fade.remaining = (fade.remaining - dt).clamp(0.0, fade.duration);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_increase_with_distance() {
+        let delays: Vec<f32> = [0.0, 100.0, 500.0, 1000.0]
+            .iter()
+            .map(|distance| FADE_IN.with_delay_for_distance(*distance).delay)
+            .collect();
+        assert_eq!(delays[0], 0.0);
+        assert!(delays.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(FADE_IN.with_delay_for_distance(1.0e6).delay, MAX_FADE_DELAY);
+    }
+
+    #[test]
+    fn delay_keeps_the_fade() {
+        let fade = FadeUniform::with_duration(2.0, false).with_delay_for_distance(100.0);
+        assert_eq!((fade.duration, fade.remaining), (2.0, 2.0));
+        assert!(!fade.fade_in);
+    }
+}
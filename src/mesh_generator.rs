@@ -27,60 +27,208 @@
 use crate::{
     app_state::AppState,
     fog::FogConfig,
-    mesh_fade::{FadeUniform, FADE_IN, FADE_OUT},
-    utilities::bevy_util::thread_local_resource::ThreadLocalResource,
-    voxel_map::{Voxel, VoxelMap},
+    frame_budget::FrameBudget,
+    material_registry::MaterialRegistry,
+    mesh_fade::FadeUniform,
+    season::Season,
+    utilities::bevy_util::{camera::Frustum, thread_local_resource::ThreadLocalResource},
+    voxel_map::{MeshingMode, Voxel, VoxelMap, VoxelMapConfig},
 };
 
 use bevy_mod_bounding::{aabb::Aabb, obb::Obb};
+use bevy_prototype_character_controller::controller::CameraTag;
 use bevy_rapier3d::prelude::{ColliderBundle, ColliderShape, RigidBodyBundle, RigidBodyType};
 use building_blocks::{
     mesh::*,
     prelude::*,
-    storage::{LodChunkKey3, LodChunkUpdate3, SmallKeyHashMap},
+    storage::{LodChunkKey3, LodChunkUpdate3, SmallKeyHashMap, SmallKeyHashSet},
 };
 
 use bevy::{
     asset::prelude::*,
     ecs,
     prelude::*,
-    render::{mesh::Indices, pipeline::PrimitiveTopology},
+    render::{camera::Camera, mesh::Indices, pipeline::PrimitiveTopology},
     tasks::ComputeTaskPool,
 };
-use std::{cell::RefCell, collections::VecDeque};
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+pub struct MeshConfig {
+    /// Coalesce coplanar quads of the same material after greedy meshing
+    pub merge_coplanar_quads: bool,
+    pub collider_mode: ColliderMode,
+    /// Fill the padding of lod0 chunks with the voxels of neighbouring lod0 chunks so that faces
+    /// between them are culled. Neighbours at other LODs still leave the padding empty so that
+    /// there are no holes on LOD boundaries.
+    pub pad_lod0_with_neighbors: bool,
+}
+
+impl Default for MeshConfig {
+    fn default() -> Self {
+        Self {
+            merge_coplanar_quads: true,
+            collider_mode: ColliderMode::Trimesh,
+            pad_lod0_with_neighbors: true,
+        }
+    }
+}
 
-fn max_mesh_creations_per_frame(pool: &ComputeTaskPool) -> usize {
-    40 * pool.thread_num()
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColliderMode {
+    /// A triangle mesh of the chunk's render mesh
+    Trimesh,
+    /// A compound of cuboids covering the opaque voxels, which is cheaper for bodies resting on
+    /// blocky terrain
+    MergedBoxes,
 }
 
 #[derive(Default)]
 pub struct MeshCommandQueue {
     commands: VecDeque<MeshCommand>,
+    // Creates don't depend on the order of other commands so they are kept apart and sorted by
+    // priority, with the most important at the back
+    creates: Vec<LodChunkKey3>,
+    // The keys in `creates`, so that a chunk is only queued for creation once
+    queued_creates: SmallKeyHashSet<LodChunkKey3>,
+    // The keys of the `MeshCommand::Remesh`es in `commands`, so that a chunk is only queued for
+    // re-meshing once
+    queued_remeshes: SmallKeyHashSet<LodChunkKey3>,
+    num_creates_in_frustum: usize,
+    num_created_last_frame: usize,
+    spawn_time_last_frame: Duration,
 }
 
 impl MeshCommandQueue {
     pub fn enqueue(&mut self, command: MeshCommand) {
-        self.commands.push_front(command);
+        match command {
+            MeshCommand::Create(lod_key) => {
+                if self.queued_creates.insert(lod_key) {
+                    self.creates.push(lod_key);
+                }
+            }
+            // A pending re-mesh reads the voxels when it runs, so it will pick up later edits too
+            MeshCommand::Remesh(lod_key) => {
+                if self.queued_remeshes.insert(lod_key) {
+                    self.commands.push_front(command);
+                }
+            }
+            command => self.commands.push_front(command),
+        }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.commands.is_empty()
+        self.commands.is_empty() && self.creates.is_empty()
     }
 
     pub fn len(&self) -> usize {
-        self.commands.len()
+        self.commands.len() + self.creates.len()
+    }
+
+    /// The number of meshes created or re-meshed in the last run of the mesh generator
+    pub fn num_created_last_frame(&self) -> usize {
+        self.num_created_last_frame
+    }
+
+    /// The main thread time spent spawning the entities of the meshes and colliders created in
+    /// the last run of the mesh generator
+    pub fn spawn_time_last_frame(&self) -> Duration {
+        self.spawn_time_last_frame
     }
 
     pub fn clear(&mut self) {
         self.commands.clear();
+        self.creates.clear();
+        self.queued_creates.clear();
+        self.queued_remeshes.clear();
+        self.num_creates_in_frustum = 0;
+    }
+
+    /// Sorts the pending creates so that chunks in the camera frustum come first, nearest first,
+    /// followed by the rest, also nearest first. Without a camera, every chunk counts as visible.
+    fn prioritize_creates(&mut self, voxel_map: &VoxelMap, camera: Option<(Frustum, Vec3)>) {
+        let mut prioritized: Vec<_> = self
+            .creates
+            .drain(..)
+            .map(|lod_key| {
+                let (minimum, maximum) = chunk_world_bounds(voxel_map, lod_key);
+                let (in_frustum, distance_squared) = match camera {
+                    Some((frustum, position)) => (
+                        frustum.intersects_aabb(minimum, maximum),
+                        position.distance_squared(0.5 * (minimum + maximum)),
+                    ),
+                    None => (true, 0.0),
+                };
+                (in_frustum, distance_squared, lod_key)
+            })
+            .collect();
+        prioritized.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then(b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        self.num_creates_in_frustum = prioritized.iter().filter(|p| p.0).count();
+        self.creates = prioritized
+            .into_iter()
+            .map(|(_, _, lod_key)| lod_key)
+            .collect();
+    }
+
+    // A chunk that has been split or merged away must not be meshed by a create that is still
+    // pending.
+    fn cancel_create(&mut self, lod_key: &LodChunkKey3) {
+        if self.queued_creates.remove(lod_key) {
+            self.creates.retain(|key| key != lod_key);
+        }
+    }
+
+    // Drops the `num_run` oldest commands, which are at the back, once they have been run
+    fn remove_run_commands(&mut self, num_run: usize) {
+        let new_length = self.commands.len() - num_run;
+        for command in self.commands.drain(new_length..) {
+            if let MeshCommand::Remesh(lod_key) = command {
+                self.queued_remeshes.remove(&lod_key);
+            }
+        }
+    }
+
+    // The most important create
+    fn pop_create(&mut self) -> Option<LodChunkKey3> {
+        let lod_key = self.creates.pop()?;
+        self.queued_creates.remove(&lod_key);
+        Some(lod_key)
     }
 }
 
+fn chunk_world_bounds(voxel_map: &VoxelMap, lod_key: LodChunkKey3) -> (Vec3, Vec3) {
+    let extent = voxel_map
+        .pyramid
+        .level(lod_key.lod)
+        .indexer
+        .extent_for_chunk_at_key(lod_key.chunk_key);
+    let voxel_size = (1 << lod_key.lod) as f32;
+    let minimum = Vec3::new(
+        extent.minimum.x() as f32,
+        extent.minimum.y() as f32,
+        extent.minimum.z() as f32,
+    ) * voxel_size;
+    let maximum = Vec3::new(
+        extent.least_upper_bound().x() as f32,
+        extent.least_upper_bound().y() as f32,
+        extent.least_upper_bound().z() as f32,
+    ) * voxel_size;
+    (minimum, maximum)
+}
+
 // PERF: try to eliminate the use of multiple Vecs
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum MeshCommand {
     Create(LodChunkKey3),
     Update(LodChunkUpdate3),
+    /// Re-mesh a chunk whose voxels have changed, if it is currently active.
+    Remesh(LodChunkKey3),
 }
 
 #[derive(Default)]
@@ -88,6 +236,13 @@ pub struct ChunkMeshes {
     // Map from chunk key to mesh entity.
     entities: SmallKeyHashMap<LodChunkKey3, (Entity, Handle<Mesh>)>,
     remove_queue: SmallKeyHashMap<LodChunkKey3, (Entity, Handle<Mesh>)>,
+    // Active chunks that were meshed but produced no quads, so have no entity.
+    empty: SmallKeyHashSet<LodChunkKey3>,
+    // Colliders of lod0 chunk entities, kept so that they can be re-attached when the player
+    // comes back within range.
+    collider_shapes: SmallKeyHashMap<LodChunkKey3, ColliderShape>,
+    // Chunks whose entity currently has its collider attached.
+    attached_colliders: SmallKeyHashSet<LodChunkKey3>,
 }
 
 impl ChunkMeshes {
@@ -100,6 +255,19 @@ impl ChunkMeshes {
             clear_up_entity(entity, mesh, commands, meshes);
             false
         });
+        self.empty.clear();
+        self.collider_shapes.clear();
+        self.attached_colliders.clear();
+    }
+
+    /// Whether the entity of the lod0 chunk at `chunk_key` currently has its collider attached
+    pub fn has_lod0_collider(&self, chunk_key: Point3i) -> bool {
+        self.attached_colliders
+            .contains(&LodChunkKey3 { lod: 0, chunk_key })
+    }
+
+    fn is_active(&self, lod_chunk_key: &LodChunkKey3) -> bool {
+        self.entities.contains_key(lod_chunk_key) || self.empty.contains(lod_chunk_key)
     }
 
     pub fn remove_entity(
@@ -114,25 +282,88 @@ impl ChunkMeshes {
     }
 }
 
+/// Attaches colliders to lod0 chunk entities within `collider_radius_chunks` of the camera and
+/// detaches them from those further away. The meshes stay either way.
+pub fn collider_distance_system(
+    mut commands: Commands,
+    cameras: Query<&GlobalTransform, With<CameraTag>>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    mut chunk_meshes: ResMut<ChunkMeshes>,
+) {
+    let camera_position = if let Some(tfm) = cameras.iter().next() {
+        tfm.translation
+    } else {
+        return;
+    };
+    let center = Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
+    let radius = voxel_map_config.collider_radius_chunks;
+
+    let ChunkMeshes {
+        entities,
+        collider_shapes,
+        attached_colliders,
+        ..
+    } = &mut *chunk_meshes;
+    // Entities that were despawned, e.g. when merged into a higher LOD, took their colliders with
+    // them
+    collider_shapes.retain(|key, _| entities.contains_key(key));
+    attached_colliders.retain(|key| entities.contains_key(key));
+
+    for (lod_chunk_key, shape) in collider_shapes.iter() {
+        let (entity, _) = entities[lod_chunk_key];
+        let offset = (lod_chunk_key.chunk_key >> voxel_map_config.chunk_log2) - center;
+        let in_range =
+            offset.x().abs() <= radius && offset.y().abs() <= radius && offset.z().abs() <= radius;
+        let attached = attached_colliders.contains(lod_chunk_key);
+        if in_range && !attached {
+            commands
+                .entity(entity)
+                .insert_bundle(RigidBodyBundle {
+                    body_type: RigidBodyType::Static,
+                    ..Default::default()
+                })
+                .insert_bundle(ColliderBundle {
+                    shape: shape.clone(),
+                    ..Default::default()
+                });
+            attached_colliders.insert(*lod_chunk_key);
+        } else if !in_range && attached {
+            remove_collider(&entity, &mut commands);
+            attached_colliders.remove(lod_chunk_key);
+        }
+    }
+}
+
+fn remove_collider(entity: &Entity, commands: &mut Commands) {
+    commands
+        .entity(*entity)
+        .remove_bundle::<ColliderBundle>()
+        .remove_bundle::<RigidBodyBundle>();
+}
+
 fn clear_up_entity(
     entity: &Entity,
     mesh: &Handle<Mesh>,
     commands: &mut Commands,
     meshes: &mut Assets<Mesh>,
 ) {
-    commands.entity(*entity).despawn();
+    // Recursive so that debug children such as LOD wireframes go with the chunk
+    commands.entity(*entity).despawn_recursive();
     meshes.remove(mesh);
 }
 
 // Utility struct for building the mesh
-#[derive(Debug, Clone)]
-struct MeshBuf {
+#[derive(Clone)]
+pub(crate) struct MeshBuf {
     pub positions: Vec<[f32; 3]>,
     pub normals: Vec<[f32; 3]>,
     pub tex_coords: Vec<[f32; 2]>,
     pub layer: Vec<u32>,
+    pub ao: Vec<f32>,
     pub indices: Vec<u32>,
     pub extent: Extent3i,
+    // Built off the main thread along with the mesh, for lod0 only
+    pub collider: Option<ColliderShape>,
 }
 
 impl Default for MeshBuf {
@@ -142,8 +373,10 @@ impl Default for MeshBuf {
             normals: Vec::new(),
             tex_coords: Vec::new(),
             layer: Vec::new(),
+            ao: Vec::new(),
             indices: Vec::new(),
             extent: Extent3i::from_min_and_shape(PointN([0, 0, 0]), PointN([0, 0, 0])),
+            collider: None,
         }
     }
 }
@@ -156,22 +389,248 @@ impl MeshBuf {
         voxel_size: f32,
         u_flip_face: Axis3,
         layer: u32,
+        ao: [u8; 4],
     ) {
         let start_index = self.positions.len() as u32;
         self.positions
             .extend_from_slice(&face.quad_mesh_positions(quad, voxel_size));
         self.normals.extend_from_slice(&face.quad_mesh_normals());
 
+        // The coordinates already run from 0 to the quad's width and height, so textures tile once
+        // per voxel across merged quads
         let flip_v = true;
         self.tex_coords
             .extend_from_slice(&face.tex_coords(u_flip_face, flip_v, quad));
 
         self.layer.extend_from_slice(&[layer; 4]);
-        self.indices
-            .extend_from_slice(&face.quad_mesh_indices(start_index));
+
+        self.ao
+            .extend(ao.iter().map(|&a| AO_BRIGHTNESS[a as usize]));
+
+        // Triangulate along the diagonal whose corners are less occluded, otherwise the
+        // interpolation across the quad is anisotropic and shows seams between adjacent quads
+        let indices = face.quad_mesh_indices(start_index);
+        if ao[0] + ao[3] > ao[1] + ao[2] {
+            let s = start_index;
+            let counter_clockwise = indices[1] == s + 1;
+            if counter_clockwise {
+                self.indices
+                    .extend_from_slice(&[s, s + 1, s + 3, s, s + 3, s + 2]);
+            } else {
+                self.indices
+                    .extend_from_slice(&[s, s + 3, s + 1, s, s + 2, s + 3]);
+            }
+        } else {
+            self.indices.extend_from_slice(&indices);
+        }
     }
 }
 
+type MeshQuad = (UnorientedQuad, Voxel, [u8; 4]);
+
+// Greedy meshing extends each quad along u and then v, so rows that were interrupted by another
+// material leave same-material quads that share a full edge. This merges those, first along u
+// and then along v. Only unoccluded quads are merged so that the ambient occlusion is unchanged.
+fn merge_coplanar_quads(face: &OrientedCubeFace, quads: Vec<MeshQuad>) -> Vec<MeshQuad> {
+    let (mut mergeable, mut merged): (Vec<_>, Vec<_>) =
+        quads.into_iter().partition(|(_, _, ao)| *ao == [3; 4]);
+    mergeable = merge_quads_along_axis(face, mergeable, true);
+    mergeable = merge_quads_along_axis(face, mergeable, false);
+    merged.append(&mut mergeable);
+    merged
+}
+
+fn merge_quads_along_axis(
+    face: &OrientedCubeFace,
+    mut quads: Vec<MeshQuad>,
+    along_u: bool,
+) -> Vec<MeshQuad> {
+    let (axis, across) = if along_u {
+        (face.u, face.v)
+    } else {
+        (face.v, face.u)
+    };
+    let coord = |p: Point3i, axis: Point3i| p.x() * axis.x() + p.y() * axis.y() + p.z() * axis.z();
+    let length = |quad: &UnorientedQuad| {
+        if along_u {
+            quad.width as i32
+        } else {
+            quad.height as i32
+        }
+    };
+    let breadth = |quad: &UnorientedQuad| {
+        if along_u {
+            quad.height as i32
+        } else {
+            quad.width as i32
+        }
+    };
+
+    // Sorting by plane, row and material leaves mergeable quads adjacent and in order along the
+    // axis
+    quads.sort_by_key(|(quad, mat, _)| {
+        (
+            coord(quad.minimum, face.n),
+            coord(quad.minimum, across),
+            breadth(quad),
+            mat.0,
+            coord(quad.minimum, axis),
+        )
+    });
+    let mut merged: Vec<MeshQuad> = Vec::with_capacity(quads.len());
+    for (quad, mat, ao) in quads.into_iter() {
+        if let Some((last, last_mat, _)) = merged.last_mut() {
+            if *last_mat == mat
+                && coord(last.minimum, face.n) == coord(quad.minimum, face.n)
+                && coord(last.minimum, across) == coord(quad.minimum, across)
+                && breadth(last) == breadth(&quad)
+                && coord(last.minimum, axis) + length(last) == coord(quad.minimum, axis)
+            {
+                if along_u {
+                    last.width += quad.width;
+                } else {
+                    last.height += quad.height;
+                }
+                continue;
+            }
+        }
+        merged.push((quad, mat, ao));
+    }
+    merged
+}
+
+// The material and ambient occlusion of each greedy quad of `face`. Greedy meshing merges voxel
+// faces whatever their occlusion, while the AO is sampled at the corners of the quad it produces,
+// so quads with any occluded voxel face are split into voxel faces with their own AO, and the
+// unoccluded ones merged back up. Snow that comes and goes with the seasons settles on top faces,
+// decided per voxel, so quads that the snow line crosses are split the same way.
+fn face_quads(
+    face: &OrientedCubeFace,
+    quads: &[UnorientedQuad],
+    voxels: &Array3x1<Voxel>,
+    is_seasonal_snow: impl Fn(Point3i) -> bool,
+) -> Vec<MeshQuad> {
+    let is_top = face.n * face.n_sign == PointN([0, 1, 0]);
+    let material = |p: Point3i, mat: Voxel| {
+        if is_top && mat.is_solid() && is_seasonal_snow(p) {
+            Voxel::SNOW
+        } else {
+            mat
+        }
+    };
+    let mut face_quads = Vec::with_capacity(quads.len());
+    for quad in quads.iter() {
+        let mat = voxels.get(quad.minimum);
+        let quad_mat = material(quad.minimum, mat);
+        let is_uniform = voxel_faces(face, quad).all(|unit| {
+            material(unit.minimum, mat) == quad_mat && quad_corner_ao(face, &unit, voxels) == [3; 4]
+        });
+        if is_uniform {
+            face_quads.push((quad.clone(), quad_mat, [3; 4]));
+        } else {
+            let units = voxel_faces(face, quad)
+                .map(|unit| {
+                    let ao = quad_corner_ao(face, &unit, voxels);
+                    (unit, material(unit.minimum, mat), ao)
+                })
+                .collect();
+            face_quads.append(&mut merge_coplanar_quads(face, units));
+        }
+    }
+    face_quads
+}
+
+// The 1x1 quads of each voxel face that `quad` covers
+fn voxel_faces(
+    face: &OrientedCubeFace,
+    quad: &UnorientedQuad,
+) -> impl Iterator<Item = UnorientedQuad> {
+    let (u, v, minimum) = (face.u, face.v, quad.minimum);
+    let (width, height) = (quad.width as i32, quad.height as i32);
+    (0..height).flat_map(move |j| {
+        (0..width).map(move |i| UnorientedQuad {
+            minimum: minimum + u * i + v * j,
+            width: 1,
+            height: 1,
+        })
+    })
+}
+
+// Surface nets meshes have no per-voxel materials, so they are all textured with this one
+const SURFACE_NETS_MATERIAL: Voxel = Voxel::GRASS;
+
+impl MeshBuf {
+    // Texture coordinates are projected along the dominant axis of each normal, one texture per
+    // voxel like the greedy quads.
+    fn from_pos_norm_mesh(
+        mesh: &PosNormMesh,
+        voxel_size: f32,
+        material_registry: &MaterialRegistry,
+    ) -> Option<Self> {
+        if mesh.indices.is_empty() {
+            return None;
+        }
+        let mut mesh_buf = MeshBuf::default();
+        for (position, normal) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            let n = Vec3::from(*normal).normalize_or_zero();
+            let p = Vec3::from(*position) / voxel_size;
+            let (uv, axis) = if n.x.abs() >= n.y.abs() && n.x.abs() >= n.z.abs() {
+                ([p.z, p.y], PointN([n.x.signum() as i32, 0, 0]))
+            } else if n.y.abs() >= n.z.abs() {
+                ([p.x, p.z], PointN([0, n.y.signum() as i32, 0]))
+            } else {
+                ([p.x, p.y], PointN([0, 0, n.z.signum() as i32]))
+            };
+            mesh_buf.positions.push(*position);
+            mesh_buf.normals.push(n.into());
+            mesh_buf.tex_coords.push(uv);
+            mesh_buf
+                .layer
+                .push(material_registry.layer(SURFACE_NETS_MATERIAL, axis));
+            mesh_buf.ao.push(1.0);
+        }
+        mesh_buf.indices = mesh.indices.clone();
+        Some(mesh_buf)
+    }
+}
+
+// Brightness for each ambient occlusion level, from fully occluded to unoccluded
+const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+// Minecraft-style vertex ambient occlusion. For each quad corner, in the order of
+// OrientedCubeFace::quad_corners, looks at the two side voxels and the corner voxel in front of
+// the face and returns 0 (fully occluded) to 3 (unoccluded).
+fn quad_corner_ao(
+    face: &OrientedCubeFace,
+    quad: &UnorientedQuad,
+    voxels: &Array3x1<Voxel>,
+) -> [u8; 4] {
+    let front = quad.minimum + face.n * face.n_sign;
+    let u_max = face.u * (quad.width as i32 - 1);
+    let v_max = face.v * (quad.height as i32 - 1);
+    let corners = [
+        (Point3i::ZERO, -face.u, -face.v),
+        (u_max, face.u, -face.v),
+        (v_max, -face.u, face.v),
+        (u_max + v_max, face.u, face.v),
+    ];
+    let is_solid = |p: Point3i| voxels.extent().contains(p) && voxels.get(p).is_solid();
+
+    let mut ao = [3; 4];
+    for (corner_ao, (offset, u_out, v_out)) in ao.iter_mut().zip(corners.iter()) {
+        let p = front + *offset;
+        let side1 = is_solid(p + *u_out);
+        let side2 = is_solid(p + *v_out);
+        let corner = is_solid(p + *u_out + *v_out);
+        *corner_ao = if side1 && side2 {
+            0
+        } else {
+            3 - (side1 as u8 + side2 as u8 + corner as u8)
+        };
+    }
+    ao
+}
+
 pub struct ArrayTextureMaterial(pub Handle<StandardMaterial>);
 pub struct ArrayTexturePipelines(pub RenderPipelines);
 
@@ -181,33 +640,68 @@ pub fn mesh_generator_system(
     pool: Res<ComputeTaskPool>,
     voxel_map: Res<VoxelMap>,
     local_mesh_buffers: ecs::system::Local<ThreadLocalMeshBuffers>,
+    mesh_config: Res<MeshConfig>,
+    material_registry: Res<MaterialRegistry>,
+    voxel_map_config: Res<VoxelMapConfig>,
+    season: Option<Res<Season>>,
+    frame_budget: Res<FrameBudget>,
     mut mesh_commands: ResMut<MeshCommandQueue>,
     mut mesh_assets: ResMut<Assets<Mesh>>,
     mut chunk_meshes: ResMut<ChunkMeshes>,
     array_texture_pipelines: Res<ArrayTexturePipelines>,
     array_texture_material: Res<ArrayTextureMaterial>,
     mut state: ResMut<State<AppState>>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CameraTag>>,
 ) {
+    let span = info_span!("mesh_generator_system");
+    let _guard = span.enter();
     let first_run = chunk_meshes.entities.is_empty();
+    let camera = cameras
+        .iter()
+        .next()
+        .map(|(camera, tfm)| (Frustum::from_camera(camera, tfm), tfm.translation));
+    mesh_commands.prioritize_creates(&*voxel_map, camera);
+    // A freshly generated map is revealed outwards from the camera instead of all at once
+    let reveal_origin = if first_run {
+        camera.map(|(_, position)| position)
+    } else {
+        None
+    };
     let new_chunk_meshes = apply_mesh_commands(
         &*voxel_map,
         &*local_mesh_buffers,
+        &*mesh_config,
+        &*material_registry,
+        &*voxel_map_config,
+        season.as_deref(),
+        &*frame_budget,
         &*pool,
         &mut *mesh_commands,
         &mut *chunk_meshes,
         &mut commands,
         first_run,
     );
-    spawn_mesh_entities(
-        new_chunk_meshes,
-        &mut commands,
-        &mut *mesh_assets,
-        &mut *chunk_meshes,
-        &*array_texture_pipelines,
-        &*array_texture_material,
-    );
-    if first_run {
-        println!("MESHES GENERATED!\n-> AppState::Running");
+    mesh_commands.num_created_last_frame = new_chunk_meshes.len();
+    {
+        let spawn_span = info_span!("spawn_mesh_entities", count = new_chunk_meshes.len());
+        let _guard = spawn_span.enter();
+        let spawn_start = Instant::now();
+        spawn_mesh_entities(
+            new_chunk_meshes,
+            &mut commands,
+            &mut *mesh_assets,
+            &mut *chunk_meshes,
+            &*array_texture_pipelines,
+            &*array_texture_material,
+            &*voxel_map_config,
+            &*voxel_map,
+            reveal_origin,
+        );
+        mesh_commands.spawn_time_last_frame = spawn_start.elapsed();
+    }
+    // The meshes are also all cleared when the map is remeshed or regenerated while running
+    if first_run && state.current() != &AppState::Running {
+        debug!("Meshes generated, -> AppState::Running");
         state.set(AppState::Running).unwrap();
     }
 }
@@ -215,45 +709,60 @@ pub fn mesh_generator_system(
 fn apply_mesh_commands(
     voxel_map: &VoxelMap,
     local_mesh_buffers: &ThreadLocalMeshBuffers,
+    mesh_config: &MeshConfig,
+    material_registry: &MaterialRegistry,
+    voxel_map_config: &VoxelMapConfig,
+    season: Option<&Season>,
+    frame_budget: &FrameBudget,
     pool: &ComputeTaskPool,
     mesh_commands: &mut MeshCommandQueue,
     chunk_meshes: &mut ChunkMeshes,
     commands: &mut Commands,
     first_run: bool,
 ) -> Vec<(LodChunkKey3, Option<MeshBuf>)> {
-    let num_chunks_to_mesh = mesh_commands.len().min(max_mesh_creations_per_frame(pool));
+    let num_chunks_to_mesh = mesh_commands
+        .len()
+        .min(frame_budget.creations_per_frame(pool));
+    let meshing_mode = voxel_map_config.meshing_mode;
+    let lod0_keys = if mesh_config.pad_lod0_with_neighbors {
+        Some(lod0_mesh_keys(mesh_commands, chunk_meshes))
+    } else {
+        None
+    };
+    let lod0_keys = lod0_keys.as_ref();
 
-    let mut num_creates = 0;
     let mut num_updates = 0;
+    let mut num_remeshes = 0;
     pool.scope(|s| {
         let mut num_meshes_created = 0;
+        let mut cancelled_creates = Vec::new();
+        let mut remesh_keys = Vec::new();
+        // lod0 chunks that appear or disappear change the padding of their lod0 neighbours
+        let mut changed_lod0_keys = Vec::new();
         for command in mesh_commands.commands.iter().rev().cloned() {
             match command {
-                MeshCommand::Create(lod_key) => {
-                    if !chunk_meshes.entities.contains_key(&lod_key) {
-                        num_creates += 1;
-                        num_meshes_created += 1;
-                        s.spawn(async move {
-                            (
-                                lod_key,
-                                create_mesh_for_chunk(lod_key, voxel_map, local_mesh_buffers),
-                            )
-                        });
-                    }
-                }
+                MeshCommand::Create(_) => unreachable!("Creates are queued separately"),
                 MeshCommand::Update(update) => {
                     num_updates += 1;
                     match update {
                         LodChunkUpdate3::Split(split) => {
+                            cancelled_creates.push(split.old_chunk);
+                            chunk_meshes.empty.remove(&split.old_chunk);
                             if let Some((entity, mesh)) =
                                 chunk_meshes.entities.remove(&split.old_chunk)
                             {
                                 chunk_meshes
                                     .remove_queue
                                     .insert(split.old_chunk, (entity, mesh));
-                                commands.entity(entity).insert(FADE_OUT);
+                                commands.entity(entity).insert(FadeUniform::with_duration(
+                                    voxel_map_config.fade_duration_for_lod(split.old_chunk.lod),
+                                    false,
+                                ));
                             }
                             for &lod_key in split.new_chunks.iter() {
+                                if lod_key.lod == 0 {
+                                    changed_lod0_keys.push(lod_key.chunk_key);
+                                }
                                 if !chunk_meshes.entities.contains_key(&lod_key) {
                                     num_meshes_created += 1;
                                     s.spawn(async move {
@@ -263,6 +772,11 @@ fn apply_mesh_commands(
                                                 lod_key,
                                                 voxel_map,
                                                 local_mesh_buffers,
+                                                mesh_config,
+                                                material_registry,
+                                                meshing_mode,
+                                                lod0_keys,
+                                                season,
                                             ),
                                         )
                                     });
@@ -271,10 +785,18 @@ fn apply_mesh_commands(
                         }
                         LodChunkUpdate3::Merge(merge) => {
                             for lod_key in merge.old_chunks.iter() {
+                                if lod_key.lod == 0 {
+                                    changed_lod0_keys.push(lod_key.chunk_key);
+                                }
+                                cancelled_creates.push(*lod_key);
+                                chunk_meshes.empty.remove(lod_key);
                                 if let Some((entity, mesh)) = chunk_meshes.entities.remove(lod_key)
                                 {
                                     chunk_meshes.remove_queue.insert(*lod_key, (entity, mesh));
-                                    commands.entity(entity).insert(FADE_OUT);
+                                    commands.entity(entity).insert(FadeUniform::with_duration(
+                                        voxel_map_config.fade_duration_for_lod(lod_key.lod),
+                                        false,
+                                    ));
                                 }
                             }
                             if !chunk_meshes.entities.contains_key(&merge.new_chunk) {
@@ -286,6 +808,11 @@ fn apply_mesh_commands(
                                             merge.new_chunk,
                                             voxel_map,
                                             local_mesh_buffers,
+                                            mesh_config,
+                                            material_registry,
+                                            meshing_mode,
+                                            lod0_keys,
+                                            season,
                                         ),
                                     )
                                 });
@@ -293,14 +820,84 @@ fn apply_mesh_commands(
                         }
                     }
                 }
+                MeshCommand::Remesh(lod_key) => {
+                    num_remeshes += 1;
+                    if chunk_meshes.is_active(&lod_key) {
+                        num_meshes_created += 1;
+                        remesh_keys.push(lod_key);
+                    }
+                }
             }
             if !first_run && num_meshes_created >= num_chunks_to_mesh {
                 break;
             }
         }
+        // A later split or merge in the same batch may have retired a chunk that was queued for
+        // re-meshing, and its mesh would be spawned as an entity outside of the clipmap
+        for lod_key in remesh_keys.into_iter() {
+            if chunk_meshes.is_active(&lod_key) {
+                s.spawn(async move {
+                    (
+                        lod_key,
+                        create_mesh_for_chunk(
+                            lod_key,
+                            voxel_map,
+                            local_mesh_buffers,
+                            mesh_config,
+                            material_registry,
+                            meshing_mode,
+                            lod0_keys,
+                            season,
+                        ),
+                    )
+                });
+            }
+        }
+
+        mesh_commands.remove_run_commands(num_updates + num_remeshes);
+        for lod_key in cancelled_creates.iter() {
+            mesh_commands.cancel_create(lod_key);
+        }
+        if lod0_keys.is_some() {
+            let chunk_shape = voxel_map.pyramid.chunk_shape();
+            for chunk_key in lod0_face_neighbors(&changed_lod0_keys, chunk_shape).into_iter() {
+                let lod_key = LodChunkKey3 { lod: 0, chunk_key };
+                if chunk_meshes.is_active(&lod_key) {
+                    mesh_commands.enqueue(MeshCommand::Remesh(lod_key));
+                }
+            }
+        }
 
-        let new_length = mesh_commands.len() - (num_creates + num_updates);
-        mesh_commands.commands.truncate(new_length);
+        // On the first run, everything in view is meshed before the app starts running and the
+        // rest is left to later frames
+        let creates_budget = if first_run {
+            mesh_commands.num_creates_in_frustum
+        } else {
+            num_chunks_to_mesh.saturating_sub(num_meshes_created)
+        };
+        for _ in 0..creates_budget {
+            let lod_key = match mesh_commands.pop_create() {
+                Some(lod_key) => lod_key,
+                None => break,
+            };
+            if !chunk_meshes.entities.contains_key(&lod_key) {
+                s.spawn(async move {
+                    (
+                        lod_key,
+                        create_mesh_for_chunk(
+                            lod_key,
+                            voxel_map,
+                            local_mesh_buffers,
+                            mesh_config,
+                            material_registry,
+                            meshing_mode,
+                            lod0_keys,
+                            season,
+                        ),
+                    )
+                });
+            }
+        }
     })
 }
 
@@ -313,21 +910,249 @@ pub fn mesh_despawn_system(
     for (fade, lod_chunk_key) in query.iter() {
         if !fade.fade_in && fade.remaining == 0.0 {
             if let Some((entity, mesh)) = chunk_meshes.remove_queue.remove(lod_chunk_key) {
-                commands.entity(entity).despawn();
+                commands.entity(entity).despawn_recursive();
                 meshes.remove(&mesh);
             }
         }
     }
 }
 
+// The keys of the lod0 chunks that share a face with any of `chunk_keys`, other than those
+fn lod0_face_neighbors(chunk_keys: &[Point3i], chunk_shape: Point3i) -> SmallKeyHashSet<Point3i> {
+    let mut neighbors = SmallKeyHashSet::default();
+    for chunk_key in chunk_keys.iter() {
+        for offset in [
+            PointN([1, 0, 0]),
+            PointN([-1, 0, 0]),
+            PointN([0, 1, 0]),
+            PointN([0, -1, 0]),
+            PointN([0, 0, 1]),
+            PointN([0, 0, -1]),
+        ]
+        .iter()
+        {
+            neighbors.insert(*chunk_key + *offset * chunk_shape);
+        }
+    }
+    for chunk_key in chunk_keys.iter() {
+        neighbors.remove(chunk_key);
+    }
+    neighbors
+}
+
+// The lod0 chunks that are or will soon be meshed at lod0, so that their voxels can be used as the
+// padding of neighbouring lod0 chunks
+fn lod0_mesh_keys(
+    mesh_commands: &MeshCommandQueue,
+    chunk_meshes: &ChunkMeshes,
+) -> SmallKeyHashSet<Point3i> {
+    let mut keys = SmallKeyHashSet::default();
+    let mut insert = |lod_key: &LodChunkKey3| {
+        if lod_key.lod == 0 {
+            keys.insert(lod_key.chunk_key);
+        }
+    };
+    chunk_meshes.entities.keys().for_each(&mut insert);
+    mesh_commands.creates.iter().for_each(&mut insert);
+    for command in mesh_commands.commands.iter() {
+        if let MeshCommand::Update(LodChunkUpdate3::Split(split)) = command {
+            split.new_chunks.iter().for_each(&mut insert);
+        }
+    }
+    keys
+}
+
 fn create_mesh_for_chunk(
     key: LodChunkKey3,
     voxel_map: &VoxelMap,
     local_mesh_buffers: &ThreadLocalMeshBuffers,
+    mesh_config: &MeshConfig,
+    material_registry: &MaterialRegistry,
+    meshing_mode: MeshingMode,
+    lod0_keys: Option<&SmallKeyHashSet<Point3i>>,
+    season: Option<&Season>,
 ) -> Option<MeshBuf> {
-    let chunks = voxel_map.pyramid.level(key.lod);
+    let span = info_span!("create_mesh_for_chunk", lod = key.lod, chunk_key = ?key.chunk_key);
+    let _guard = span.enter();
+    let mut mesh_buf = mesh_chunk(
+        key,
+        voxel_map,
+        local_mesh_buffers,
+        mesh_config,
+        material_registry,
+        meshing_mode,
+        lod0_keys,
+        season,
+    )?;
+    if key.lod == 0 {
+        mesh_buf.collider = match mesh_config.collider_mode {
+            ColliderMode::Trimesh => Some(trimesh_collider(&mesh_buf)),
+            ColliderMode::MergedBoxes => voxel_map
+                .pyramid
+                .level(0)
+                .get_chunk(key.chunk_key)
+                .and_then(merged_boxes_collider),
+        };
+    }
+    Some(mesh_buf)
+}
 
-    let chunk_extent = chunks.indexer.extent_for_chunk_at_key(key.chunk_key);
+/// Greedy quad meshes of the given lod0 chunks, with the faces between them culled. Each is passed
+/// to `f` as soon as it is built so that callers do not need to hold them all.
+pub(crate) fn for_each_lod0_chunk_mesh(
+    voxel_map: &VoxelMap,
+    chunk_keys: &SmallKeyHashSet<Point3i>,
+    mesh_config: &MeshConfig,
+    material_registry: &MaterialRegistry,
+    mut f: impl FnMut(&MeshBuf),
+) {
+    let local_mesh_buffers = ThreadLocalMeshBuffers::new();
+    let lod0_keys = if mesh_config.pad_lod0_with_neighbors {
+        Some(chunk_keys)
+    } else {
+        None
+    };
+    for chunk_key in chunk_keys.iter() {
+        if let Some(mesh_buf) = mesh_chunk(
+            LodChunkKey3 {
+                lod: 0,
+                chunk_key: *chunk_key,
+            },
+            voxel_map,
+            &local_mesh_buffers,
+            mesh_config,
+            material_registry,
+            MeshingMode::GreedyQuads,
+            lod0_keys,
+            None,
+        ) {
+            f(&mesh_buf);
+        }
+    }
+}
+
+/// The collider that mesh_generator_system builds for the lod0 chunk at `chunk_key` with the
+/// `collider_mode` of `mesh_config`, e.g. to compare the modes outside of the app
+pub fn lod0_chunk_collider(
+    voxel_map: &VoxelMap,
+    chunk_key: Point3i,
+    mesh_config: &MeshConfig,
+    material_registry: &MaterialRegistry,
+) -> Option<ColliderShape> {
+    create_mesh_for_chunk(
+        LodChunkKey3 { lod: 0, chunk_key },
+        voxel_map,
+        &ThreadLocalMeshBuffers::new(),
+        mesh_config,
+        material_registry,
+        MeshingMode::GreedyQuads,
+        None,
+        None,
+    )
+    .and_then(|mesh_buf| mesh_buf.collider)
+}
+
+fn trimesh_collider(mesh_buf: &MeshBuf) -> ColliderShape {
+    let collider_vertices = mesh_buf
+        .positions
+        .iter()
+        .map(|p| bevy_rapier3d::rapier::math::Point::from_slice(p))
+        .collect();
+    let collider_indices: Vec<[u32; 3]> = mesh_buf
+        .indices
+        .chunks(3)
+        .map(|i| [i[0], i[1], i[2]])
+        .collect();
+    ColliderShape::trimesh(collider_vertices, collider_indices)
+}
+
+// Greedily grows boxes of solid voxels along x, then z, then y, and builds a compound of
+// cuboids from them.
+fn merged_boxes_collider(chunk: &Array3x1<Voxel>) -> Option<ColliderShape> {
+    let extent = *chunk.extent();
+    let min = extent.minimum;
+    let shape = extent.shape;
+    let index = |p: Point3i| {
+        let d = p - min;
+        (d.x() + shape.x() * (d.z() + shape.z() * d.y())) as usize
+    };
+    let mut solid = vec![false; extent.num_points() as usize];
+    chunk.for_each(&extent, |p: Point3i, voxel: Voxel| {
+        solid[index(p)] = voxel.is_solid();
+    });
+
+    let mut boxes = Vec::new();
+    for y in 0..shape.y() {
+        for z in 0..shape.z() {
+            for x in 0..shape.x() {
+                let start = min + PointN([x, y, z]);
+                if !solid[index(start)] {
+                    continue;
+                }
+                let is_solid_row = |solid: &[bool], y: i32, z: i32, x0: i32, x1: i32| {
+                    (x0..x1).all(|x| solid[index(min + PointN([x, y, z]))])
+                };
+                let mut x_end = x + 1;
+                while x_end < shape.x() && solid[index(min + PointN([x_end, y, z]))] {
+                    x_end += 1;
+                }
+                let mut z_end = z + 1;
+                while z_end < shape.z() && is_solid_row(&solid, y, z_end, x, x_end) {
+                    z_end += 1;
+                }
+                let mut y_end = y + 1;
+                while y_end < shape.y()
+                    && (z..z_end).all(|z| is_solid_row(&solid, y_end, z, x, x_end))
+                {
+                    y_end += 1;
+                }
+                for by in y..y_end {
+                    for bz in z..z_end {
+                        for bx in x..x_end {
+                            solid[index(min + PointN([bx, by, bz]))] = false;
+                        }
+                    }
+                }
+
+                let half_extents =
+                    0.5 * Vec3::new((x_end - x) as f32, (y_end - y) as f32, (z_end - z) as f32);
+                let center = Vec3::new(
+                    (min.x() + x) as f32,
+                    (min.y() + y) as f32,
+                    (min.z() + z) as f32,
+                ) + half_extents;
+                boxes.push((
+                    bevy_rapier3d::rapier::math::Isometry::translation(
+                        center.x, center.y, center.z,
+                    ),
+                    ColliderShape::cuboid(half_extents.x, half_extents.y, half_extents.z),
+                ));
+            }
+        }
+    }
+
+    if boxes.is_empty() {
+        None
+    } else {
+        Some(ColliderShape::compound(boxes))
+    }
+}
+
+fn mesh_chunk(
+    key: LodChunkKey3,
+    voxel_map: &VoxelMap,
+    local_mesh_buffers: &ThreadLocalMeshBuffers,
+    mesh_config: &MeshConfig,
+    material_registry: &MaterialRegistry,
+    meshing_mode: MeshingMode,
+    lod0_keys: Option<&SmallKeyHashSet<Point3i>>,
+    season: Option<&Season>,
+) -> Option<MeshBuf> {
+    let chunk_extent = voxel_map
+        .pyramid
+        .level(key.lod)
+        .indexer
+        .extent_for_chunk_at_key(key.chunk_key);
     let padded_chunk_extent = padded_greedy_quads_chunk_extent(&chunk_extent);
 
     // Keep a thread-local cache of buffers to avoid expensive reallocations every time we want to mesh a chunk.
@@ -340,21 +1165,67 @@ fn create_mesh_for_chunk(
                     RIGHT_HANDED_Y_UP_CONFIG.quad_groups(),
                 ),
                 neighborhood_buffer: Array3x1::fill(padded_chunk_extent, Voxel::EMPTY),
+                surface_nets_buffer: SurfaceNetsBuffer::default(),
+                sdf_buffer: Array3x1::fill(padded_chunk_extent, 1.0),
+                padding_dirty: false,
             })
         })
         .borrow_mut();
     let LocalSurfaceNetsBuffers {
         mesh_buffer,
         neighborhood_buffer,
+        surface_nets_buffer,
+        sdf_buffer,
+        padding_dirty,
     } = &mut *surface_nets_buffers;
 
     // While the chunk shape doesn't change, we need to make sure that it's in the right position for each particular chunk.
     neighborhood_buffer.set_minimum(padded_chunk_extent.minimum);
 
+    if *padding_dirty {
+        neighborhood_buffer.fill_extent(&padded_chunk_extent, Voxel::EMPTY);
+        *padding_dirty = false;
+    }
     // Only copy the chunk_extent, leaving the padding empty so that we don't get holes on LOD boundaries.
-    copy_extent(&chunk_extent, chunks, neighborhood_buffer);
+    voxel_map.copy_chunk(key, neighborhood_buffer);
+    // Except between lod0 neighbours, where the faces between them must be culled
+    if let (0, Some(lod0_keys)) = (key.lod, lod0_keys) {
+        let lod0 = voxel_map.pyramid.level(0);
+        for neighbor_key in lod0.indexer.chunk_keys_for_extent(&padded_chunk_extent) {
+            if neighbor_key != key.chunk_key && lod0_keys.contains(&neighbor_key) {
+                let neighbor_extent = lod0.indexer.extent_for_chunk_at_key(neighbor_key);
+                copy_extent(
+                    &padded_chunk_extent.intersection(&neighbor_extent),
+                    lod0,
+                    neighborhood_buffer,
+                );
+                *padding_dirty = true;
+            }
+        }
+    }
 
     let voxel_size = (1 << key.lod) as f32;
+    if meshing_mode == MeshingMode::SurfaceNets {
+        sdf_buffer.set_minimum(padded_chunk_extent.minimum);
+        sdf_buffer.for_each_mut(&padded_chunk_extent, |p: Point3i, d: &mut f32| {
+            *d = if neighborhood_buffer.get(p) == Voxel::EMPTY {
+                1.0
+            } else {
+                -1.0
+            };
+        });
+        surface_nets(
+            &*sdf_buffer,
+            &padded_chunk_extent,
+            voxel_size,
+            &mut *surface_nets_buffer,
+        );
+        let mut mesh_buf =
+            MeshBuf::from_pos_norm_mesh(&surface_nets_buffer.mesh, voxel_size, material_registry)?;
+        mesh_buf.extent = chunk_extent * voxel_map.pyramid.chunk_shape();
+        return Some(mesh_buf);
+    }
+
     greedy_quads(neighborhood_buffer, &padded_chunk_extent, &mut *mesh_buffer);
 
     if mesh_buffer.num_quads() == 0 {
@@ -362,15 +1233,28 @@ fn create_mesh_for_chunk(
     } else {
         let mut mesh_buf = MeshBuf::default();
         mesh_buf.extent = chunk_extent * voxel_map.pyramid.chunk_shape();
+        let lod_scale = 1 << key.lod;
+        let is_seasonal_snow =
+            |p: Point3i| season.map_or(false, |season| season.is_snow(p * lod_scale));
         for group in mesh_buffer.quad_groups.iter() {
-            for quad in group.quads.iter() {
-                let mat = neighborhood_buffer.get(quad.minimum);
+            let mut quads = face_quads(
+                &group.face,
+                &group.quads,
+                neighborhood_buffer,
+                &is_seasonal_snow,
+            );
+            if mesh_config.merge_coplanar_quads {
+                quads = merge_coplanar_quads(&group.face, quads);
+            }
+            let normal = group.face.n * group.face.n_sign;
+            for (quad, mat, ao) in quads.iter() {
                 mesh_buf.add_quad(
                     &group.face,
                     quad,
                     voxel_size,
                     RIGHT_HANDED_Y_UP_CONFIG.u_flip_face,
-                    mat.0 as u32 - 1,
+                    material_registry.layer(*mat, normal),
+                    *ao,
                 );
             }
         }
@@ -386,6 +1270,10 @@ type ThreadLocalMeshBuffers = ThreadLocalResource<RefCell<LocalSurfaceNetsBuffer
 pub struct LocalSurfaceNetsBuffers {
     mesh_buffer: GreedyQuadsBuffer,
     neighborhood_buffer: Array3x1<Voxel>,
+    surface_nets_buffer: SurfaceNetsBuffer,
+    sdf_buffer: Array3x1<f32>,
+    // Whether neighbouring voxels were copied into the padding of neighborhood_buffer
+    padding_dirty: bool,
 }
 
 fn spawn_mesh_entities(
@@ -395,8 +1283,27 @@ fn spawn_mesh_entities(
     chunk_meshes: &mut ChunkMeshes,
     array_texture_pipelines: &ArrayTexturePipelines,
     array_texture_material: &ArrayTextureMaterial,
+    voxel_map_config: &VoxelMapConfig,
+    voxel_map: &VoxelMap,
+    reveal_origin: Option<Vec3>,
 ) {
     for (lod_chunk_key, item) in new_chunk_meshes.into_iter() {
+        let fade = FadeUniform::with_duration(
+            voxel_map_config.fade_duration_for_lod(lod_chunk_key.lod),
+            true,
+        );
+        // Re-meshed chunks replace their old mesh in place rather than fading in again
+        let fade = if chunk_meshes.entities.contains_key(&lod_chunk_key) {
+            FadeUniform {
+                remaining: 0.0,
+                ..fade
+            }
+        } else if let Some(origin) = reveal_origin {
+            let (minimum, maximum) = chunk_world_bounds(voxel_map, lod_chunk_key);
+            fade.with_delay_for_distance(origin.distance(0.5 * (minimum + maximum)))
+        } else {
+            fade
+        };
         let old_mesh = if let Some(mesh_buf) = item {
             if mesh_buf.indices.is_empty() {
                 None
@@ -408,15 +1315,18 @@ fn spawn_mesh_entities(
                     normals,
                     tex_coords,
                     layer,
+                    ao,
                     indices,
                     extent,
+                    collider,
                 } = mesh_buf;
 
-                render_mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions.clone());
+                render_mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
                 render_mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
                 render_mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, tex_coords);
                 render_mesh.set_attribute("Vertex_Layer", layer);
-                render_mesh.set_indices(Some(Indices::U32(indices.clone())));
+                render_mesh.set_attribute("Vertex_AO", ao);
+                render_mesh.set_indices(Some(Indices::U32(indices)));
 
                 let mesh_handle = mesh_assets.add(render_mesh);
 
@@ -438,7 +1348,7 @@ fn spawn_mesh_entities(
                         ..Default::default()
                     })
                     .insert_bundle((
-                        FADE_IN,
+                        fade,
                         lod_chunk_key,
                         Obb::from_aabb_orientation(
                             Aabb::from_extents(minimum, maximum),
@@ -448,35 +1358,342 @@ fn spawn_mesh_entities(
                     ))
                     .id();
 
-                if lod_chunk_key.lod == 0 {
-                    let collider_vertices = positions
-                        .iter()
-                        .cloned()
-                        .map(|p| bevy_rapier3d::rapier::math::Point::from_slice(&p))
-                        .collect();
-                    let collider_indices: Vec<[u32; 3]> =
-                        indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect();
-
-                    commands
-                        .entity(entity)
-                        .insert_bundle(RigidBodyBundle {
-                            body_type: RigidBodyType::Static,
-                            ..Default::default()
-                        })
-                        .insert_bundle(ColliderBundle {
-                            shape: ColliderShape::trimesh(collider_vertices, collider_indices),
-                            ..Default::default()
-                        });
+                // The new entity has no collider yet, collider_distance_system attaches it if the
+                // chunk is in range
+                chunk_meshes.attached_colliders.remove(&lod_chunk_key);
+                if let Some(collider) = collider {
+                    chunk_meshes.collider_shapes.insert(lod_chunk_key, collider);
+                } else {
+                    chunk_meshes.collider_shapes.remove(&lod_chunk_key);
                 }
+                chunk_meshes.empty.remove(&lod_chunk_key);
                 chunk_meshes
                     .entities
                     .insert(lod_chunk_key, (entity, mesh_handle))
             }
         } else {
+            chunk_meshes.empty.insert(lod_chunk_key);
             chunk_meshes.entities.remove(&lod_chunk_key)
         };
-        if let Some((entity, _mesh)) = old_mesh {
-            commands.entity(entity).insert(FADE_OUT);
+        if let Some((entity, mesh)) = old_mesh {
+            clear_up_entity(&entity, &mesh, commands, mesh_assets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The merged quads of each face of `voxels`, which are padded by one empty voxel on every side
+    fn mesh_quads(
+        extent: Extent3i,
+        voxels: &[(Extent3i, Voxel)],
+    ) -> Vec<(OrientedCubeFace, Vec<MeshQuad>)> {
+        mesh_quads_with_snow(extent, voxels, |_| false)
+    }
+
+    fn mesh_quads_with_snow(
+        extent: Extent3i,
+        voxels: &[(Extent3i, Voxel)],
+        is_seasonal_snow: impl Fn(Point3i) -> bool,
+    ) -> Vec<(OrientedCubeFace, Vec<MeshQuad>)> {
+        let padded_extent = extent.padded(1);
+        let mut array = Array3x1::fill(padded_extent, Voxel::EMPTY);
+        for (voxel_extent, voxel) in voxels.iter() {
+            array.fill_extent(voxel_extent, *voxel);
+        }
+        let mut buffer =
+            GreedyQuadsBuffer::new(padded_extent, RIGHT_HANDED_Y_UP_CONFIG.quad_groups());
+        greedy_quads(&array, &padded_extent, &mut buffer);
+        buffer
+            .quad_groups
+            .iter()
+            .map(|group| {
+                let quads = face_quads(&group.face, &group.quads, &array, &is_seasonal_snow);
+                (group.face.clone(), merge_coplanar_quads(&group.face, quads))
+            })
+            .collect()
+    }
+
+    fn top_face() -> OrientedCubeFace {
+        RIGHT_HANDED_Y_UP_CONFIG
+            .quad_groups()
+            .iter()
+            .map(|group| group.face.clone())
+            .find(|face| face.n * face.n_sign == PointN([0, 1, 0]))
+            .unwrap()
+    }
+
+    #[test]
+    fn textures_tile_once_per_voxel_across_merged_quads() {
+        let quad = UnorientedQuad {
+            minimum: PointN([0; 3]),
+            width: 5,
+            height: 3,
+        };
+        let mut mesh_buf = MeshBuf::default();
+        mesh_buf.add_quad(
+            &top_face(),
+            &quad,
+            1.0,
+            RIGHT_HANDED_Y_UP_CONFIG.u_flip_face,
+            0,
+            [0; 4],
+        );
+        let mut tex_coords = mesh_buf.tex_coords.clone();
+        tex_coords.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            tex_coords,
+            vec![[0.0, 0.0], [0.0, 3.0], [5.0, 0.0], [5.0, 3.0]]
+        );
+    }
+
+    #[test]
+    fn flat_grass_plane_is_one_quad_per_side() {
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([32, 1, 32]));
+        let faces = mesh_quads(extent, &[(extent, Voxel::GRASS)]);
+        let num_quads: usize = faces.iter().map(|(_, quads)| quads.len()).sum();
+        assert_eq!(num_quads, 6);
+    }
+
+    #[test]
+    fn flat_grass_plane_tiles_merge_into_one_quad() {
+        let face = top_face();
+        let tiles = (0..4)
+            .map(|i| {
+                let quad = UnorientedQuad {
+                    minimum: face.u * (16 * (i % 2)) + face.v * (16 * (i / 2)),
+                    width: 16,
+                    height: 16,
+                };
+                (quad, Voxel::GRASS, [3; 4])
+            })
+            .collect();
+        let merged = merge_coplanar_quads(&face, tiles);
+        assert_eq!(merged.len(), 1);
+        let (quad, voxel, _) = &merged[0];
+        assert_eq!((quad.width, quad.height), (32, 32));
+        assert_eq!(quad.minimum, Point3i::ZERO);
+        assert_eq!(*voxel, Voxel::GRASS);
+    }
+
+    #[test]
+    fn different_materials_and_occluded_quads_are_not_merged() {
+        let face = top_face();
+        let tile = |i: i32, voxel: Voxel, ao: [u8; 4]| {
+            let quad = UnorientedQuad {
+                minimum: face.u * (16 * i),
+                width: 16,
+                height: 32,
+            };
+            (quad, voxel, ao)
+        };
+        let merged = merge_coplanar_quads(
+            &face,
+            vec![tile(0, Voxel::GRASS, [3; 4]), tile(1, Voxel::SAND, [3; 4])],
+        );
+        assert_eq!(merged.len(), 2);
+        let merged = merge_coplanar_quads(
+            &face,
+            vec![
+                tile(0, Voxel::GRASS, [3; 4]),
+                tile(1, Voxel::GRASS, [3, 2, 3, 3]),
+            ],
+        );
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn seasonal_snow_is_decided_per_voxel() {
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([32, 1, 32]));
+        let faces = mesh_quads_with_snow(extent, &[(extent, Voxel::GRASS)], |p| p.x() < 10);
+        let (_, top_quads) = faces
+            .iter()
+            .find(|(face, _)| face.n * face.n_sign == PointN([0, 1, 0]))
+            .unwrap();
+        assert_eq!(top_quads.len(), 2);
+        let area = |voxel: Voxel| -> u32 {
+            top_quads
+                .iter()
+                .filter(|(_, mat, _)| *mat == voxel)
+                .map(|(quad, _, _)| quad.width * quad.height)
+                .sum()
+        };
+        assert_eq!(area(Voxel::SNOW), 10 * 32);
+        assert_eq!(area(Voxel::GRASS), 22 * 32);
+        for (quad, mat, _) in top_quads.iter() {
+            let snowy = *mat == Voxel::SNOW;
+            assert!(voxel_faces(&top_face(), quad).all(|unit| (unit.minimum.x() < 10) == snowy));
+        }
+        // Only top faces get seasonal snow
+        for (face, quads) in faces.iter() {
+            if face.n * face.n_sign != PointN([0, 1, 0]) {
+                assert!(quads.iter().all(|(_, mat, _)| *mat == Voxel::GRASS));
+            }
+        }
+    }
+
+    #[test]
+    fn occluded_voxel_faces_keep_their_own_ao() {
+        let plane = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([32, 1, 32]));
+        let block = Extent3i::from_min_and_shape(PointN([5, 1, 5]), PointN([1; 3]));
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([32, 2, 32]));
+        let faces = mesh_quads(extent, &[(plane, Voxel::GRASS), (block, Voxel::STONE)]);
+        let face = top_face();
+        let (_, top_quads) = faces
+            .iter()
+            .find(|(f, _)| f.n * f.n_sign == PointN([0, 1, 0]))
+            .unwrap();
+        let plane_quads: Vec<_> = top_quads
+            .iter()
+            .filter(|(quad, _, _)| quad.minimum.y() == 0)
+            .collect();
+        let area: u32 = plane_quads
+            .iter()
+            .map(|(quad, _, _)| quad.width * quad.height)
+            .sum();
+        assert_eq!(area, 32 * 32 - 1);
+        let occluded: Vec<_> = plane_quads
+            .iter()
+            .filter(|(_, _, ao)| *ao != [3; 4])
+            .collect();
+        // The ring of voxel faces around the block
+        assert_eq!(occluded.len(), 8);
+        for (quad, _, _) in occluded.iter() {
+            assert_eq!((quad.width, quad.height), (1, 1));
+            let (x, z) = (quad.minimum.x(), quad.minimum.z());
+            assert!((4..=6).contains(&x) && (4..=6).contains(&z));
+        }
+        // Merging greedy quads has not hidden any occlusion
+        let padded_extent = extent.padded(1);
+        let mut array = Array3x1::fill(padded_extent, Voxel::EMPTY);
+        array.fill_extent(&plane, Voxel::GRASS);
+        array.fill_extent(&block, Voxel::STONE);
+        for (quad, _, ao) in plane_quads.iter() {
+            if *ao == [3; 4] {
+                assert!(voxel_faces(&face, quad)
+                    .all(|unit| quad_corner_ao(&face, &unit, &array) == [3; 4]));
+            }
+        }
+    }
+
+    #[test]
+    fn remeshes_are_queued_once_until_they_run() {
+        let lod_key = |x: i32| LodChunkKey3 {
+            lod: 0,
+            chunk_key: PointN([x, 0, 0]),
+        };
+        let mut queue = MeshCommandQueue::default();
+        for _ in 0..3 {
+            queue.enqueue(MeshCommand::Remesh(lod_key(0)));
+            queue.enqueue(MeshCommand::Remesh(lod_key(1)));
+        }
+        assert_eq!(queue.len(), 2);
+
+        // Only the oldest has run, so the other is still pending
+        queue.remove_run_commands(1);
+        queue.enqueue(MeshCommand::Remesh(lod_key(0)));
+        queue.enqueue(MeshCommand::Remesh(lod_key(1)));
+        assert_eq!(queue.len(), 2);
+
+        queue.clear();
+        queue.enqueue(MeshCommand::Remesh(lod_key(1)));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn neighbors_of_merged_lod0_chunks_are_found() {
+        let VoxelMapConfig {
+            chunk_shape,
+            chunk_log2,
+            ..
+        } = VoxelMapConfig::default();
+        // The eight lod0 chunks that merge into one lod1 chunk
+        let merged: Vec<_> = (0..8)
+            .map(|i| chunk_shape * PointN([i & 1, (i >> 1) & 1, (i >> 2) & 1]))
+            .collect();
+        let neighbors = lod0_face_neighbors(&merged, chunk_shape);
+        // Four on each side of the 2x2x2 block
+        assert_eq!(neighbors.len(), 24);
+        for neighbor in neighbors.iter() {
+            assert!(!merged.contains(neighbor));
+            let p = *neighbor >> chunk_log2;
+            let outside = p.0.iter().filter(|c| **c < 0 || **c > 1).count();
+            assert_eq!(outside, 1, "{:?} does not share a face with the block", p);
         }
     }
+
+    #[test]
+    fn colliders_follow_the_camera() {
+        let voxel_map_config = VoxelMapConfig {
+            collider_radius_chunks: 1,
+            ..Default::default()
+        };
+        let chunk_size = voxel_map_config.chunk_shape.x() as f32;
+        let mut world = World::default();
+        let mut chunk_meshes = ChunkMeshes::default();
+        // A row of lod0 chunks along x, with the camera in the middle of the first
+        for x in 0..5 {
+            let key = LodChunkKey3 {
+                lod: 0,
+                chunk_key: voxel_map_config.chunk_shape * PointN([x, 0, 0]),
+            };
+            let entity = world.spawn().id();
+            chunk_meshes
+                .entities
+                .insert(key, (entity, Handle::default()));
+            chunk_meshes
+                .collider_shapes
+                .insert(key, ColliderShape::cuboid(1.0, 1.0, 1.0));
+        }
+        world.insert_resource(voxel_map_config);
+        world.insert_resource(chunk_meshes);
+        let camera = world
+            .spawn()
+            .insert(CameraTag)
+            .insert(GlobalTransform::from_translation(Vec3::splat(
+                0.5 * chunk_size,
+            )))
+            .id();
+        let mut stage = SystemStage::single(collider_distance_system.system());
+        fn num_colliders(world: &mut World) -> usize {
+            let num_attached = world
+                .get_resource::<ChunkMeshes>()
+                .unwrap()
+                .attached_colliders
+                .len();
+            let num_components = world.query::<&ColliderShape>().iter(world).count();
+            assert_eq!(num_attached, num_components);
+            num_attached
+        }
+
+        stage.run(&mut world);
+        assert_eq!(num_colliders(&mut world), 2);
+
+        world
+            .get_mut::<GlobalTransform>(camera)
+            .unwrap()
+            .translation
+            .x += 3.0 * chunk_size;
+        stage.run(&mut world);
+        assert_eq!(num_colliders(&mut world), 3);
+        assert!(!world
+            .get_resource::<ChunkMeshes>()
+            .unwrap()
+            .has_lod0_collider(Point3i::ZERO));
+
+        world
+            .get_mut::<GlobalTransform>(camera)
+            .unwrap()
+            .translation
+            .x += 10.0 * chunk_size;
+        stage.run(&mut world);
+        assert_eq!(num_colliders(&mut world), 0);
+        // The chunk entities themselves stay
+        assert_eq!(
+            world.get_resource::<ChunkMeshes>().unwrap().entities.len(),
+            5
+        );
+    }
 }
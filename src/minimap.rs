@@ -0,0 +1,241 @@
+use crate::{
+    key_bindings::KeyBindings,
+    material_registry::MaterialRegistry,
+    mesh_generator::ArrayTextureMaterial,
+    voxel_map::{Voxel, VoxelMap},
+};
+
+use bevy::{
+    prelude::*,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
+};
+use bevy_prototype_character_controller::controller::CameraTag;
+use building_blocks::prelude::*;
+
+const MINIMAP_SIZE: u32 = 128;
+const MARKER_SIZE: f32 = 4.0;
+// Drawn where a column has not been generated
+const UNLOADED_COLOR: [u8; 4] = [0, 0, 0, 128];
+// Drawn until the colours have been read from the array texture
+const DEFAULT_COLOR: [u8; 4] = [128, 128, 128, 255];
+// Surfaces at this height have the colour of their material, higher ones are lighter and lower
+// ones darker, by up to a half at a difference of SHADE_RANGE
+const SHADE_MIDPOINT_Y: f32 = 148.0;
+const SHADE_RANGE: f32 = 512.0;
+
+/// A top-down map of the surface around the camera in the corner of the screen, with the camera at
+/// its centre. It is resampled from lod0 a few rows at a time, so chunks show up as they stream
+/// in.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Minimap>()
+            .add_startup_system(minimap_setup.system())
+            .add_system(minimap_toggle_system.system())
+            .add_system(minimap_colors_system.system().label("minimap_colors"))
+            .add_system(minimap_update_system.system().after("minimap_colors"));
+    }
+}
+
+pub struct Minimap {
+    pub enabled: bool,
+    pub voxels_per_pixel: i32,
+    /// The rows of pixels that are resampled each frame
+    pub rows_per_frame: u32,
+    texture: Handle<Texture>,
+    // By voxel value, the average colour of the top of each material in the array texture
+    colors: Vec<[u8; 4]>,
+    next_row: u32,
+}
+
+impl Default for Minimap {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            voxels_per_pixel: 8,
+            rows_per_frame: 8,
+            texture: Handle::default(),
+            colors: Vec::new(),
+            next_row: 0,
+        }
+    }
+}
+
+impl Minimap {
+    fn color(&self, voxel: Voxel, height: i32) -> [u8; 4] {
+        let base = self
+            .colors
+            .get(voxel.0 as usize)
+            .copied()
+            .unwrap_or(DEFAULT_COLOR);
+        let shade = (1.0 + 0.5 * (height as f32 - SHADE_MIDPOINT_Y) / SHADE_RANGE)
+            .max(0.5)
+            .min(1.5);
+        let shaded = |c: u8| (c as f32 * shade).min(255.0) as u8;
+        [shaded(base[0]), shaded(base[1]), shaded(base[2]), base[3]]
+    }
+}
+
+// The map and its marker
+struct MinimapTag;
+
+fn minimap_setup(
+    mut commands: Commands,
+    mut minimap: ResMut<Minimap>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let texture = Texture::new(
+        Extent3d::new(MINIMAP_SIZE, MINIMAP_SIZE, 1),
+        TextureDimension::D2,
+        UNLOADED_COLOR.repeat((MINIMAP_SIZE * MINIMAP_SIZE) as usize),
+        TextureFormat::Rgba8UnormSrgb,
+    );
+    minimap.texture = textures.add(texture);
+    let marker = color_materials.add(ColorMaterial::color(Color::RED));
+    commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                size: Size::new(Val::Px(MINIMAP_SIZE as f32), Val::Px(MINIMAP_SIZE as f32)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: color_materials.add(ColorMaterial::texture(minimap.texture.clone())),
+            ..Default::default()
+        })
+        .insert(MinimapTag)
+        .with_children(|p| {
+            p.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Px(MARKER_SIZE), Val::Px(MARKER_SIZE)),
+                    ..Default::default()
+                },
+                material: marker,
+                ..Default::default()
+            })
+            .insert(MinimapTag);
+        });
+}
+
+fn minimap_toggle_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut minimap: ResMut<Minimap>,
+    mut nodes: Query<&mut Visible, With<MinimapTag>>,
+) {
+    if keyboard_input.just_pressed(key_bindings.toggle_minimap) {
+        minimap.enabled = !minimap.enabled;
+        for mut visible in nodes.iter_mut() {
+            visible.is_visible = minimap.enabled;
+        }
+    }
+}
+
+/// Averages each layer of the array texture once it has been split into layers
+fn minimap_colors_system(
+    mut minimap: ResMut<Minimap>,
+    array_texture_material: Option<Res<ArrayTextureMaterial>>,
+    material_registry: Res<MaterialRegistry>,
+    materials: Res<Assets<StandardMaterial>>,
+    textures: Res<Assets<Texture>>,
+) {
+    if !minimap.colors.is_empty() {
+        return;
+    }
+    let texture = match array_texture_material
+        .and_then(|material| materials.get(&material.0))
+        .and_then(|material| material.base_color_texture.as_ref())
+        .and_then(|texture| textures.get(texture))
+    {
+        Some(texture) => texture,
+        None => return,
+    };
+    let num_layers = texture.size.depth as usize;
+    if num_layers < 2 || texture.format.pixel_size() != 4 {
+        return;
+    }
+    let layer_averages: Vec<[u8; 4]> = texture
+        .data
+        .chunks(texture.data.len() / num_layers)
+        .map(|layer| {
+            let mut sum = [0u64; 4];
+            for pixel in layer.chunks(4) {
+                for (total, channel) in sum.iter_mut().zip(pixel.iter()) {
+                    *total += *channel as u64;
+                }
+            }
+            let num_pixels = (layer.len() / 4).max(1) as u64;
+            [
+                (sum[0] / num_pixels) as u8,
+                (sum[1] / num_pixels) as u8,
+                (sum[2] / num_pixels) as u8,
+                255,
+            ]
+        })
+        .collect();
+
+    let mut colors = vec![DEFAULT_COLOR; 256];
+    for material in Voxel::MATERIALS.iter() {
+        let layer = material_registry.layer(*material, PointN([0, 1, 0])) as usize;
+        if let Some(color) = layer_averages.get(layer) {
+            colors[material.0 as usize] = *color;
+        }
+    }
+    minimap.colors = colors;
+}
+
+fn minimap_update_system(
+    mut minimap: ResMut<Minimap>,
+    voxel_map: Option<Res<VoxelMap>>,
+    mut textures: ResMut<Assets<Texture>>,
+    cameras: Query<&GlobalTransform, With<CameraTag>>,
+) {
+    if !minimap.enabled {
+        return;
+    }
+    let (voxel_map, camera) = match (voxel_map, cameras.iter().next()) {
+        (Some(voxel_map), Some(camera)) => (voxel_map, camera),
+        _ => return,
+    };
+    let center = Point3f::from(camera.translation).in_voxel();
+    let half_size = (MINIMAP_SIZE / 2) as i32;
+
+    let mut rows = Vec::new();
+    for _ in 0..minimap.rows_per_frame.min(MINIMAP_SIZE) {
+        let row = minimap.next_row;
+        minimap.next_row = (row + 1) % MINIMAP_SIZE;
+        // North, -z, is at the top
+        let z = center.z() + (row as i32 - half_size) * minimap.voxels_per_pixel;
+        let pixels: Vec<[u8; 4]> = (0..MINIMAP_SIZE as i32)
+            .map(|column| {
+                let x = center.x() + (column - half_size) * minimap.voxels_per_pixel;
+                match voxel_map.surface_height(x, z) {
+                    Some(y) => minimap.color(voxel_map.get_voxel(PointN([x, y, z])), y),
+                    None => UNLOADED_COLOR,
+                }
+            })
+            .collect();
+        rows.push((row, pixels));
+    }
+
+    if let Some(texture) = textures.get_mut(&minimap.texture) {
+        let row_bytes = (4 * MINIMAP_SIZE) as usize;
+        for (row, pixels) in rows.into_iter() {
+            let start = row as usize * row_bytes;
+            for (bytes, pixel) in texture.data[start..start + row_bytes]
+                .chunks_mut(4)
+                .zip(pixels.iter())
+            {
+                bytes.copy_from_slice(pixel);
+            }
+        }
+    }
+}
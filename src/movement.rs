@@ -0,0 +1,311 @@
+use crate::{
+    ground_check::GroundCheck,
+    voxel_map::{Voxel, VoxelMap},
+};
+
+use bevy::prelude::*;
+use bevy_prototype_character_controller::controller::CharacterController;
+use bevy_rapier3d::prelude::{
+    RapierConfiguration, RigidBodyDamping, RigidBodyForces, RigidBodyPosition, RigidBodyVelocity,
+};
+use building_blocks::prelude::*;
+
+pub const GRAVITY: [f32; 3] = [0.0, -9.81, 0.0];
+pub const MOON_GRAVITY: [f32; 3] = [0.0, -1.62, 0.0];
+pub const NO_GRAVITY: [f32; 3] = [0.0, 0.0, 0.0];
+
+// A body whose feet are this close above a solid voxel is standing on it
+const GROUND_TOLERANCE: f32 = 0.1;
+
+/// Applies the `MovementConfig` to the physics and to every character controller with a
+/// `GroundCheck`, so it can be changed at runtime, e.g. to moon gravity or none for building.
+/// Characters whose centre is in a fluid swim: they move more slowly, float and are slowed down.
+/// Characters with a `JumpTimers` can still jump shortly after walking off an edge, and a jump
+/// pressed shortly before landing happens on landing.
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MovementConfig>()
+            .add_system(gravity_system.system())
+            .add_system(movement_speed_system.system())
+            .add_system(jump_assist_system.system());
+    }
+}
+
+pub struct MovementConfig {
+    /// Metres per second squared
+    pub gravity: Vec3,
+    /// Metres per second
+    pub walk_speed: f32,
+    /// Metres per second
+    pub run_speed: f32,
+    /// The upward speed a jump starts with, in metres per second
+    pub jump_speed: f32,
+    /// The fraction of the walk and run speeds that can be steered while in the air
+    pub air_control: f32,
+    /// The fraction of the walk and run speeds while in a fluid
+    pub swim_control: f32,
+    /// The fraction of gravity that is cancelled out while in a fluid, so above 1.0 floats up
+    pub buoyancy: f32,
+    /// The linear damping while in a fluid
+    pub fluid_drag: f32,
+    /// Seconds after leaving the ground during which a jump is still allowed
+    pub coyote_time: f32,
+    /// Seconds before landing during which a jump press is kept until the landing
+    pub jump_buffer_time: f32,
+    /// The key that `jump_assist_system` watches, which should be the character controller's
+    pub jump_key: KeyCode,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        let controller = CharacterController::default();
+        Self {
+            gravity: GRAVITY.into(),
+            walk_speed: controller.walk_speed,
+            run_speed: 40.0,
+            jump_speed: controller.jump_speed,
+            air_control: 1.0,
+            swim_control: 0.5,
+            buoyancy: 1.05,
+            fluid_drag: 2.0,
+            coyote_time: 0.15,
+            jump_buffer_time: 0.15,
+            jump_key: KeyCode::Space,
+        }
+    }
+}
+
+impl MovementConfig {
+    /// A character controller with these speeds
+    pub fn character_controller(&self) -> CharacterController {
+        CharacterController {
+            walk_speed: self.walk_speed,
+            run_speed: self.run_speed,
+            jump_speed: self.jump_speed,
+            ..Default::default()
+        }
+    }
+}
+
+fn gravity_system(
+    movement_config: Res<MovementConfig>,
+    mut rapier_configuration: ResMut<RapierConfiguration>,
+) {
+    if movement_config.is_changed() {
+        rapier_configuration.gravity = movement_config.gravity.into();
+    }
+}
+
+/// How long since a character was last on the ground and since jump was last pressed, for
+/// `jump_assist_system`
+pub struct JumpTimers {
+    since_grounded: f32,
+    since_jump_pressed: f32,
+}
+
+impl Default for JumpTimers {
+    fn default() -> Self {
+        Self {
+            since_grounded: f32::INFINITY,
+            since_jump_pressed: f32::INFINITY,
+        }
+    }
+}
+
+impl JumpTimers {
+    // Returns true if a jump should be started that the character controller would not start
+    // itself, as it only jumps when jump is pressed on the ground
+    fn update(
+        &mut self,
+        dt: f32,
+        grounded: bool,
+        jump_pressed: bool,
+        falling: bool,
+        movement_config: &MovementConfig,
+    ) -> bool {
+        let was_grounded_recently = self.since_grounded <= movement_config.coyote_time;
+        let jump_buffered = self.since_jump_pressed <= movement_config.jump_buffer_time;
+        self.since_grounded = if grounded {
+            0.0
+        } else {
+            self.since_grounded + dt
+        };
+        self.since_jump_pressed = if jump_pressed {
+            0.0
+        } else {
+            self.since_jump_pressed + dt
+        };
+        // Rising characters have jumped already, so coyote time is only for walking off edges
+        let coyote_jump = jump_pressed && !grounded && falling && was_grounded_recently;
+        let buffered_jump = !jump_pressed && grounded && jump_buffered;
+        if jump_pressed && grounded {
+            // The character controller jumps itself
+            self.since_jump_pressed = f32::INFINITY;
+        }
+        if coyote_jump || buffered_jump {
+            self.since_grounded = f32::INFINITY;
+            self.since_jump_pressed = f32::INFINITY;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// A body whose feet are within GROUND_TOLERANCE above a solid voxel, or any body until the map
+// exists as there is nothing to fall through yet
+fn is_grounded(voxel_map: Option<&VoxelMap>, translation: Vec3, foot_offset: f32) -> bool {
+    let feet = translation.y - foot_offset - GROUND_TOLERANCE;
+    voxel_map.map_or(true, |voxel_map| {
+        voxel_map.is_solid(PointN([
+            translation.x.floor() as i32,
+            feet.floor() as i32,
+            translation.z.floor() as i32,
+        ]))
+    })
+}
+
+fn jump_assist_system(
+    time: Res<Time>,
+    keyboard: Res<Input<KeyCode>>,
+    movement_config: Res<MovementConfig>,
+    voxel_map: Option<Res<VoxelMap>>,
+    mut bodies: Query<(
+        &mut JumpTimers,
+        &GroundCheck,
+        &RigidBodyPosition,
+        &mut RigidBodyVelocity,
+    )>,
+) {
+    let jump_pressed = keyboard.just_pressed(movement_config.jump_key);
+    for (mut timers, ground_check, position, mut velocity) in bodies.iter_mut() {
+        let translation = position.position.translation;
+        let translation = Vec3::new(translation.x, translation.y, translation.z);
+        let grounded = is_grounded(voxel_map.as_deref(), translation, ground_check.foot_offset);
+        let falling = velocity.linvel.y <= 0.0;
+        if timers.update(
+            time.delta_seconds(),
+            grounded,
+            jump_pressed,
+            falling,
+            &*movement_config,
+        ) {
+            velocity.linvel.y = movement_config.jump_speed;
+        }
+    }
+}
+
+fn movement_speed_system(
+    movement_config: Res<MovementConfig>,
+    voxel_map: Option<Res<VoxelMap>>,
+    mut controllers: Query<(
+        &mut CharacterController,
+        &GroundCheck,
+        &RigidBodyPosition,
+        &mut RigidBodyForces,
+        &mut RigidBodyDamping,
+    )>,
+) {
+    let dry_damping = RigidBodyDamping::default().linear_damping;
+    for (mut controller, ground_check, position, mut forces, mut damping) in controllers.iter_mut()
+    {
+        let translation = position.position.translation;
+        let translation = Vec3::new(translation.x, translation.y, translation.z);
+        let grounded = is_grounded(voxel_map.as_deref(), translation, ground_check.foot_offset);
+        // Until the map exists there is nothing to swim in
+        let in_fluid = voxel_map.as_ref().map_or(false, |voxel_map| {
+            Voxel::FLUIDS.contains(&voxel_map.get_voxel(PointN([
+                translation.x.floor() as i32,
+                translation.y.floor() as i32,
+                translation.z.floor() as i32,
+            ])))
+        });
+        let control = if in_fluid {
+            movement_config.swim_control
+        } else if grounded {
+            1.0
+        } else {
+            movement_config.air_control
+        };
+        let walk_speed = control * movement_config.walk_speed;
+        let run_speed = control * movement_config.run_speed;
+        // Only write on change so that components are not flagged as changed every frame
+        if controller.walk_speed != walk_speed
+            || controller.run_speed != run_speed
+            || controller.jump_speed != movement_config.jump_speed
+        {
+            controller.walk_speed = walk_speed;
+            controller.run_speed = run_speed;
+            controller.jump_speed = movement_config.jump_speed;
+        }
+        let (gravity_scale, linear_damping) = if in_fluid {
+            (1.0 - movement_config.buoyancy, movement_config.fluid_drag)
+        } else {
+            (1.0, dry_damping)
+        };
+        if forces.gravity_scale != gravity_scale {
+            forces.gravity_scale = gravity_scale;
+        }
+        if damping.linear_damping != linear_damping {
+            damping.linear_damping = linear_damping;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.05;
+
+    #[test]
+    fn jumps_shortly_after_walking_off_an_edge() {
+        let config = MovementConfig::default();
+        let mut timers = JumpTimers::default();
+        assert!(!timers.update(DT, true, false, true, &config));
+        assert!(!timers.update(DT, false, false, true, &config));
+        assert!(timers.update(DT, false, true, true, &config));
+        // Only once per time on the ground
+        assert!(!timers.update(DT, false, true, true, &config));
+
+        let mut timers = JumpTimers::default();
+        timers.update(DT, true, false, true, &config);
+        let steps = (config.coyote_time / DT) as usize + 1;
+        for _ in 0..steps {
+            timers.update(DT, false, false, true, &config);
+        }
+        assert!(!timers.update(DT, false, true, true, &config));
+    }
+
+    #[test]
+    fn no_coyote_jump_while_rising() {
+        let config = MovementConfig::default();
+        let mut timers = JumpTimers::default();
+        timers.update(DT, true, false, true, &config);
+        assert!(!timers.update(DT, false, true, false, &config));
+    }
+
+    #[test]
+    fn jumps_on_landing_shortly_after_jump_was_pressed() {
+        let config = MovementConfig::default();
+        let mut timers = JumpTimers::default();
+        assert!(!timers.update(DT, false, true, true, &config));
+        assert!(!timers.update(DT, false, false, true, &config));
+        assert!(timers.update(DT, true, false, true, &config));
+
+        // Jumps pressed on the ground are left to the character controller
+        let mut timers = JumpTimers::default();
+        assert!(!timers.update(DT, true, true, true, &config));
+        assert!(!timers.update(DT, true, false, true, &config));
+
+        let mut timers = JumpTimers::default();
+        timers.update(DT, false, true, true, &config);
+        let steps = (config.jump_buffer_time / DT) as usize + 1;
+        for _ in 0..steps {
+            timers.update(DT, false, false, true, &config);
+        }
+        assert!(!timers.update(DT, true, false, true, &config));
+    }
+}
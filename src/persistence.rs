@@ -0,0 +1,333 @@
+use crate::{
+    chunk_compression::{compress_chunk, CompressedChunk},
+    voxel_map::{Voxel, VoxelMap, VoxelMapConfig},
+    world_seed::WorldSeed,
+};
+
+use building_blocks::{
+    prelude::*,
+    storage::{SmallKeyHashMap, SmallKeyHashSet},
+};
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+// File format, all little-endian:
+//   header: MAGIC, VERSION: u32, chunk_shape: [i32; 3], num_lods: u8, seed: u64, num_chunks: u32
+//   per chunk: minimum voxel key: [i32; 3], num_runs: u32, runs: [(length: u16, voxel: u8)]
+// Only the edited lod0 chunks are stored, the rest is generated again, which is why a file can
+// only be loaded with the chunk shape, number of LODs and seed that it was saved with. The runs
+// are those of a CompressedChunk.
+const MAGIC: &[u8; 4] = b"MKVX";
+const VERSION: u32 = 2;
+
+impl VoxelMap {
+    /// Writes the edited lod0 chunks to the file at `path`, including those that have been
+    /// unloaded. Edited chunks that are now entirely empty are written too, so that they are not
+    /// generated again. `seed` is the seed that the rest of the map is generated with.
+    pub fn save_to_path(&self, path: &Path, seed: WorldSeed) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_edits(&mut writer, seed)?;
+        writer.flush()
+    }
+
+    /// Reads a map written by `save_to_path`. It starts without any loaded chunks: the edited
+    /// chunks are restored as their columns are generated, and downsampled and compressed into
+    /// the LODs with them. Fails with `InvalidData` if the file was saved with another chunk
+    /// shape, number of LODs or seed than those of the running map.
+    pub fn load_from_path(
+        path: &Path,
+        voxel_map_config: &VoxelMapConfig,
+        seed: WorldSeed,
+    ) -> io::Result<VoxelMap> {
+        VoxelMap::read_edits(
+            &mut BufReader::new(File::open(path)?),
+            voxel_map_config,
+            seed,
+        )
+    }
+
+    fn write_edits(&self, writer: &mut impl Write, seed: WorldSeed) -> io::Result<()> {
+        let lod0 = self.pyramid.level(0);
+        let loaded = self.edited.iter().filter_map(|voxel_key| {
+            lod0.get_chunk(*voxel_key)
+                .map(|chunk| (*voxel_key, compress_chunk(chunk)))
+        });
+        let unloaded = self.unloaded_edits.values().flatten().cloned();
+        let chunks: Vec<_> = loaded.chain(unloaded).collect();
+
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+        write_point(writer, self.pyramid.chunk_shape())?;
+        writer.write_all(&[self.pyramid.num_levels()])?;
+        writer.write_all(&seed.0.to_le_bytes())?;
+        write_u32(writer, chunks.len() as u32)?;
+
+        for (voxel_key, compressed) in chunks.into_iter() {
+            write_point(writer, voxel_key)?;
+            let runs = compressed.runs();
+            write_u32(writer, runs.len() as u32)?;
+            for (length, voxel) in runs.iter() {
+                writer.write_all(&length.to_le_bytes())?;
+                writer.write_all(&[voxel.0])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_edits(
+        reader: &mut impl Read,
+        voxel_map_config: &VoxelMapConfig,
+        seed: WorldSeed,
+    ) -> io::Result<VoxelMap> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a minkraft voxel map file"));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "Unsupported voxel map file version {}",
+                version
+            )));
+        }
+        let chunk_shape = read_point(reader)?;
+        if chunk_shape != voxel_map_config.chunk_shape {
+            return Err(invalid_data(&format!(
+                "The map was saved with chunk shape {:?}, not {:?}",
+                chunk_shape, voxel_map_config.chunk_shape
+            )));
+        }
+        let num_lods = read_u8(reader)?;
+        if num_lods != voxel_map_config.num_lods {
+            return Err(invalid_data(&format!(
+                "The map was saved with {} LODs, not {}",
+                num_lods, voxel_map_config.num_lods
+            )));
+        }
+        let saved_seed = WorldSeed(read_u64(reader)?);
+        if saved_seed != seed {
+            return Err(invalid_data(&format!(
+                "The map was saved with seed {}, not {}",
+                saved_seed.0, seed.0
+            )));
+        }
+        let num_chunks = read_u32(reader)?;
+
+        let chunk_log2 = voxel_map_config.chunk_log2;
+        let chunk_volume = chunk_shape.x() * chunk_shape.y() * chunk_shape.z();
+        let mut voxel_keys = SmallKeyHashSet::default();
+        let mut unloaded_edits = SmallKeyHashMap::new();
+        for _ in 0..num_chunks {
+            let voxel_key = read_point(reader)?;
+            if voxel_key.0.iter().any(|c| c & (chunk_shape.x() - 1) != 0) {
+                return Err(invalid_data(&format!(
+                    "Chunk key {:?} is not a multiple of the chunk shape",
+                    voxel_key
+                )));
+            }
+            if !voxel_keys.insert(voxel_key) {
+                return Err(invalid_data(&format!(
+                    "Chunk {:?} is stored twice",
+                    voxel_key
+                )));
+            }
+            // Every run covers at least one voxel, which bounds how much is read for each chunk
+            let num_runs = read_u32(reader)?;
+            if num_runs as u64 > chunk_volume as u64 {
+                return Err(invalid_data(&format!(
+                    "Chunk {:?} has {} runs, more than its {} voxels",
+                    voxel_key, num_runs, chunk_volume
+                )));
+            }
+            let mut runs = Vec::new();
+            for _ in 0..num_runs {
+                let length = read_u16(reader)?;
+                let voxel = Voxel(read_u8(reader)?);
+                runs.push((length, voxel));
+            }
+            let extent = Extent3i::from_min_and_shape(voxel_key, chunk_shape);
+            let compressed = CompressedChunk::from_runs(extent, runs)
+                .ok_or_else(|| invalid_data("Chunk run lengths do not match the chunk shape"))?;
+            let column_key = PointN([voxel_key.x() >> chunk_log2, 0, voxel_key.z() >> chunk_log2]);
+            unloaded_edits
+                .entry(column_key)
+                .or_insert_with(Vec::new)
+                .push((voxel_key, compressed));
+        }
+
+        // Restored chunks are marked as edited again by `take_unloaded_edits`
+        let mut voxel_map = VoxelMap::empty(voxel_map_config);
+        voxel_map.unloaded_edits = unloaded_edits;
+        Ok(voxel_map)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_point(writer: &mut impl Write, p: Point3i) -> io::Result<()> {
+    for i in 0..3 {
+        writer.write_all(&p.0[i].to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_point(reader: &mut impl Read) -> io::Result<Point3i> {
+    let mut p = Point3i::ZERO;
+    for i in 0..3 {
+        let mut bytes = [0u8; 4];
+        reader.read_exact(&mut bytes)?;
+        p.0[i] = i32::from_le_bytes(bytes);
+    }
+    Ok(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_at(voxel_key: Point3i, voxel: Voxel) -> Array3x1<Voxel> {
+        let chunk_shape = VoxelMapConfig::default().chunk_shape;
+        Array3x1::fill(Extent3i::from_min_and_shape(voxel_key, chunk_shape), voxel)
+    }
+
+    fn header(chunk_shape: [i32; 3], num_lods: u8, seed: u64, num_chunks: u32) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        write_u32(&mut bytes, VERSION).unwrap();
+        write_point(&mut bytes, PointN(chunk_shape)).unwrap();
+        bytes.push(num_lods);
+        bytes.extend_from_slice(&seed.to_le_bytes());
+        write_u32(&mut bytes, num_chunks).unwrap();
+        bytes
+    }
+
+    fn read_error(bytes: &[u8]) -> io::ErrorKind {
+        match VoxelMap::read_edits(
+            &mut &bytes[..],
+            &VoxelMapConfig::default(),
+            WorldSeed::default(),
+        ) {
+            Ok(_) => panic!("The map was read"),
+            Err(e) => e.kind(),
+        }
+    }
+
+    #[test]
+    fn only_edited_chunks_are_saved_and_restored() {
+        let voxel_map_config = VoxelMapConfig::default();
+        let size = voxel_map_config.chunk_shape.x();
+        let mut map = VoxelMap::empty(&voxel_map_config);
+        let generated = PointN([0, 0, 0]);
+        let edited = PointN([size, 0, 0]);
+        let emptied = PointN([size, size, 0]);
+        let unloaded = PointN([-size, 0, 2 * size]);
+        let lod0 = map.pyramid.level_mut(0);
+        lod0.write_chunk(generated, chunk_at(generated, Voxel::STONE));
+        lod0.write_chunk(edited, chunk_at(edited, Voxel::DIRT));
+        lod0.write_chunk(emptied, chunk_at(emptied, Voxel::EMPTY));
+        map.edited.insert(edited);
+        map.edited.insert(emptied);
+        map.unloaded_edits.insert(
+            PointN([-1, 0, 2]),
+            vec![(unloaded, compress_chunk(&chunk_at(unloaded, Voxel::SAND)))],
+        );
+
+        let mut bytes = Vec::new();
+        map.write_edits(&mut bytes, WorldSeed::default()).unwrap();
+        let mut loaded =
+            VoxelMap::read_edits(&mut &bytes[..], &voxel_map_config, WorldSeed::default()).unwrap();
+
+        assert!(loaded.pyramid.level(0).storage().is_empty());
+        assert_eq!(loaded.pyramid.num_levels(), voxel_map_config.num_lods);
+        assert_eq!(loaded.unloaded_edits.len(), 2);
+        let column = loaded.take_unloaded_edits(PointN([1, 0, 0]));
+        assert_eq!(column.len(), 2);
+        for (voxel_key, chunk) in column.iter() {
+            let voxel = if *voxel_key == edited {
+                Voxel::DIRT
+            } else {
+                assert_eq!(*voxel_key, emptied);
+                Voxel::EMPTY
+            };
+            assert_eq!(*chunk, chunk_at(*voxel_key, voxel));
+        }
+        assert_eq!(
+            loaded.take_unloaded_edits(PointN([-1, 0, 2])),
+            vec![(unloaded, chunk_at(unloaded, Voxel::SAND))]
+        );
+        assert!(loaded.edited.contains(&edited) && loaded.edited.contains(&unloaded));
+    }
+
+    #[test]
+    fn headers_that_do_not_match_the_running_map_are_rejected() {
+        let seed = WorldSeed::default().0;
+        assert!(VoxelMap::read_edits(
+            &mut &header([32, 32, 32], 6, seed, 0)[..],
+            &VoxelMapConfig::default(),
+            WorldSeed::default()
+        )
+        .is_ok());
+        for bytes in [
+            header([32, 32, 32], 0, seed, 0),
+            header([32, 32, 32], 5, seed, 0),
+            header([0, 0, 0], 6, seed, 0),
+            header([-32, -32, -32], 6, seed, 0),
+            header([16, 16, 16], 6, seed, 0),
+            header([32, 16, 32], 6, seed, 0),
+            header([1 << 9, 1 << 9, 1 << 9], 6, seed, 0),
+            header([32, 32, 32], 6, seed + 1, 0),
+        ]
+        .iter()
+        {
+            assert_eq!(read_error(bytes), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn invalid_chunks_are_rejected_before_reading_their_runs() {
+        let seed = WorldSeed::default().0;
+        let mut misaligned = header([32, 32, 32], 6, seed, 1);
+        write_point(&mut misaligned, PointN([1, 0, 0])).unwrap();
+        let mut too_many_runs = header([32, 32, 32], 6, seed, 1);
+        write_point(&mut too_many_runs, PointN([0, 0, 0])).unwrap();
+        write_u32(&mut too_many_runs, u32::MAX).unwrap();
+        for bytes in [misaligned, too_many_runs].iter() {
+            assert_eq!(read_error(bytes), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::voxel_map::{Voxel, VoxelMap};
+
+use bevy::prelude::*;
+use building_blocks::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelHit {
+    /// The first non-empty voxel along the ray
+    pub point: Point3i,
+    /// The empty voxel the ray passed through just before `point`, where a new voxel would be
+    /// placed against the hit face
+    pub adjacent: Point3i,
+    /// The normal of the face of `point` that the ray entered through. Zero if the ray started
+    /// inside `point`.
+    pub normal: Point3i,
+    pub distance: f32,
+}
+
+impl VoxelMap {
+    /// Walks the lod0 voxels along the ray from `origin` in direction `dir` (Amanatides and Woo)
+    /// and returns the first non-empty voxel within `max_dist`. Voxels in chunks that have not
+    /// been generated are treated as empty.
+    pub fn raycast(&self, origin: Vec3, dir: Vec3, max_dist: f32) -> Option<VoxelHit> {
+        let dir = dir.normalize_or_zero();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+        let origin_voxel = origin.floor();
+        let mut point = PointN([
+            origin_voxel.x as i32,
+            origin_voxel.y as i32,
+            origin_voxel.z as i32,
+        ]);
+        let mut step = [0; 3];
+        // The ray distance to cross one voxel along each axis
+        let mut t_delta = [f32::INFINITY; 3];
+        // The ray distance to the next voxel boundary along each axis
+        let mut t_max = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            let (o, d, v) = (origin[axis], dir[axis], origin_voxel[axis]);
+            if d > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = 1.0 / d;
+                t_max[axis] = (v + 1.0 - o) / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -1.0 / d;
+                t_max[axis] = (v - o) / d;
+            }
+        }
+
+        let mut adjacent = point;
+        let mut normal = Point3i::ZERO;
+        let mut distance = 0.0;
+        loop {
+            if self.get_voxel(point) != Voxel::EMPTY {
+                return Some(VoxelHit {
+                    point,
+                    adjacent,
+                    normal,
+                    distance,
+                });
+            }
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            if t_max[axis] > max_dist {
+                return None;
+            }
+            adjacent = point;
+            point.0[axis] += step[axis];
+            distance = t_max[axis];
+            t_max[axis] += t_delta[axis];
+            normal = Point3i::ZERO;
+            normal.0[axis] = -step[axis];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_map::VoxelMapConfig;
+
+    const STONE: Point3i = PointN([5, 2, 3]);
+
+    fn single_voxel_map() -> VoxelMap {
+        let mut voxel_map = VoxelMap::empty(&VoxelMapConfig::default());
+        let lod0 = voxel_map.pyramid.level_mut(0);
+        let chunk_key = lod0.indexer.min_of_chunk_containing_point(STONE);
+        let mut chunk = Array3x1::fill(
+            lod0.indexer.extent_for_chunk_at_key(chunk_key),
+            Voxel::EMPTY,
+        );
+        *chunk.get_mut(STONE) = Voxel::STONE;
+        lod0.write_chunk(chunk_key, chunk);
+        voxel_map
+    }
+
+    #[test]
+    fn rays_hit_the_face_they_enter() {
+        let voxel_map = single_voxel_map();
+        let hit = voxel_map
+            .raycast(Vec3::new(0.5, 2.5, 3.5), Vec3::X, 10.0)
+            .unwrap();
+        assert_eq!(
+            hit,
+            VoxelHit {
+                point: STONE,
+                adjacent: PointN([4, 2, 3]),
+                normal: PointN([-1, 0, 0]),
+                distance: 4.5,
+            }
+        );
+
+        let hit = voxel_map
+            .raycast(Vec3::new(5.5, 10.5, 3.5), -Vec3::Y, 10.0)
+            .unwrap();
+        assert_eq!((hit.point, hit.adjacent), (STONE, PointN([5, 3, 3])));
+        assert_eq!(hit.normal, PointN([0, 1, 0]));
+        assert!((hit.distance - 7.5).abs() < 1e-5);
+
+        // Diagonally, entering through the top face
+        let hit = voxel_map
+            .raycast(Vec3::new(2.5, 5.6, 3.5), Vec3::new(1.0, -1.0, 0.0), 10.0)
+            .unwrap();
+        assert_eq!((hit.point, hit.normal), (STONE, PointN([0, 1, 0])));
+    }
+
+    #[test]
+    fn rays_miss() {
+        let voxel_map = single_voxel_map();
+        let origin = Vec3::new(0.5, 2.5, 3.5);
+        // Past the voxel
+        assert_eq!(voxel_map.raycast(origin, Vec3::Z, 100.0), None);
+        assert_eq!(voxel_map.raycast(origin, -Vec3::X, 100.0), None);
+        // Not far enough
+        assert_eq!(voxel_map.raycast(origin, Vec3::X, 4.0), None);
+        // No direction
+        assert_eq!(voxel_map.raycast(origin, Vec3::ZERO, 100.0), None);
+    }
+
+    #[test]
+    fn rays_from_inside_a_voxel_hit_it() {
+        let voxel_map = single_voxel_map();
+        let hit = voxel_map
+            .raycast(Vec3::new(5.5, 2.5, 3.5), Vec3::X, 10.0)
+            .unwrap();
+        assert_eq!((hit.point, hit.adjacent), (STONE, STONE));
+        assert_eq!((hit.normal, hit.distance), (Point3i::ZERO, 0.0));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::voxel_map::{NoiseConfig, TemperatureConfig};
+
+use bevy::prelude::*;
+use bevy_physical_sky::{Datelike, SolarPosition};
+use building_blocks::prelude::*;
+
+// Around the 20th of July, as the warmest time of year lags the solstice by about a month
+const MIDSUMMER_DAY_OF_YEAR: f32 = 201.0;
+const DAYS_PER_YEAR: f32 = 365.25;
+
+/// Follows the date of the `SolarPosition` through the year so that snow grows down the mountains
+/// in winter and melts back in summer
+pub struct SeasonPlugin;
+
+impl Plugin for SeasonPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Season>()
+            .add_system(season_system.system())
+            .add_system(season_noise_config_system.system());
+    }
+}
+
+/// The snow that the terrain generator leaves out because it melts in summer is drawn on top faces
+/// as chunks are meshed, so the snow line moves as chunks are remeshed rather than all at once.
+pub struct Season {
+    pub enabled: bool,
+    /// 1 at the northern midsummer and -1 at the northern midwinter
+    pub phase: f32,
+    /// Copied from the `NoiseConfig` resource whenever it changes, so that seasonal snow lines up
+    /// with the generated snow
+    pub temperature: TemperatureConfig,
+    pub sea_level: i32,
+}
+
+impl Default for Season {
+    fn default() -> Self {
+        let noise_config = NoiseConfig::default();
+        Self {
+            enabled: true,
+            phase: 1.0,
+            temperature: noise_config.temperature.clone(),
+            sea_level: noise_config.sea_level,
+        }
+    }
+}
+
+impl Season {
+    /// Whether ground with its top at voxel `p` is covered in snow now
+    pub fn is_snow(&self, p: Point3i) -> bool {
+        self.enabled && self.temperature.is_snow(p, self.sea_level, self.phase)
+    }
+}
+
+/// Does nothing without a SolarPosition resource
+fn season_system(solar_position: Option<Res<SolarPosition>>, mut season: ResMut<Season>) {
+    let solar_position = match solar_position {
+        Some(solar_position) => solar_position,
+        None => return,
+    };
+    let day = solar_position.now.ordinal0() as f32;
+    season.phase =
+        (2.0 * std::f32::consts::PI * (day - MIDSUMMER_DAY_OF_YEAR) / DAYS_PER_YEAR).cos();
+}
+
+/// Does nothing without a NoiseConfig resource
+fn season_noise_config_system(noise_config: Option<Res<NoiseConfig>>, mut season: ResMut<Season>) {
+    let noise_config = match noise_config {
+        Some(noise_config) => noise_config,
+        None => return,
+    };
+    if noise_config.is_changed() {
+        season.temperature = noise_config.temperature.clone();
+        season.sea_level = noise_config.sea_level;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_noise_config() {
+        let mut noise_config = NoiseConfig::default();
+        noise_config.sea_level = 20;
+        let mut world = World::default();
+        world.insert_resource(Season::default());
+        world.insert_resource(noise_config);
+        let mut stage = SystemStage::single(season_noise_config_system.system());
+        stage.run(&mut world);
+        assert_eq!(world.get_resource::<Season>().unwrap().sea_level, 20);
+
+        {
+            let mut noise_config = world.get_resource_mut::<NoiseConfig>().unwrap();
+            noise_config.sea_level = 40;
+            noise_config.temperature.snow_temperature = 5.0;
+        }
+        stage.run(&mut world);
+        let season = world.get_resource::<Season>().unwrap();
+        assert_eq!(season.sea_level, 40);
+        assert_eq!(season.temperature.snow_temperature, 5.0);
+    }
+}
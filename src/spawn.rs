@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+use std::str::FromStr;
+
+/// The environment variable that `SpawnConfig::from_env` reads, e.g.
+/// `MINKRAFT_SPAWN=8.5,641,-3.5,90`
+pub const SPAWN_ENV_VAR: &str = "MINKRAFT_SPAWN";
+
+/// Where the player starts, which is also where the map starts loading and the sky is first placed.
+/// Insert one before the app's `init_resource::<SpawnConfig>()` to start somewhere else.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnConfig {
+    pub position: Vec3,
+    /// Degrees, as in `MouseSettings::yaw_pitch_roll`
+    pub yaw: f32,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(8.5, 641.0, -3.5),
+            yaw: 0.0,
+        }
+    }
+}
+
+impl SpawnConfig {
+    /// The spawn in `SPAWN_ENV_VAR`, if it is set
+    pub fn from_env() -> Option<Result<Self, String>> {
+        std::env::var(SPAWN_ENV_VAR).ok().map(|value| value.parse())
+    }
+}
+
+/// Parses `x,y,z` or `x,y,z,yaw`
+impl FromStr for SpawnConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|e| format!("Invalid spawn {:?}: {}", s, e))?;
+        match values.as_slice() {
+            [x, y, z] => Ok(Self {
+                position: Vec3::new(*x, *y, *z),
+                yaw: 0.0,
+            }),
+            [x, y, z, yaw] => Ok(Self {
+                position: Vec3::new(*x, *y, *z),
+                yaw: *yaw,
+            }),
+            _ => Err(format!(
+                "Invalid spawn {:?}: expected x,y,z or x,y,z,yaw",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_position_with_and_without_yaw() {
+        assert_eq!(
+            "1, 2.5, -3".parse::<SpawnConfig>(),
+            Ok(SpawnConfig {
+                position: Vec3::new(1.0, 2.5, -3.0),
+                yaw: 0.0,
+            })
+        );
+        assert_eq!(
+            "1,2,3,90".parse::<SpawnConfig>(),
+            Ok(SpawnConfig {
+                position: Vec3::new(1.0, 2.0, 3.0),
+                yaw: 90.0,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_spawns() {
+        for s in &["", "1,2", "1,2,3,4,5", "1,two,3"] {
+            assert!(s.parse::<SpawnConfig>().is_err(), "{:?} parsed", s);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::{
+    chunk_generator::{ChunkCommandQueue, ChunkGenerationPipeline},
+    mesh_generator::MeshCommandQueue,
+};
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::*,
+};
+
+/// Adds diagnostics for the chunk generation and meshing backlogs. Both pending counts reaching
+/// zero means the world has settled.
+#[derive(Default)]
+pub struct TerrainDiagnosticsPlugin;
+
+impl Plugin for TerrainDiagnosticsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(Self::setup_system.system())
+            .add_system(Self::diagnostic_system.system());
+    }
+}
+
+impl TerrainDiagnosticsPlugin {
+    pub const PENDING_CHUNK_COMMANDS: DiagnosticId =
+        DiagnosticId::from_u128(313810876875749611052868428661956291978);
+    pub const PENDING_MESH_COMMANDS: DiagnosticId =
+        DiagnosticId::from_u128(202296548999033722032077985318001740499);
+    pub const CHUNKS_GENERATED: DiagnosticId =
+        DiagnosticId::from_u128(79719808298929292411980717050393752237);
+    pub const MESHES_CREATED: DiagnosticId =
+        DiagnosticId::from_u128(140740170658812628585148890357517010991);
+    /// Milliseconds
+    pub const MESH_SPAWN_TIME: DiagnosticId =
+        DiagnosticId::from_u128(262908390755684954195989290932590624417);
+
+    pub fn setup_system(mut diagnostics: ResMut<Diagnostics>) {
+        diagnostics.add(Diagnostic::new(
+            Self::PENDING_CHUNK_COMMANDS,
+            "pending_chunk_commands",
+            1,
+        ));
+        diagnostics.add(Diagnostic::new(
+            Self::PENDING_MESH_COMMANDS,
+            "pending_mesh_commands",
+            1,
+        ));
+        diagnostics.add(Diagnostic::new(
+            Self::CHUNKS_GENERATED,
+            "chunks_generated",
+            1,
+        ));
+        diagnostics.add(Diagnostic::new(Self::MESHES_CREATED, "meshes_created", 1));
+        diagnostics.add(Diagnostic::new(
+            Self::MESH_SPAWN_TIME,
+            "mesh_spawn_time_ms",
+            1,
+        ));
+    }
+
+    pub fn diagnostic_system(
+        mut diagnostics: ResMut<Diagnostics>,
+        chunk_commands: Option<Res<ChunkCommandQueue>>,
+        chunk_generation: Option<Res<ChunkGenerationPipeline>>,
+        mesh_commands: Option<Res<MeshCommandQueue>>,
+    ) {
+        if let Some(chunk_commands) = chunk_commands {
+            // Columns that are being generated are no longer queued but have not arrived yet
+            let generating = chunk_generation.map_or(0, |generation| generation.num_pending());
+            diagnostics.add_measurement(
+                Self::PENDING_CHUNK_COMMANDS,
+                (chunk_commands.len() + generating) as f64,
+            );
+            diagnostics.add_measurement(
+                Self::CHUNKS_GENERATED,
+                chunk_commands.num_generated_last_frame() as f64,
+            );
+        }
+        if let Some(mesh_commands) = mesh_commands {
+            diagnostics.add_measurement(Self::PENDING_MESH_COMMANDS, mesh_commands.len() as f64);
+            diagnostics.add_measurement(
+                Self::MESHES_CREATED,
+                mesh_commands.num_created_last_frame() as f64,
+            );
+            diagnostics.add_measurement(
+                Self::MESH_SPAWN_TIME,
+                1000.0 * mesh_commands.spawn_time_last_frame().as_secs_f64(),
+            );
+        }
+    }
+}
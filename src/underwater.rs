@@ -0,0 +1,94 @@
+use crate::{
+    fog::{FogConfig, FogOverride, FOG_TINT_SYSTEM},
+    voxel_map::{Voxel, VoxelMap},
+};
+
+use bevy::prelude::*;
+use bevy_prototype_character_controller::controller::CameraTag;
+use building_blocks::prelude::*;
+
+/// Tags the camera with `CameraInFluid` while it is inside a fluid voxel, and replaces the fog
+/// with a short, tinted one meanwhile. Swimming itself is handled by the `MovementConfig`.
+pub struct UnderwaterPlugin;
+
+impl Plugin for UnderwaterPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<UnderwaterConfig>()
+            .add_system(camera_in_fluid_system.system().label("camera_in_fluid"))
+            .add_system(
+                underwater_fog_system
+                    .system()
+                    .after("camera_in_fluid")
+                    .before(FOG_TINT_SYSTEM),
+            );
+    }
+}
+
+/// On the camera while its position is inside `fluid`. Other systems can react to it being added
+/// and removed.
+pub struct CameraInFluid {
+    pub fluid: Voxel,
+}
+
+pub struct UnderwaterConfig {
+    /// The fog while the camera is under water
+    pub water_fog: FogConfig,
+}
+
+impl Default for UnderwaterConfig {
+    fn default() -> Self {
+        Self {
+            water_fog: FogConfig {
+                color: [0.05, 0.2, 0.35, 1.0],
+                near: 0.0,
+                far: 48.0,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn camera_in_fluid_system(
+    mut commands: Commands,
+    voxel_map: Option<Res<VoxelMap>>,
+    cameras: Query<(Entity, &GlobalTransform, Option<&CameraInFluid>), With<CameraTag>>,
+) {
+    let voxel_map = match voxel_map {
+        Some(voxel_map) => voxel_map,
+        None => return,
+    };
+    for (entity, transform, in_fluid) in cameras.iter() {
+        let voxel = voxel_map.get_voxel(Point3f::from(transform.translation).in_voxel());
+        let fluid = Some(voxel).filter(|voxel| Voxel::FLUIDS.contains(voxel));
+        match (fluid, in_fluid) {
+            (Some(fluid), None) => {
+                commands.entity(entity).insert(CameraInFluid { fluid });
+            }
+            (Some(fluid), Some(in_fluid)) if in_fluid.fluid != fluid => {
+                commands.entity(entity).insert(CameraInFluid { fluid });
+            }
+            (None, Some(_)) => {
+                commands.entity(entity).remove::<CameraInFluid>();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Only clears an override that it set itself
+fn underwater_fog_system(
+    config: Res<UnderwaterConfig>,
+    cameras: Query<&CameraInFluid, With<CameraTag>>,
+    mut fog_override: ResMut<FogOverride>,
+    mut overriding: Local<bool>,
+) {
+    let fog = cameras
+        .iter()
+        .next()
+        .filter(|in_fluid| in_fluid.fluid == Voxel::WATER)
+        .map(|_| config.water_fog);
+    if fog.is_some() || *overriding {
+        *overriding = fog.is_some();
+        fog_override.0 = fog;
+    }
+}
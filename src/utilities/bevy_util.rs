@@ -25,4 +25,5 @@
 
 pub mod camera;
 pub mod mesh;
+pub mod shapes;
 pub mod thread_local_resource;
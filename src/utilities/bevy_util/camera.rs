@@ -23,7 +23,10 @@
  *
  */
 
-use bevy::{prelude::*, render::camera::PerspectiveProjection};
+use bevy::{
+    prelude::*,
+    render::camera::{Camera, PerspectiveProjection},
+};
 
 pub fn create_camera_bundle(transform: Transform) -> PerspectiveCameraBundle {
     PerspectiveCameraBundle {
@@ -35,3 +38,36 @@ pub fn create_camera_bundle(transform: Transform) -> PerspectiveCameraBundle {
         ..Default::default()
     }
 }
+
+/// The six clipping planes of a camera's view frustum in world space, each stored as
+/// (normal, distance) with the normal pointing into the frustum.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    pub fn from_camera(camera: &Camera, transform: &GlobalTransform) -> Self {
+        Self::from_view_projection(camera.projection_matrix * transform.compute_matrix().inverse())
+    }
+
+    /// Extracts the planes from a view-projection matrix with a 0..1 depth range.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let rows = view_projection.transpose();
+        let (r0, r1, r2, r3) = (rows.x_axis, rows.y_axis, rows.z_axis, rows.w_axis);
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2];
+        for plane in planes.iter_mut() {
+            *plane /= plane.truncate().length();
+        }
+        Self { planes }
+    }
+
+    /// Conservative test that returns false only if the box lies entirely outside a plane.
+    pub fn intersects_aabb(&self, minimum: Vec3, maximum: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let furthest = Vec3::select(normal.cmpge(Vec3::ZERO), maximum, minimum);
+            normal.dot(furthest) + plane.w >= 0.0
+        })
+    }
+}
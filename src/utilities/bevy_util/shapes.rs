@@ -0,0 +1,373 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, pipeline::PrimitiveTopology},
+};
+
+/// A UV sphere centred on the origin, with `rings` bands of latitude and `sectors` bands of
+/// longitude
+#[derive(Clone, Copy, Debug)]
+pub struct Sphere {
+    pub radius: f32,
+    pub rings: usize,
+    pub sectors: usize,
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            rings: 16,
+            sectors: 32,
+        }
+    }
+}
+
+impl From<Sphere> for Mesh {
+    fn from(sphere: Sphere) -> Self {
+        let Sphere {
+            radius,
+            rings,
+            sectors,
+        } = sphere;
+        assert!(
+            rings >= 2 && sectors >= 3,
+            "A sphere needs at least 2 rings and 3 sectors"
+        );
+
+        // The seam and the poles have a vertex per sector so that the UVs do not wrap
+        let num_vertices = (rings + 1) * (sectors + 1);
+        let mut positions = Vec::with_capacity(num_vertices);
+        let mut normals = Vec::with_capacity(num_vertices);
+        let mut uvs = Vec::with_capacity(num_vertices);
+        for ring in 0..=rings {
+            let v = ring as f32 / rings as f32;
+            let polar = v * PI;
+            for sector in 0..=sectors {
+                let u = sector as f32 / sectors as f32;
+                let azimuth = u * 2.0 * PI;
+                let normal = [
+                    polar.sin() * azimuth.cos(),
+                    polar.cos(),
+                    polar.sin() * azimuth.sin(),
+                ];
+                positions.push([normal[0] * radius, normal[1] * radius, normal[2] * radius]);
+                normals.push(normal);
+                uvs.push([u, v]);
+            }
+        }
+
+        let mut indices = Vec::with_capacity(rings * sectors * 6);
+        let stride = sectors as u32 + 1;
+        for ring in 0..rings as u32 {
+            for sector in 0..sectors as u32 {
+                let top_left = ring * stride + sector;
+                let bottom_left = top_left + stride;
+                // The triangles that would be degenerate at the poles are left out
+                if ring != 0 {
+                    indices.extend_from_slice(&[top_left, top_left + 1, bottom_left]);
+                }
+                if ring != rings as u32 - 1 {
+                    indices.extend_from_slice(&[top_left + 1, bottom_left + 1, bottom_left]);
+                }
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// A torus centred on the origin and lying in the xz plane. `radius` is from the centre to the
+/// middle of the tube.
+#[derive(Clone, Copy, Debug)]
+pub struct Torus {
+    pub radius: f32,
+    pub tube_radius: f32,
+    /// Divisions around the y axis
+    pub segments: usize,
+    /// Divisions around the tube
+    pub sides: usize,
+}
+
+impl Default for Torus {
+    fn default() -> Self {
+        Self {
+            radius: 1.0,
+            tube_radius: 0.25,
+            segments: 32,
+            sides: 16,
+        }
+    }
+}
+
+impl From<Torus> for Mesh {
+    fn from(torus: Torus) -> Self {
+        let Torus {
+            radius,
+            tube_radius,
+            segments,
+            sides,
+        } = torus;
+        assert!(
+            segments >= 3 && sides >= 3,
+            "A torus needs at least 3 segments and 3 sides"
+        );
+
+        let num_vertices = (segments + 1) * (sides + 1);
+        let mut positions = Vec::with_capacity(num_vertices);
+        let mut normals = Vec::with_capacity(num_vertices);
+        let mut uvs = Vec::with_capacity(num_vertices);
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let theta = u * 2.0 * PI;
+            for side in 0..=sides {
+                let v = side as f32 / sides as f32;
+                let phi = v * 2.0 * PI;
+                let normal = [phi.cos() * theta.cos(), phi.sin(), phi.cos() * theta.sin()];
+                positions.push([
+                    radius * theta.cos() + tube_radius * normal[0],
+                    tube_radius * normal[1],
+                    radius * theta.sin() + tube_radius * normal[2],
+                ]);
+                normals.push(normal);
+                uvs.push([u, v]);
+            }
+        }
+
+        let mut indices = Vec::with_capacity(segments * sides * 6);
+        let stride = sides as u32 + 1;
+        for segment in 0..segments as u32 {
+            for side in 0..sides as u32 {
+                let a = segment * stride + side;
+                let b = a + stride;
+                indices.extend_from_slice(&[a, a + 1, b, a + 1, b + 1, b]);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// A capsule along the y axis centred on the origin, matching a rapier capsule with the same
+/// `half_height` and `radius`. Each hemisphere has `rings` bands of latitude.
+#[derive(Clone, Copy, Debug)]
+pub struct Capsule {
+    pub radius: f32,
+    /// Half the length of the cylindrical section
+    pub half_height: f32,
+    pub rings: usize,
+}
+
+impl Default for Capsule {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            half_height: 0.5,
+            rings: 8,
+        }
+    }
+}
+
+impl From<Capsule> for Mesh {
+    fn from(capsule: Capsule) -> Self {
+        let Capsule {
+            radius,
+            half_height,
+            rings,
+        } = capsule;
+        assert!(rings >= 1, "A capsule needs at least 1 ring per hemisphere");
+        // Roughly square quads around the equator
+        let sectors = 4 * rings;
+
+        // The top hemisphere from its pole to its equator, then the bottom hemisphere from its
+        // equator to its pole. The two equators are joined by the cylindrical section.
+        let rows: Vec<(f32, f32)> = (0..=rings)
+            .map(|ring| (ring as f32 / rings as f32 * 0.5 * PI, half_height))
+            .chain(
+                (0..=rings)
+                    .map(|ring| ((1.0 + ring as f32 / rings as f32) * 0.5 * PI, -half_height)),
+            )
+            .collect();
+        let total_height = 2.0 * (half_height + radius);
+
+        let num_vertices = rows.len() * (sectors + 1);
+        let mut positions = Vec::with_capacity(num_vertices);
+        let mut normals = Vec::with_capacity(num_vertices);
+        let mut uvs = Vec::with_capacity(num_vertices);
+        for (polar, y_offset) in rows.iter() {
+            for sector in 0..=sectors {
+                let u = sector as f32 / sectors as f32;
+                let azimuth = u * 2.0 * PI;
+                let normal = [
+                    polar.sin() * azimuth.cos(),
+                    polar.cos(),
+                    polar.sin() * azimuth.sin(),
+                ];
+                let y = normal[1] * radius + y_offset;
+                positions.push([normal[0] * radius, y, normal[2] * radius]);
+                normals.push(normal);
+                uvs.push([u, 0.5 - y / total_height]);
+            }
+        }
+
+        let num_rows = rows.len() as u32;
+        let mut indices = Vec::with_capacity((rows.len() - 1) * sectors * 6);
+        let stride = sectors as u32 + 1;
+        for row in 0..num_rows - 1 {
+            for sector in 0..sectors as u32 {
+                let top_left = row * stride + sector;
+                let bottom_left = top_left + stride;
+                if row != 0 {
+                    indices.extend_from_slice(&[top_left, top_left + 1, bottom_left]);
+                }
+                if row != num_rows - 2 {
+                    indices.extend_from_slice(&[top_left + 1, bottom_left + 1, bottom_left]);
+                }
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::mesh::VertexAttributeValues;
+    use std::collections::HashMap;
+
+    fn float3_attribute(mesh: &Mesh, name: &'static str) -> Vec<[f32; 3]> {
+        match mesh.attribute(name) {
+            Some(VertexAttributeValues::Float3(values)) => values.clone(),
+            _ => panic!("The mesh has no {} attribute", name),
+        }
+    }
+
+    fn indices(mesh: &Mesh) -> Vec<u32> {
+        match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.clone(),
+            _ => panic!("The mesh has no u32 indices"),
+        }
+    }
+
+    fn assert_unit_normals(mesh: &Mesh) {
+        for normal in float3_attribute(mesh, Mesh::ATTRIBUTE_NORMAL).iter() {
+            assert!((Vec3::from(*normal).length() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    // The Euler characteristic of the mesh with the vertices at the same position welded
+    // together, after checking that every edge is shared by exactly two triangles so that the
+    // surface is closed
+    fn closed_euler_characteristic(mesh: &Mesh) -> i64 {
+        let positions = float3_attribute(mesh, Mesh::ATTRIBUTE_POSITION);
+        let mut welded = HashMap::new();
+        let vertex_ids: Vec<usize> = positions
+            .iter()
+            .map(|p| {
+                let key: Vec<i64> = p.iter().map(|x| (x * 1e4).round() as i64).collect();
+                let next_id = welded.len();
+                *welded.entry(key).or_insert(next_id)
+            })
+            .collect();
+        let indices = indices(mesh);
+        let mut edges = HashMap::new();
+        for triangle in indices.chunks(3) {
+            for i in 0..3 {
+                let (a, b) = (
+                    vertex_ids[triangle[i] as usize],
+                    vertex_ids[triangle[(i + 1) % 3] as usize],
+                );
+                assert_ne!(a, b, "Degenerate triangle {:?}", triangle);
+                *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        assert!(edges.values().all(|count| *count == 2));
+        welded.len() as i64 - edges.len() as i64 + (indices.len() / 3) as i64
+    }
+
+    #[test]
+    fn sphere_counts_and_normals() {
+        let sphere = Sphere {
+            radius: 2.0,
+            rings: 6,
+            sectors: 8,
+        };
+        let mesh = Mesh::from(sphere);
+        assert_eq!(mesh.count_vertices(), 7 * 9);
+        // Two triangles per quad, less one at each pole
+        assert_eq!(indices(&mesh).len(), 3 * 8 * (2 * 6 - 2));
+        assert_unit_normals(&mesh);
+        let positions = float3_attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+        let normals = float3_attribute(&mesh, Mesh::ATTRIBUTE_NORMAL);
+        for (position, normal) in positions.iter().zip(normals.iter()) {
+            assert!((Vec3::from(*position) - 2.0 * Vec3::from(*normal)).length() < 1e-5);
+        }
+        assert_eq!(closed_euler_characteristic(&mesh), 2);
+    }
+
+    #[test]
+    fn torus_is_closed_with_a_hole() {
+        let mesh = Mesh::from(Torus {
+            radius: 1.5,
+            tube_radius: 0.5,
+            segments: 12,
+            sides: 8,
+        });
+        assert_eq!(mesh.count_vertices(), 13 * 9);
+        assert_eq!(indices(&mesh).len(), 6 * 12 * 8);
+        assert_unit_normals(&mesh);
+        assert_eq!(closed_euler_characteristic(&mesh), 0);
+    }
+
+    #[test]
+    fn capsule_is_closed() {
+        for rings in 1..=4 {
+            let mesh = Mesh::from(Capsule {
+                radius: 0.5,
+                half_height: 0.75,
+                rings,
+            });
+            let (num_rows, sectors) = (2 * (rings + 1), 4 * rings);
+            assert_eq!(mesh.count_vertices(), num_rows * (sectors + 1));
+            // Two triangles per quad, less one at each pole
+            assert_eq!(indices(&mesh).len(), 3 * sectors * (2 * (num_rows - 1) - 2));
+            assert_unit_normals(&mesh);
+            assert_eq!(closed_euler_characteristic(&mesh), 2);
+        }
+    }
+
+    #[test]
+    fn capsule_matches_its_collider() {
+        let mesh = Mesh::from(Capsule {
+            radius: 0.5,
+            half_height: 0.75,
+            rings: 4,
+        });
+        let positions = float3_attribute(&mesh, Mesh::ATTRIBUTE_POSITION);
+        let max_y = positions.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+        let min_y = positions.iter().map(|p| p[1]).fold(f32::MAX, f32::min);
+        assert!((max_y - 1.25).abs() < 1e-5 && (min_y + 1.25).abs() < 1e-5);
+        // Every vertex is `radius` from the segment between the hemisphere centres
+        for p in positions.iter() {
+            let axis_point = Vec3::new(0.0, p[1].max(-0.75).min(0.75), 0.0);
+            assert!((Vec3::from(*p).distance(axis_point) - 0.5).abs() < 1e-5);
+        }
+    }
+}
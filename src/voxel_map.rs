@@ -28,7 +28,9 @@ use bevy::{prelude::*, render::camera::Camera, tasks::ComputeTaskPool};
 use bevy_prototype_character_controller::controller::CameraTag;
 use building_blocks::{
     prelude::*,
-    storage::{ChunkHashMapPyramid3, OctreeChunkIndex, SmallKeyHashMap},
+    storage::{
+        ChunkHashMapPyramid3, LodChunkKey3, OctreeChunkIndex, SmallKeyHashMap, SmallKeyHashSet,
+    },
 };
 
 use building_blocks::mesh::{IsOpaque, MergeVoxel};
@@ -36,21 +38,39 @@ use simdnoise::NoiseBuilder;
 
 use crate::{
     app_state::AppState,
-    chunk_generator::{chunk_detection_system, chunk_generator_system, ChunkCommandQueue},
+    biome::{biome_material, BiomePalette},
+    chunk_compression::{compress_chunk, CompressedChunk},
+    chunk_generator::{
+        chunk_detection_system, chunk_generator_system, chunk_unload_system, ChunkCommand,
+        ChunkCommandQueue, ChunkGenerationPipeline, ChunkGenerator, TerrainGenerator,
+    },
+    decoration::{decorate_column, DecorationSpills, TreeConfig},
+    frame_budget::{frame_budget_system, FrameBudget},
+    key_bindings::KeyBindings,
     level_of_detail::{level_of_detail_system, LodState},
-    mesh_fade::mesh_fade_update_system,
+    material_registry::MaterialRegistry,
+    mesh_fade::{mesh_fade_transparency_system, mesh_fade_update_system, FADE_DURATION},
     mesh_generator::{
-        mesh_despawn_system, mesh_generator_system, ChunkMeshes, MeshCommand, MeshCommandQueue,
+        collider_distance_system, mesh_despawn_system, mesh_generator_system, ChunkMeshes,
+        MeshCommand, MeshCommandQueue, MeshConfig,
     },
+    world_seed::WorldSeed,
 };
 
 pub struct VoxelMapPlugin;
 
 impl Plugin for VoxelMapPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.insert_resource(NoiseConfig::default())
+        app.init_resource::<KeyBindings>()
+            .init_resource::<WorldSeed>()
+            .init_resource::<TerrainGenerator>()
+            .init_resource::<NoiseConfig>()
+            .init_resource::<MeshConfig>()
+            .init_resource::<MaterialRegistry>()
+            .init_resource::<FrameBudget>()
             .insert_resource(VoxelMapConfig::default())
             .insert_resource(ChunkCommandQueue::default())
+            .init_resource::<ChunkGenerationPipeline>()
             .insert_resource(MeshCommandQueue::default())
             .add_system_set(
                 SystemSet::on_update(AppState::Running)
@@ -65,17 +85,30 @@ impl Plugin for VoxelMapPlugin {
                             .label("voxel_map_config_changed")
                             .after("voxel_map_config_update"),
                     )
+                    .with_system(
+                        frame_budget_system
+                            .system()
+                            .label("frame_budget")
+                            .before("chunk_generator")
+                            .before("mesh_generator"),
+                    )
                     .with_system(
                         chunk_detection_system
                             .system()
                             .label("chunk_detection")
                             .after("voxel_map_config_changed"),
                     )
+                    .with_system(
+                        chunk_unload_system
+                            .system()
+                            .label("chunk_unload")
+                            .after("chunk_detection"),
+                    )
                     .with_system(
                         chunk_generator_system
                             .system()
                             .label("chunk_generator")
-                            .after("chunk_detection"),
+                            .after("chunk_unload"),
                     )
                     .with_system(
                         level_of_detail_system
@@ -89,12 +122,24 @@ impl Plugin for VoxelMapPlugin {
                             .label("mesh_generator")
                             .after("level_of_detail"),
                     )
+                    .with_system(
+                        collider_distance_system
+                            .system()
+                            .label("collider_distance")
+                            .after("mesh_generator"),
+                    )
                     .with_system(
                         mesh_fade_update_system
                             .system()
                             .label("mesh_fade_update")
                             .before("mesh_generator"),
                     )
+                    .with_system(
+                        mesh_fade_transparency_system
+                            .system()
+                            .label("mesh_fade_transparency")
+                            .after("mesh_fade_update"),
+                    )
                     .with_system(
                         mesh_despawn_system
                             .system()
@@ -116,6 +161,35 @@ impl Voxel {
     pub const DIRT: Self = Self(4);
     pub const STONE: Self = Self(5);
     pub const SNOW: Self = Self(6);
+    pub const WOOD: Self = Self(7);
+    pub const LEAVES: Self = Self(8);
+    pub const GRAVEL: Self = Self(9);
+    pub const ICE: Self = Self(10);
+
+    /// Every non-empty material, in the order of their layers in the array texture
+    pub const MATERIALS: &'static [Self] = &[
+        Self::WATER,
+        Self::SAND,
+        Self::GRASS,
+        Self::DIRT,
+        Self::STONE,
+        Self::SNOW,
+        Self::WOOD,
+        Self::LEAVES,
+        Self::GRAVEL,
+        Self::ICE,
+    ];
+
+    /// Materials that neighboring faces can be seen through, so they must not be culled
+    pub const TRANSPARENT: &'static [Self] = &[Self::WATER, Self::LEAVES, Self::ICE];
+
+    /// Materials that can be moved through
+    pub const FLUIDS: &'static [Self] = &[Self::WATER];
+
+    /// Whether the voxel can be stood on and occludes light, whether or not it can be seen through
+    pub fn is_solid(&self) -> bool {
+        *self != Self::EMPTY && !Self::FLUIDS.contains(self)
+    }
 }
 
 impl IsEmpty for Voxel {
@@ -126,7 +200,7 @@ impl IsEmpty for Voxel {
 
 impl IsOpaque for Voxel {
     fn is_opaque(&self) -> bool {
-        true
+        !Voxel::TRANSPARENT.contains(self)
     }
 }
 
@@ -141,111 +215,670 @@ impl MergeVoxel for Voxel {
 pub struct VoxelMap {
     pub pyramid: ChunkHashMapPyramid3<Voxel>,
     pub index: OctreeChunkIndex,
+    /// Chunks of the higher LODs that have been moved out of the pyramid to save memory
+    pub compressed: SmallKeyHashMap<LodChunkKey3, CompressedChunk>,
+    /// The minimum voxel keys of the lod0 chunks that have been edited since they were generated
+    pub edited: SmallKeyHashSet<Point3i>,
+    /// Edited lod0 chunks that have been unloaded, by the key of their column, to be restored when
+    /// the column is generated again
+    pub unloaded_edits: SmallKeyHashMap<Point3i, Vec<(Point3i, CompressedChunk)>>,
+    /// Trees that reach across column boundaries, placed as the columns they reach are generated
+    pub decorations: DecorationSpills,
 }
 
 impl VoxelMap {
     pub fn new(
         pool: &Res<ComputeTaskPool>,
         voxel_map_config: &Res<VoxelMapConfig>,
-        noise_config: &Res<NoiseConfig>,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
         mut mesh_commands: ResMut<MeshCommandQueue>,
         lod0_center: Point3i,
     ) -> VoxelMap {
-        println!(
+        debug!(
             "Generating map with {} LODs of {:?} chunks...",
             voxel_map_config.num_lods, voxel_map_config.chunk_shape
         );
         // Generate a voxel map from noise.
         let map = generate_map(
             pool,
-            voxel_map_config.visible_chunks_extent,
-            noise_config,
+            voxel_map_config.generation_chunks_extent(lod0_center),
+            generator,
+            seed,
             voxel_map_config,
         );
-        println!("...DONE!!!");
+        debug!("...done");
 
         // Queue up commands to initialize the chunk meshes to their appropriate LODs given the starting camera position.
-        map.index.active_clipmap_lod_chunks(
-            &voxel_map_config.visible_voxel_extent,
+        map.enqueue_clipmap_creates(voxel_map_config, lod0_center, &mut *mesh_commands);
+        assert!(!mesh_commands.is_empty());
+        map
+    }
+
+    /// Queues the creation of a mesh for every chunk of the clipmap around `lod0_center`. The
+    /// clipmap is bounded by the generated voxels, like the updates in `level_of_detail_system`, so
+    /// it follows the camera however far it travels.
+    pub fn enqueue_clipmap_creates(
+        &self,
+        voxel_map_config: &VoxelMapConfig,
+        lod0_center: Point3i,
+        mesh_commands: &mut MeshCommandQueue,
+    ) {
+        self.index.active_clipmap_lod_chunks(
+            &self.pyramid.level(0).bounding_extent(),
             voxel_map_config.clip_box_radius,
             lod0_center,
             |chunk_key| mesh_commands.enqueue(MeshCommand::Create(chunk_key)),
         );
-        assert!(!mesh_commands.is_empty());
-        map
     }
+
+    /// A map with no chunks, for loading edits into or for tests and benchmarks that only need a
+    /// few chunks
+    pub fn empty(voxel_map_config: &VoxelMapConfig) -> VoxelMap {
+        let builder = ChunkMapBuilder3x1::new(voxel_map_config.chunk_shape, Voxel::EMPTY);
+        let pyramid = ChunkHashMapPyramid3::new(
+            builder,
+            || SmallKeyHashMap::new(),
+            voxel_map_config.num_lods,
+        );
+        let index =
+            OctreeChunkIndex::index_chunk_map(voxel_map_config.superchunk_shape, pyramid.level(0));
+        VoxelMap {
+            pyramid,
+            index,
+            compressed: SmallKeyHashMap::new(),
+            edited: SmallKeyHashSet::default(),
+            unloaded_edits: SmallKeyHashMap::new(),
+            decorations: DecorationSpills::default(),
+        }
+    }
+
+    /// The lod0 voxel at `p`, or `Voxel::EMPTY` where the map has not been generated
+    pub fn get_voxel(&self, p: Point3i) -> Voxel {
+        let lod0 = self.pyramid.level(0);
+        lod0.get_chunk(lod0.indexer.min_of_chunk_containing_point(p))
+            .map(|chunk| chunk.get(p))
+            .unwrap_or(Voxel::EMPTY)
+    }
+
+    /// Whether the voxel at `p` is one that can be stood on, so neither empty nor water
+    pub fn is_solid(&self, p: Point3i) -> bool {
+        self.get_voxel(p).is_solid()
+    }
+
+    /// The y of the topmost solid voxel in the column at `x`, `z`, if there is one
+    pub fn surface_height(&self, x: i32, z: i32) -> Option<i32> {
+        let lod0 = self.pyramid.level(0);
+        let extent = lod0.bounding_extent();
+        let chunk_height = self.pyramid.chunk_shape().y();
+        let mut chunk_min =
+            lod0.indexer
+                .min_of_chunk_containing_point(PointN([x, extent.max().y(), z]));
+        while chunk_min.y() >= extent.minimum.y() {
+            // Chunks that have not been generated are skipped whole
+            if let Some(chunk) = lod0.get_chunk(chunk_min) {
+                let top = chunk_min.y() + chunk_height - 1;
+                if let Some(y) = (chunk_min.y()..=top)
+                    .rev()
+                    .find(|y| chunk.get(PointN([x, *y, z])).is_solid())
+                {
+                    return Some(y);
+                }
+            }
+            chunk_min = chunk_min - PointN([0, chunk_height, 0]);
+        }
+        None
+    }
+
+    /// Sets the voxel at `p` and returns the `ChunkCommand::Edit`s that downsample and re-mesh its
+    /// containing chunk. If the chunk stack containing `p` has not been generated yet, it is
+    /// generated first so that the edit is not later overwritten by generation, and there is an
+    /// edit for each chunk that generating it wrote too.
+    pub fn set_voxel(
+        &mut self,
+        p: Point3i,
+        v: Voxel,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
+        voxel_map_config: &VoxelMapConfig,
+    ) -> Vec<ChunkCommand> {
+        self.edit_extent(
+            &Extent3i::from_min_and_shape(p, Point3i::ONES),
+            generator,
+            seed,
+            voxel_map_config,
+            |_| Some(v),
+        )
+    }
+
+    /// Sets all voxels within `radius` of `center`, returning one `ChunkCommand::Edit` per chunk
+    /// that the sphere changes
+    pub fn set_sphere(
+        &mut self,
+        center: Point3i,
+        radius: i32,
+        v: Voxel,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
+        voxel_map_config: &VoxelMapConfig,
+    ) -> Vec<ChunkCommand> {
+        let extent = Extent3i::from_min_and_shape(
+            center - Point3i::fill(radius),
+            Point3i::fill(2 * radius + 1),
+        );
+        self.edit_extent(&extent, generator, seed, voxel_map_config, |p| {
+            if (p - center).norm() <= radius as f32 {
+                Some(v)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Sets all voxels in `extent`, returning one `ChunkCommand::Edit` per chunk that it changes
+    pub fn set_box(
+        &mut self,
+        extent: Extent3i,
+        v: Voxel,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
+        voxel_map_config: &VoxelMapConfig,
+    ) -> Vec<ChunkCommand> {
+        self.edit_extent(&extent, generator, seed, voxel_map_config, |_| Some(v))
+    }
+
+    // Applies `edit` to every point in `extent`, where `None` leaves the voxel unchanged. The
+    // chunks are written straight back to lod0 so that later edits in the same frame build on
+    // this one, and edits are returned for the chunks in which some voxel was set and for those
+    // written by generating missing stacks, to downsample and re-mesh them.
+    fn edit_extent(
+        &mut self,
+        extent: &Extent3i,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
+        voxel_map_config: &VoxelMapConfig,
+        edit: impl Fn(Point3i) -> Option<Voxel>,
+    ) -> Vec<ChunkCommand> {
+        let voxel_keys: Vec<_> = self
+            .pyramid
+            .level(0)
+            .indexer
+            .chunk_keys_for_extent(extent)
+            .collect();
+        let mut commands = Vec::new();
+        for voxel_key in voxel_keys.into_iter() {
+            commands.extend(
+                self.generate_stack_if_missing(voxel_key, generator, seed, voxel_map_config)
+                    .into_iter()
+                    .map(ChunkCommand::Edit),
+            );
+
+            let chunk_extent =
+                Extent3i::from_min_and_shape(voxel_key, voxel_map_config.chunk_shape);
+            let mut chunk = self
+                .pyramid
+                .level(0)
+                .get_chunk(voxel_key)
+                .cloned()
+                .unwrap_or_else(|| Array3x1::fill(chunk_extent, Voxel::EMPTY));
+            let mut changed = false;
+            chunk.for_each_mut(
+                &chunk_extent.intersection(extent),
+                |p: Point3i, voxel: &mut Voxel| {
+                    if let Some(v) = edit(p) {
+                        *voxel = v;
+                        changed = true;
+                    }
+                },
+            );
+            if changed {
+                self.pyramid.level_mut(0).write_chunk(voxel_key, chunk);
+                self.edited.insert(voxel_key);
+                commands.push(ChunkCommand::Edit(voxel_key));
+            }
+        }
+        commands
+    }
+
+    /// Moves the lod0 chunk at `voxel_key` out of the pyramid. It is kept compressed if it has been
+    /// edited and `keep_edits` is set, otherwise it will be generated from scratch next time.
+    pub fn unload_chunk(&mut self, voxel_key: Point3i, chunk_log2: i32, keep_edits: bool) -> bool {
+        let chunk = match self.pyramid.level_mut(0).pop_chunk(voxel_key) {
+            Some(chunk) => chunk,
+            None => return false,
+        };
+        let column_key = PointN([voxel_key.x() >> chunk_log2, 0, voxel_key.z() >> chunk_log2]);
+        self.decorations.remove_source(column_key);
+        if self.edited.remove(&voxel_key) && keep_edits {
+            self.unloaded_edits
+                .entry(column_key)
+                .or_insert_with(Vec::new)
+                .push((voxel_key, compress_chunk(&chunk)));
+        }
+        true
+    }
+
+    /// Takes the unloaded edited chunks of the column, which are marked as edited again
+    pub fn take_unloaded_edits(&mut self, column_key: Point3i) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        let chunks = self.unloaded_edits.remove(&column_key).unwrap_or_default();
+        chunks
+            .into_iter()
+            .map(|(voxel_key, compressed)| {
+                self.edited.insert(voxel_key);
+                (voxel_key, compressed.decompress())
+            })
+            .collect()
+    }
+
+    // Generates the chunk stack containing the chunk at `voxel_key` if none of the stack exists,
+    // returning the keys of the lod0 chunks that were written, which include those that its
+    // decorations reach into. They are added to the index when they are downsampled.
+    fn generate_stack_if_missing(
+        &mut self,
+        voxel_key: Point3i,
+        generator: &dyn ChunkGenerator,
+        seed: WorldSeed,
+        voxel_map_config: &VoxelMapConfig,
+    ) -> Vec<Point3i> {
+        let lod0 = self.pyramid.level_mut(0);
+        if lod0.get_chunk(voxel_key).is_some() {
+            return Vec::new();
+        }
+        let stack_key = PointN([
+            voxel_key.x() >> voxel_map_config.chunk_log2,
+            0,
+            voxel_key.z() >> voxel_map_config.chunk_log2,
+        ]);
+        let lod0_voxel_extent = lod0.bounding_extent();
+        let min_y = lod0_voxel_extent.minimum.y() >> voxel_map_config.chunk_log2;
+        let max_y = lod0_voxel_extent.max().y() >> voxel_map_config.chunk_log2;
+        let stack_exists = (min_y..=max_y).any(|y| {
+            let key = PointN([
+                voxel_key.x(),
+                y << voxel_map_config.chunk_log2,
+                voxel_key.z(),
+            ]);
+            lod0.get_chunk(key).is_some()
+        });
+        if stack_exists {
+            return Vec::new();
+        }
+        let mut chunks = generator.generate(stack_key, seed, voxel_map_config);
+        let spills = decorate_column(stack_key, &mut chunks, seed, voxel_map_config);
+        self.decorations
+            .place_incoming(stack_key, &mut chunks, voxel_map_config.chunk_shape);
+        chunks.extend(self.take_unloaded_edits(stack_key));
+        let lod0 = self.pyramid.level_mut(0);
+        let mut written = Vec::with_capacity(chunks.len());
+        for (key, chunk) in chunks.into_iter() {
+            lod0.write_chunk(key, chunk);
+            written.push(key);
+        }
+        written.extend(
+            self.decorations
+                .add(lod0, stack_key, spills, voxel_map_config),
+        );
+        written
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TerrainNoiseMode {
+    /// A 2D ridge noise heightmap. Cannot produce caves or overhangs.
+    Heightmap2D,
+    /// A 3D fbm noise density field, biased by height so that there is ground below y_offset and
+    /// sky above it.
+    Density3D,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct NoiseConfig {
     frequency: f32,
-    seed: i32,
     octaves: u8,
     y_offset: f32,
     y_scale: f32,
+    pub mode: TerrainNoiseMode,
+    /// Voxels are filled where the Density3D density exceeds this value
+    pub density_threshold: f32,
+    pub biome_frequency: f32,
+    biomes: Vec<BiomePalette>,
+    /// Empty voxels below this height are filled with water
+    pub sea_level: i32,
+    pub temperature: TemperatureConfig,
+}
+
+/// A temperature field that falls with altitude and towards the poles. Ground that is at or below
+/// `snow_temperature` is covered in snow, whatever its biome's material. Only the snow that lasts
+/// through midsummer is generated, the rest comes and goes with the `Season`.
+#[derive(Clone, Debug)]
+pub struct TemperatureConfig {
+    /// Degrees Celsius at sea level on the equator
+    pub equator_temperature: f32,
+    /// Degrees Celsius at sea level at the poles
+    pub pole_temperature: f32,
+    /// Degrees Celsius lost per voxel above sea level
+    pub lapse_rate: f32,
+    /// The latitude in degrees at z = 0, like `SolarPosition::latitude`. North is towards -z.
+    pub origin_latitude: f32,
+    pub voxels_per_degree: f32,
+    pub snow_temperature: f32,
+    /// Degrees Celsius that the temperature rises above its mean at midsummer and falls below it
+    /// at midwinter
+    pub seasonal_amplitude: f32,
+}
+
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            equator_temperature: 30.0,
+            pole_temperature: -25.0,
+            // Puts the snow line about 250 voxels above sea level at the origin latitude
+            lapse_rate: 0.01,
+            origin_latitude: 45.0,
+            voxels_per_degree: 1024.0,
+            snow_temperature: 0.0,
+            seasonal_amplitude: 3.0,
+        }
+    }
+}
+
+impl TemperatureConfig {
+    pub fn latitude(&self, z: i32) -> f32 {
+        (self.origin_latitude - z as f32 / self.voxels_per_degree)
+            .max(-90.0)
+            .min(90.0)
+    }
+
+    /// The yearly mean in degrees Celsius at `p`, from its altitude and latitude
+    pub fn mean_temperature(&self, p: Point3i, sea_level: i32) -> f32 {
+        let latitude = self.latitude(p.z());
+        let sea_level_temperature = self.equator_temperature
+            + (self.pole_temperature - self.equator_temperature) * latitude.abs() / 90.0;
+        sea_level_temperature - self.lapse_rate * (p.y() - sea_level) as f32
+    }
+
+    /// The temperature at `p` when the season is at `phase`, which is 1 at the northern midsummer
+    /// and -1 at the northern midwinter. The seasons are the other way around south of the
+    /// equator.
+    pub fn seasonal_temperature(&self, p: Point3i, sea_level: i32, phase: f32) -> f32 {
+        let hemisphere = self.latitude(p.z()).signum();
+        self.mean_temperature(p, sea_level) + self.seasonal_amplitude * phase * hemisphere
+    }
+
+    /// Whether ground at `p` is covered in snow when the season is at `phase`
+    pub fn is_snow(&self, p: Point3i, sea_level: i32, phase: f32) -> bool {
+        self.seasonal_temperature(p, sea_level, phase) <= self.snow_temperature
+    }
 }
 
 impl Default for NoiseConfig {
     fn default() -> Self {
         Self {
             frequency: 1.0 / 256.0,
-            seed: 1234,
             octaves: 5,
             y_offset: 128.0,
             y_scale: 1024.0,
+            mode: TerrainNoiseMode::Heightmap2D,
+            density_threshold: 0.0,
+            biome_frequency: 1.0 / 2048.0,
+            biomes: vec![
+                BiomePalette::tundra(),
+                BiomePalette::forest(),
+                BiomePalette::temperate(),
+                BiomePalette::desert(),
+            ],
+            // The top of the water material of every biome
+            sea_level: 148,
+            temperature: TemperatureConfig::default(),
         }
     }
 }
 
+impl NoiseConfig {
+    /// Ridge or fbm noise of `frequency` per voxel and `octaves`, whose output is scaled by
+    /// `y_scale` and offset by `y_offset` into voxel heights. The other settings start at their
+    /// defaults. The seed comes from the `WorldSeed`.
+    pub fn new(frequency: f32, octaves: u8, y_offset: f32, y_scale: f32) -> Self {
+        Self::default()
+            .with_frequency(frequency)
+            .with_octaves(octaves)
+            .with_y_offset(y_offset)
+            .with_y_scale(y_scale)
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.set_frequency(frequency);
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u8) -> Self {
+        self.set_octaves(octaves);
+        self
+    }
+
+    pub fn with_y_offset(mut self, y_offset: f32) -> Self {
+        self.set_y_offset(y_offset);
+        self
+    }
+
+    pub fn with_y_scale(mut self, y_scale: f32) -> Self {
+        self.set_y_scale(y_scale);
+        self
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        assert!(
+            frequency > 0.0 && frequency.is_finite(),
+            "The noise frequency must be positive: {}",
+            frequency
+        );
+        self.frequency = frequency;
+    }
+
+    pub fn set_octaves(&mut self, octaves: u8) {
+        assert!(octaves >= 1, "At least one noise octave is required");
+        self.octaves = octaves;
+    }
+
+    pub fn set_y_offset(&mut self, y_offset: f32) {
+        assert!(
+            y_offset.is_finite(),
+            "The y offset must be finite: {}",
+            y_offset
+        );
+        self.y_offset = y_offset;
+    }
+
+    /// Heights are divided by the scale to find their material, so it must not be 0
+    pub fn set_y_scale(&mut self, y_scale: f32) {
+        assert!(
+            y_scale != 0.0 && y_scale.is_finite(),
+            "The y scale must be finite and not 0: {}",
+            y_scale
+        );
+        self.y_scale = y_scale;
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    pub fn octaves(&self) -> u8 {
+        self.octaves
+    }
+
+    pub fn y_offset(&self) -> f32 {
+        self.y_offset
+    }
+
+    pub fn y_scale(&self) -> f32 {
+        self.y_scale
+    }
+
+    /// Replaces the height to material mapping with a single biome. The noise heights must be
+    /// strictly increasing.
+    pub fn with_material_breakpoints(self, material_breakpoints: Vec<(f32, Voxel)>) -> Self {
+        self.with_biomes(vec![BiomePalette::new("custom", 0.0, material_breakpoints)])
+    }
+
+    /// Replaces the biomes. Each column uses the biome nearest to its sampled biome noise value.
+    pub fn with_biomes(mut self, biomes: Vec<BiomePalette>) -> Self {
+        assert!(!biomes.is_empty(), "At least one biome is required");
+        self.biomes = biomes;
+        self
+    }
+
+    pub fn biomes(&self) -> &[BiomePalette] {
+        &self.biomes
+    }
+
+    /// The yearly mean in degrees Celsius at `p`, from its altitude above `sea_level` and its
+    /// latitude
+    pub fn temperature_at(&self, p: Point3i) -> f32 {
+        self.temperature.mean_temperature(p, self.sea_level)
+    }
+}
+
 const VISIBLE_SIZE_VOXELS: i32 = 4096;
+// The vertical range that is detected and meshed, deep enough for caves in the Density3D terrain
+// and high enough for mountains
+const WORLD_MIN_Y: i32 = -1024;
+const WORLD_HEIGHT_VOXELS: i32 = 3072;
 
+#[derive(Clone, Debug)]
 pub struct VoxelMapConfig {
     pub chunk_log2: i32,
     pub chunk_shape: Point3i,
     pub num_lods: u8,
     pub superchunk_shape: Point3i,
+    /// Chunks within this many chunks of the camera are at lod0, and each coarser LOD covers
+    /// twice the distance of the one before. It only affects which chunks are meshed at which LOD,
+    /// so it can be changed at any time without regenerating the map.
     pub clip_box_radius: i32,
     pub visible_chunks_extent: Extent3i,
+    /// Relative to the camera. Its shape sets the default `generation_radius_chunks`.
     pub visible_voxel_extent: Extent3i,
+    /// Chunks at this LOD and above are kept run-length encoded. They change rarely and make up
+    /// most of the far terrain.
+    pub compressed_min_lod: u8,
+    pub meshing_mode: MeshingMode,
+    /// Only lod0 chunks within this many chunks of the camera get colliders
+    pub collider_radius_chunks: i32,
+    /// Seconds for a lod0 chunk mesh to fade in or out. See `fade_duration_for_lod`.
+    pub fade_duration: f32,
+    /// The camera must move this far from where it last caused a LOD update, as well as into
+    /// another chunk, to cause the next one
+    pub lod_hysteresis_voxels: f32,
+    /// Columns within this many chunks of the camera in x and z are generated, independently of
+    /// how far out they are meshed. Derived from the visible extent by `new`.
+    pub generation_radius_chunks: i32,
+    /// How many chunks beyond the generation radius to generate in the direction the camera is
+    /// moving
+    pub prefetch_chunks: i32,
+    /// lod0 chunks further than this many chunks from the camera in x or z are unloaded. It should
+    /// be beyond `generation_radius_chunks` plus `prefetch_chunks`, or columns are unloaded as soon
+    /// as they are generated.
+    pub keep_radius_chunks: i32,
+    /// Keep edited chunks in memory, compressed, when they are unloaded so that the edits are
+    /// restored when their column is generated again, and included by `save_to_path`
+    pub keep_unloaded_edits: bool,
+    /// Trees placed on grass once each column has been generated
+    pub trees: TreeConfig,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MeshingMode {
+    /// Blocky, textured meshes
+    GreedyQuads,
+    /// Smooth meshes with a single material
+    SurfaceNets,
 }
 
 impl Default for VoxelMapConfig {
     fn default() -> Self {
         let chunk_log2 = 5;
         let num_lods = 6;
-        let clip_box_radius = 8;
         VoxelMapConfig::new(
             chunk_log2,
             num_lods,
-            clip_box_radius,
             Extent3i::from_min_and_shape(
-                PointN([-VISIBLE_SIZE_VOXELS / 2, 0, -VISIBLE_SIZE_VOXELS / 2]),
-                PointN([VISIBLE_SIZE_VOXELS, 1, VISIBLE_SIZE_VOXELS]),
+                PointN([
+                    -VISIBLE_SIZE_VOXELS / 2,
+                    WORLD_MIN_Y,
+                    -VISIBLE_SIZE_VOXELS / 2,
+                ]),
+                PointN([
+                    VISIBLE_SIZE_VOXELS,
+                    WORLD_HEIGHT_VOXELS,
+                    VISIBLE_SIZE_VOXELS,
+                ]),
             ),
         )
     }
 }
 
 impl VoxelMapConfig {
-    pub fn new(
-        chunk_log2: i32,
-        num_lods: u8,
-        clip_box_radius: i32,
-        visible_voxel_extent: Extent3i,
-    ) -> VoxelMapConfig {
+    /// The shapes and extents are derived from `chunk_log2` and `num_lods`. The other settings
+    /// start at their defaults.
+    pub fn new(chunk_log2: i32, num_lods: u8, visible_voxel_extent: Extent3i) -> VoxelMapConfig {
+        let generation_radius_chunks = (visible_voxel_extent.shape.x() / 2) >> chunk_log2;
+        let prefetch_chunks = 4;
         VoxelMapConfig {
             chunk_log2,
             chunk_shape: PointN([1 << chunk_log2; 3]),
             num_lods,
             superchunk_shape: PointN([1 << (chunk_log2 + num_lods as i32 - 1); 3]),
-            clip_box_radius,
+            clip_box_radius: 8,
             visible_chunks_extent: Extent3i {
                 minimum: visible_voxel_extent.minimum >> chunk_log2,
                 shape: visible_voxel_extent.shape >> chunk_log2,
             },
             visible_voxel_extent,
+            compressed_min_lod: 2,
+            meshing_mode: MeshingMode::GreedyQuads,
+            collider_radius_chunks: 4,
+            fade_duration: FADE_DURATION,
+            lod_hysteresis_voxels: (1 << chunk_log2) as f32 / 4.0,
+            generation_radius_chunks,
+            prefetch_chunks,
+            keep_radius_chunks: generation_radius_chunks + 2 * prefetch_chunks,
+            keep_unloaded_edits: true,
+            trees: TreeConfig::default(),
+        }
+    }
+
+    /// The columns to generate around the chunk `center`. Only x and z are meaningful.
+    pub fn generation_chunks_extent(&self, center: Point3i) -> Extent3i {
+        let radius = PointN([
+            self.generation_radius_chunks,
+            0,
+            self.generation_radius_chunks,
+        ]);
+        Extent3i::from_min_and_max(center - radius, center + radius)
+    }
+
+    /// Rebuilds the derived shapes and extents after `chunk_log2` or `num_lods` change, keeping
+    /// the other settings
+    fn rebuild(&self) -> VoxelMapConfig {
+        VoxelMapConfig {
+            clip_box_radius: self.clip_box_radius,
+            compressed_min_lod: self.compressed_min_lod,
+            meshing_mode: self.meshing_mode,
+            collider_radius_chunks: self.collider_radius_chunks,
+            fade_duration: self.fade_duration,
+            lod_hysteresis_voxels: self.lod_hysteresis_voxels,
+            prefetch_chunks: self.prefetch_chunks,
+            keep_unloaded_edits: self.keep_unloaded_edits,
+            trees: self.trees.clone(),
+            ..VoxelMapConfig::new(self.chunk_log2, self.num_lods, self.visible_voxel_extent)
         }
     }
+
+    /// Coarser chunks cover more of the view when they change, so they fade more slowly to make
+    /// far LOD changes less noticeable
+    pub fn fade_duration_for_lod(&self, lod: u8) -> f32 {
+        self.fade_duration * (1.0 + 0.5 * lod as f32)
+    }
 }
 
 const MAX_CLIP_BOX_RADIUS: i32 = 32;
@@ -254,87 +887,144 @@ const MAX_CHUNK_LOG2: i32 = 6;
 // due to using an OctreeSet for a 'superchunk' and OctreeSet LocationCodes are limited
 // to 6 levels.
 const MAX_LODS: u8 = 6;
+const MAX_NOISE_OCTAVES: u8 = 8;
+// Each press of the noise frequency keys multiplies or divides the frequency by this
+const NOISE_FREQUENCY_STEP: f32 = 1.25;
 
 pub fn voxel_map_config_update_system(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut voxel_map_config: ResMut<VoxelMapConfig>,
+    mut noise_config: ResMut<NoiseConfig>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::R) {
+    if keyboard_input.just_pressed(key_bindings.cycle_clip_box_radius) {
         voxel_map_config.clip_box_radius <<= 1;
         if voxel_map_config.clip_box_radius > MAX_CLIP_BOX_RADIUS {
             voxel_map_config.clip_box_radius = 1;
         }
-        println!("Clip box radius: {}", voxel_map_config.clip_box_radius);
+        info!("Clip box radius: {}", voxel_map_config.clip_box_radius);
     }
-    if keyboard_input.just_pressed(KeyCode::C) {
+    if keyboard_input.just_pressed(key_bindings.cycle_chunk_log2) {
         voxel_map_config.chunk_log2 += 1;
         if voxel_map_config.chunk_log2 > MAX_CHUNK_LOG2 {
             voxel_map_config.chunk_log2 = 1;
         }
-        println!("Chunk log2: {}", voxel_map_config.chunk_log2);
-        *voxel_map_config = VoxelMapConfig::new(
-            voxel_map_config.chunk_log2,
-            voxel_map_config.num_lods,
-            voxel_map_config.clip_box_radius,
-            voxel_map_config.visible_voxel_extent,
-        );
+        info!("Chunk log2: {}", voxel_map_config.chunk_log2);
+        *voxel_map_config = voxel_map_config.rebuild();
     }
-    if keyboard_input.just_pressed(KeyCode::L) {
+    if keyboard_input.just_pressed(key_bindings.cycle_num_lods) {
         voxel_map_config.num_lods += 1;
         if voxel_map_config.num_lods > MAX_LODS {
             voxel_map_config.num_lods = 1;
         }
-        println!("Number of LoDs: {}", voxel_map_config.num_lods);
-        *voxel_map_config = VoxelMapConfig::new(
-            voxel_map_config.chunk_log2,
-            voxel_map_config.num_lods,
-            voxel_map_config.clip_box_radius,
-            voxel_map_config.visible_voxel_extent,
-        );
+        info!("Number of LoDs: {}", voxel_map_config.num_lods);
+        *voxel_map_config = voxel_map_config.rebuild();
+    }
+    if keyboard_input.just_pressed(key_bindings.toggle_meshing_mode) {
+        voxel_map_config.meshing_mode = match voxel_map_config.meshing_mode {
+            MeshingMode::GreedyQuads => MeshingMode::SurfaceNets,
+            MeshingMode::SurfaceNets => MeshingMode::GreedyQuads,
+        };
+        info!("Meshing mode: {:?}", voxel_map_config.meshing_mode);
+    }
+    // Only dereference mutably on a key press, as any change regenerates the map
+    if keyboard_input.just_pressed(key_bindings.decrease_noise_frequency) {
+        let frequency = noise_config.frequency() / NOISE_FREQUENCY_STEP;
+        noise_config.set_frequency(frequency);
+        info!("Noise frequency: {}", noise_config.frequency());
+    }
+    if keyboard_input.just_pressed(key_bindings.increase_noise_frequency) {
+        let frequency = noise_config.frequency() * NOISE_FREQUENCY_STEP;
+        noise_config.set_frequency(frequency);
+        info!("Noise frequency: {}", noise_config.frequency());
+    }
+    if keyboard_input.just_pressed(key_bindings.cycle_noise_octaves) {
+        let octaves = noise_config.octaves() % MAX_NOISE_OCTAVES + 1;
+        noise_config.set_octaves(octaves);
+        info!("Noise octaves: {}", noise_config.octaves());
     }
 }
 
+/// Regenerates the map when the voxels it would contain change, and otherwise only recreates the
+/// chunk meshes when the clipmap or the meshing changes. A change to the NoiseConfig resource
+/// replaces the TerrainGenerator with a copy that uses the new config, if it uses one at all.
 pub fn voxel_map_config_changed_system(
     cameras: Query<(&Camera, &GlobalTransform), With<CameraTag>>,
     pool: Res<ComputeTaskPool>,
     mut voxel_map: ResMut<VoxelMap>,
     voxel_map_config: Res<VoxelMapConfig>,
     mut lod_state: ResMut<LodState>,
+    mut terrain_generator: ResMut<TerrainGenerator>,
     noise_config: Res<NoiseConfig>,
+    world_seed: Res<WorldSeed>,
     mut chunk_meshes: ResMut<ChunkMeshes>,
     mut mesh_commands: ResMut<MeshCommandQueue>,
+    mut chunk_generation: ResMut<ChunkGenerationPipeline>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
+    // The config that the map and its meshes were last built with
+    mut built_config: Local<Option<VoxelMapConfig>>,
 ) {
-    if voxel_map_config.is_changed() && !voxel_map_config.is_added() {
-        chunk_meshes.clear_entities(&mut commands, &mut meshes);
-        mesh_commands.clear();
-
-        let camera_position = if let Some((_camera, tfm)) = cameras.iter().next() {
-            tfm.translation
-        } else {
-            return;
-        };
+    if noise_config.is_changed() && !noise_config.is_added() {
+        match terrain_generator.with_noise_config(&*noise_config) {
+            Some(generator) => *terrain_generator = TerrainGenerator(generator),
+            None => warn!("The TerrainGenerator does not use the NoiseConfig, so it is unchanged"),
+        }
+    }
+    let built = built_config.get_or_insert_with(|| voxel_map_config.clone());
+    let regenerate = (terrain_generator.is_changed() && !terrain_generator.is_added())
+        || (world_seed.is_changed() && !world_seed.is_added())
+        || built.chunk_log2 != voxel_map_config.chunk_log2
+        || built.num_lods != voxel_map_config.num_lods
+        || built.visible_voxel_extent != voxel_map_config.visible_voxel_extent;
+    let remesh = built.clip_box_radius != voxel_map_config.clip_box_radius
+        || built.meshing_mode != voxel_map_config.meshing_mode;
+    if !regenerate && !remesh {
+        return;
+    }
+
+    let camera_position = if let Some((_camera, tfm)) = cameras.iter().next() {
+        tfm.translation
+    } else {
+        return;
+    };
+    *built = voxel_map_config.clone();
 
-        let lod0_center = Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
+    chunk_meshes.clear_entities(&mut commands, &mut meshes);
+    mesh_commands.clear();
 
+    let lod0_center = Point3f::from(camera_position).in_voxel() >> voxel_map_config.chunk_log2;
+
+    if regenerate {
+        chunk_generation.invalidate();
         *voxel_map = VoxelMap::new(
             &pool,
             &voxel_map_config,
-            &noise_config,
+            &*terrain_generator,
+            *world_seed,
             mesh_commands,
             lod0_center,
         );
-        lod_state.old_lod0_center = lod0_center;
+    } else {
+        voxel_map.enqueue_clipmap_creates(&voxel_map_config, lod0_center, &mut *mesh_commands);
     }
+    *lod_state = LodState::new(lod0_center);
 }
 
+// Columns generated by each task of generate_map
+const MAP_COLUMNS_PER_TASK: usize = 16;
+
+/// Generates every column of `chunks_extent`, blocking until done. At most `pool.thread_num()`
+/// tasks are queued at once.
 pub fn generate_map(
     pool: &Res<ComputeTaskPool>,
     chunks_extent: Extent3i,
-    noise_config: &Res<NoiseConfig>,
+    generator: &dyn ChunkGenerator,
+    seed: WorldSeed,
     voxel_map_config: &Res<VoxelMapConfig>,
 ) -> VoxelMap {
+    let span = info_span!("generate_map", extent = ?chunks_extent);
+    let _guard = span.enter();
     let builder = ChunkMapBuilder3x1::new(voxel_map_config.chunk_shape, Voxel::EMPTY);
     let mut pyramid = ChunkHashMapPyramid3::new(
         builder,
@@ -343,16 +1033,47 @@ pub fn generate_map(
     );
     let lod0 = pyramid.level_mut(0);
 
-    let chunks = pool.scope(|s| {
-        for x in chunks_extent.minimum.x()..chunks_extent.least_upper_bound().x() {
-            for z in chunks_extent.minimum.z()..chunks_extent.least_upper_bound().z() {
-                let p = PointN([x, 0, z]);
-                s.spawn(async move { generate_chunk_stack(p, noise_config, voxel_map_config) });
+    let mut columns = Vec::new();
+    for x in chunks_extent.minimum.x()..chunks_extent.least_upper_bound().x() {
+        for z in chunks_extent.minimum.z()..chunks_extent.least_upper_bound().z() {
+            columns.push(PointN([x, 0, z]));
+        }
+    }
+    // One task per thread at a time, rather than one per column, so that the pool's queue stays
+    // short and the chunks of each batch are written before the next is generated
+    let batch_size = pool.thread_num() * MAP_COLUMNS_PER_TASK;
+    let mut decorations = DecorationSpills::default();
+    for batch in columns.chunks(batch_size) {
+        let decorations_ref = &decorations;
+        let generated = pool.scope(|s| {
+            for task_columns in batch.chunks(MAP_COLUMNS_PER_TASK) {
+                s.spawn(async move {
+                    task_columns
+                        .iter()
+                        .map(|p| {
+                            let mut chunks = generator.generate(*p, seed, voxel_map_config);
+                            let spills = decorate_column(*p, &mut chunks, seed, voxel_map_config);
+                            decorations_ref.place_incoming(
+                                *p,
+                                &mut chunks,
+                                voxel_map_config.chunk_shape,
+                            );
+                            (*p, chunks, spills)
+                        })
+                        .collect::<Vec<_>>()
+                });
             }
+        });
+        let mut generated: Vec<_> = generated.into_iter().flatten().collect();
+        for (_, chunks, _) in generated.iter_mut() {
+            for (chunk_key, chunk) in chunks.drain(..) {
+                lod0.write_chunk(chunk_key, chunk);
+            }
+        }
+        // After the whole batch is written, so that trees reach into the columns next to them
+        for (column_key, _, spills) in generated.into_iter() {
+            decorations.add(lod0, column_key, spills, voxel_map_config);
         }
-    });
-    for (chunk_key, chunk) in chunks.into_iter().flatten() {
-        lod0.write_chunk(chunk_key, chunk);
     }
 
     let index = OctreeChunkIndex::index_chunk_map(voxel_map_config.superchunk_shape, lod0);
@@ -360,7 +1081,16 @@ pub fn generate_map(
     let world_extent = lod0.bounding_extent();
     pyramid.downsample_chunks_with_index(&index, &PointDownsampler, &world_extent);
 
-    VoxelMap { pyramid, index }
+    let mut map = VoxelMap {
+        pyramid,
+        index,
+        compressed: SmallKeyHashMap::new(),
+        edited: SmallKeyHashSet::default(),
+        unloaded_edits: SmallKeyHashMap::new(),
+        decorations,
+    };
+    map.compress_extent(&world_extent, voxel_map_config.compressed_min_lod);
+    map
 }
 
 fn index(p: Point3i, shape: Point3i) -> usize {
@@ -371,10 +1101,48 @@ fn scale_noise(v: f32, config: &NoiseConfig) -> f32 {
     (v - 4.5) * config.y_scale + config.y_offset
 }
 
+fn unscale_noise(y: f32, config: &NoiseConfig) -> f32 {
+    (y - config.y_offset) / config.y_scale + 4.5
+}
+
+fn generate_biome_noise(
+    chunk_voxel_extent: &Extent3i,
+    seed: WorldSeed,
+    noise_config: &NoiseConfig,
+) -> Vec<f32> {
+    let (noise, _min, _max) = NoiseBuilder::gradient_2d_offset(
+        chunk_voxel_extent.minimum.x() as f32,
+        chunk_voxel_extent.shape.x() as usize,
+        chunk_voxel_extent.minimum.z() as f32,
+        chunk_voxel_extent.shape.z() as usize,
+    )
+    .with_seed(seed.derive("biome").as_i32())
+    .with_freq(noise_config.biome_frequency)
+    .generate();
+    noise
+}
+
 pub fn generate_chunk_stack(
     key: Point3i,
-    noise_config: &Res<NoiseConfig>,
-    voxel_map_config: &Res<VoxelMapConfig>,
+    seed: WorldSeed,
+    noise_config: &NoiseConfig,
+    voxel_map_config: &VoxelMapConfig,
+) -> Vec<(Point3i, Array3x1<Voxel>)> {
+    match noise_config.mode {
+        TerrainNoiseMode::Heightmap2D => {
+            generate_heightmap_chunk_stack(key, seed, noise_config, voxel_map_config)
+        }
+        TerrainNoiseMode::Density3D => {
+            generate_density_chunk_stack(key, seed, noise_config, voxel_map_config)
+        }
+    }
+}
+
+fn generate_heightmap_chunk_stack(
+    key: Point3i,
+    seed: WorldSeed,
+    noise_config: &NoiseConfig,
+    voxel_map_config: &VoxelMapConfig,
 ) -> Vec<(Point3i, Array3x1<Voxel>)> {
     let chunk_min = key * voxel_map_config.chunk_shape;
     let chunk_voxel_extent = Extent3i::from_min_and_shape(chunk_min, voxel_map_config.chunk_shape);
@@ -385,15 +1153,17 @@ pub fn generate_chunk_stack(
         chunk_voxel_extent.minimum.z() as f32,
         chunk_voxel_extent.shape.z() as usize,
     )
-    .with_seed(noise_config.seed)
+    .with_seed(seed.as_i32())
     .with_freq(noise_config.frequency)
     .with_octaves(noise_config.octaves)
     .generate();
+    let biome_noise = generate_biome_noise(&chunk_voxel_extent, seed, noise_config);
 
     let mut chunks = Vec::new();
 
     let min_y_chunk = (scale_noise(min_y, &noise_config) as i32) >> voxel_map_config.chunk_log2;
-    let max_y_chunk = (scale_noise(max_y, &noise_config) as i32) >> voxel_map_config.chunk_log2;
+    let max_y_chunk = ((scale_noise(max_y, &noise_config) as i32).max(noise_config.sea_level - 1))
+        >> voxel_map_config.chunk_log2;
     for y_min_chunk in (min_y_chunk - 1)..=max_y_chunk {
         let y_min = y_min_chunk << voxel_map_config.chunk_log2;
         let y_chunk_min = PointN([chunk_min.x(), y_min, chunk_min.z()]);
@@ -404,7 +1174,9 @@ pub fn generate_chunk_stack(
             let local_p = p - chunk_min;
             let noise_index = index(local_p, voxel_map_config.chunk_shape);
             if (p.y() as f32) < scale_noise(noise[noise_index], &noise_config) {
-                *v = height_to_material(p.y(), &noise_config);
+                *v = height_to_material(p, biome_noise[noise_index], &noise_config);
+            } else if p.y() < noise_config.sea_level {
+                *v = Voxel::WATER;
             }
         });
         chunks.push((y_chunk_min, chunk_noise));
@@ -413,14 +1185,258 @@ pub fn generate_chunk_stack(
     chunks
 }
 
-// FIXME: Make this more generic - take a config for the thresholds
-fn height_to_material(y: i32, config: &NoiseConfig) -> Voxel {
-    match y as f32 {
-        y if y < scale_noise(4.52, config) => Voxel::WATER,
-        y if y < scale_noise(4.54, config) => Voxel::SAND,
-        y if y < scale_noise(4.55, config) => Voxel::DIRT,
-        y if y < scale_noise(4.7, config) => Voxel::GRASS,
-        y if y < scale_noise(4.8, config) => Voxel::STONE,
-        _ => Voxel::SNOW,
+fn index3(p: Point3i, shape: Point3i) -> usize {
+    ((p.z() * shape.y() + p.y()) * shape.x() + p.x()) as usize
+}
+
+fn generate_density_chunk_stack(
+    key: Point3i,
+    seed: WorldSeed,
+    noise_config: &NoiseConfig,
+    voxel_map_config: &VoxelMapConfig,
+) -> Vec<(Point3i, Array3x1<Voxel>)> {
+    let chunk_min = key * voxel_map_config.chunk_shape;
+    let biome_noise = generate_biome_noise(
+        &Extent3i::from_min_and_shape(chunk_min, voxel_map_config.chunk_shape),
+        seed,
+        noise_config,
+    );
+
+    // Only the chunks where the height bias is within about one of the threshold are sampled.
+    // The bias makes the density mostly solid towards the bottom of that band and mostly empty
+    // towards its top, but caves can still open through the bottom, so the chunks from there
+    // down to the floor of the map are filled with stone. There is at least one, so that the
+    // band never has a hole underneath.
+    let min_y_chunk =
+        (noise_config.y_offset - noise_config.y_scale) as i32 >> voxel_map_config.chunk_log2;
+    let max_y_chunk =
+        (noise_config.y_offset + noise_config.y_scale) as i32 >> voxel_map_config.chunk_log2;
+    let floor_y_chunk = (voxel_map_config.visible_voxel_extent.minimum.y()
+        >> voxel_map_config.chunk_log2)
+        .min(min_y_chunk - 1);
+
+    let mut chunks = Vec::new();
+    for y_min_chunk in floor_y_chunk..min_y_chunk {
+        let y_chunk_min = PointN([
+            chunk_min.x(),
+            y_min_chunk << voxel_map_config.chunk_log2,
+            chunk_min.z(),
+        ]);
+        let y_chunk_voxel_extent =
+            Extent3i::from_min_and_shape(y_chunk_min, voxel_map_config.chunk_shape);
+        chunks.push((
+            y_chunk_min,
+            Array3x1::fill(y_chunk_voxel_extent, Voxel::STONE),
+        ));
+    }
+    for y_min_chunk in min_y_chunk..=max_y_chunk {
+        let y_min = y_min_chunk << voxel_map_config.chunk_log2;
+        let y_chunk_min = PointN([chunk_min.x(), y_min, chunk_min.z()]);
+        let y_chunk_voxel_extent =
+            Extent3i::from_min_and_shape(y_chunk_min, voxel_map_config.chunk_shape);
+
+        let (noise, _min, _max) = NoiseBuilder::fbm_3d_offset(
+            y_chunk_voxel_extent.minimum.x() as f32,
+            y_chunk_voxel_extent.shape.x() as usize,
+            y_chunk_voxel_extent.minimum.y() as f32,
+            y_chunk_voxel_extent.shape.y() as usize,
+            y_chunk_voxel_extent.minimum.z() as f32,
+            y_chunk_voxel_extent.shape.z() as usize,
+        )
+        .with_seed(seed.as_i32())
+        .with_freq(noise_config.frequency)
+        .with_octaves(noise_config.octaves)
+        .generate();
+
+        let mut is_empty = true;
+        let mut chunk_noise = Array3x1::fill(y_chunk_voxel_extent, Voxel::EMPTY);
+        chunk_noise.for_each_mut(&y_chunk_voxel_extent, |p: Point3i, v: &mut Voxel| {
+            let local_p = p - y_chunk_min;
+            let noise_index = index3(local_p, voxel_map_config.chunk_shape);
+            let height_bias = (noise_config.y_offset - p.y() as f32) / noise_config.y_scale;
+            if noise[noise_index] + height_bias > noise_config.density_threshold {
+                let biome_index = index(local_p, voxel_map_config.chunk_shape);
+                *v = height_to_material(p, biome_noise[biome_index], noise_config);
+                is_empty = false;
+            } else if p.y() < noise_config.sea_level {
+                *v = Voxel::WATER;
+                is_empty = false;
+            }
+        });
+        if !is_empty {
+            chunks.push((y_chunk_min, chunk_noise));
+        }
+    }
+
+    chunks
+}
+
+fn height_to_material(p: Point3i, biome_value: f32, config: &NoiseConfig) -> Voxel {
+    let material = biome_material(
+        &config.biomes,
+        biome_value,
+        unscale_noise(p.y() as f32, config),
+    );
+    // Summer in both hemispheres, as that is the least snow there ever is
+    let summer_temperature = config.temperature_at(p) + config.temperature.seasonal_amplitude;
+    if material != Voxel::EMPTY
+        && material != Voxel::WATER
+        && summer_temperature <= config.temperature.snow_temperature
+    {
+        Voxel::SNOW
+    } else {
+        material
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use building_blocks::mesh::{greedy_quads, GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG};
+
+    // The (outward normal, material) of each greedy quad for `voxels`, meshed with one voxel of
+    // empty padding around them
+    fn mesh_quads(voxels: &[(Extent3i, Voxel)]) -> Vec<(Point3i, Voxel)> {
+        let extent = Extent3i::from_min_and_shape(PointN([-1; 3]), PointN([10; 3]));
+        let mut array = Array3x1::fill(extent, Voxel::EMPTY);
+        for (voxel_extent, voxel) in voxels.iter() {
+            array.fill_extent(voxel_extent, *voxel);
+        }
+        let mut buffer = GreedyQuadsBuffer::new(extent, RIGHT_HANDED_Y_UP_CONFIG.quad_groups());
+        greedy_quads(&array, &extent, &mut buffer);
+        buffer
+            .quad_groups
+            .iter()
+            .flat_map(|group| {
+                let normal = group.face.n * group.face.n_sign;
+                let array = &array;
+                group
+                    .quads
+                    .iter()
+                    .map(move |quad| (normal, array.get(quad.minimum)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn solid_stone_block_has_one_quad_per_side() {
+        let block = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([8; 3]));
+        assert_eq!(mesh_quads(&[(block, Voxel::STONE)]).len(), 6);
+    }
+
+    #[test]
+    fn faces_against_transparent_voxels_are_meshed() {
+        let stone = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([8, 4, 8]));
+        let above = Extent3i::from_min_and_shape(PointN([0, 4, 0]), PointN([8, 4, 8]));
+        let stone_top = (PointN([0, 1, 0]), Voxel::STONE);
+        // The top of the stone can be seen through the water
+        let quads = mesh_quads(&[(stone, Voxel::STONE), (above, Voxel::WATER)]);
+        assert!(quads.contains(&stone_top));
+        // But not through opaque dirt
+        let quads = mesh_quads(&[(stone, Voxel::STONE), (above, Voxel::DIRT)]);
+        assert!(!quads.contains(&stone_top));
+        assert_eq!(quads.len(), 10);
+    }
+
+    fn generate_column(
+        key: Point3i,
+        seed: WorldSeed,
+        noise_config: &NoiseConfig,
+    ) -> Vec<(Point3i, Array3x1<Voxel>)> {
+        let voxel_map_config = VoxelMapConfig::default();
+        let mut chunks = generate_chunk_stack(key, seed, noise_config, &voxel_map_config);
+        decorate_column(key, &mut chunks, seed, &voxel_map_config);
+        chunks
+    }
+
+    #[test]
+    fn same_seed_generates_identical_chunks() {
+        let seed = WorldSeed(42);
+        for mode in [TerrainNoiseMode::Heightmap2D, TerrainNoiseMode::Density3D].iter() {
+            let noise_config = NoiseConfig {
+                mode: *mode,
+                ..Default::default()
+            };
+            for key in [PointN([0, 0, 0]), PointN([3, 0, -2])].iter() {
+                let first = generate_column(*key, seed, &noise_config);
+                assert!(!first.is_empty());
+                assert_eq!(first, generate_column(*key, seed, &noise_config));
+            }
+        }
+    }
+
+    #[test]
+    fn density_columns_are_solid_down_to_the_map_floor() {
+        let noise_config = NoiseConfig {
+            mode: TerrainNoiseMode::Density3D,
+            ..Default::default()
+        };
+        let voxel_map_config = VoxelMapConfig::default();
+        let chunks = generate_column(PointN([0, 0, 0]), WorldSeed(42), &noise_config);
+        let band_min_y = ((noise_config.y_offset - noise_config.y_scale) as i32
+            >> voxel_map_config.chunk_log2)
+            << voxel_map_config.chunk_log2;
+        let floor_y = voxel_map_config.visible_voxel_extent.minimum.y();
+        assert!(floor_y < band_min_y);
+        for y in (floor_y..band_min_y).step_by(voxel_map_config.chunk_shape.y() as usize) {
+            let (_, chunk) = chunks
+                .iter()
+                .find(|(min, _)| min.y() == y)
+                .unwrap_or_else(|| panic!("No chunk at y = {}", y));
+            let mut all_stone = true;
+            chunk.for_each(chunk.extent(), |_: Point3i, v: Voxel| {
+                all_stone &= v == Voxel::STONE
+            });
+            assert!(all_stone, "The chunk at y = {} is not solid", y);
+        }
+    }
+
+    #[test]
+    fn overlapping_edits_in_one_frame_all_apply() {
+        let mut voxel_map_config = VoxelMapConfig::default();
+        voxel_map_config.trees.enabled = false;
+        let generator = crate::chunk_generator::FlatWorldGenerator::default();
+        let seed = WorldSeed::default();
+        let mut voxel_map = VoxelMap::empty(&voxel_map_config);
+        let center = PointN([4, 4, 4]);
+        let mut commands =
+            voxel_map.set_sphere(center, 2, Voxel::STONE, &generator, seed, &voxel_map_config);
+        // The stack was generated by the sphere, so these only edit the chunk
+        commands.extend(voxel_map.set_voxel(
+            center,
+            Voxel::DIRT,
+            &generator,
+            seed,
+            &voxel_map_config,
+        ));
+        commands.extend(voxel_map.set_voxel(
+            PointN([5, 4, 4]),
+            Voxel::EMPTY,
+            &generator,
+            seed,
+            &voxel_map_config,
+        ));
+
+        // None of the edits has been applied by the chunk generator yet
+        assert_eq!(voxel_map.get_voxel(PointN([4, 4, 2])), Voxel::STONE);
+        assert_eq!(voxel_map.get_voxel(center), Voxel::DIRT);
+        assert_eq!(voxel_map.get_voxel(PointN([5, 4, 4])), Voxel::EMPTY);
+        // One for each generated chunk below the edited one and one per edit
+        let generated = [PointN([0, -64, 0]), PointN([0, -32, 0])];
+        let mut expected: Vec<_> = generated.iter().cloned().map(ChunkCommand::Edit).collect();
+        expected.extend(vec![ChunkCommand::Edit(Point3i::ZERO); 3]);
+        assert_eq!(commands, expected);
+        assert!(voxel_map.edited.contains(&Point3i::ZERO));
+        assert!(!voxel_map.edited.contains(&generated[0]));
+    }
+
+    #[test]
+    fn different_seeds_generate_different_chunks() {
+        let noise_config = NoiseConfig::default();
+        let key = PointN([0, 0, 0]);
+        assert_ne!(
+            generate_column(key, WorldSeed(1), &noise_config),
+            generate_column(key, WorldSeed(2), &noise_config)
+        );
     }
 }